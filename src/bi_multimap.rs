@@ -0,0 +1,584 @@
+use crate::maps::{DefaultMap, Lookup, Map};
+use crate::multimap::Mappings;
+use crate::sets::{Container, Set};
+use crate::MultiMap;
+use std::borrow::Borrow;
+
+/// A bidirectional multi-map, which relates left values to right values in both directions.
+/// It is implemented as two `MultiMap`s, one from left to right and its inverse from right to left,
+/// which are kept in sync by every update. The two sides may use different backends (see `MultiMapBuilder`).
+///
+/// # Example
+///
+/// ```
+/// use multi_containers::HashBiMultiMap;
+/// let mut tags = HashBiMultiMap::new();
+/// tags.insert("post-1", "rust");
+/// tags.insert("post-1", "maps");
+/// tags.insert("post-2", "rust");
+/// assert_eq!(tags.get_by_left("post-1").unwrap().len(), 2);
+/// assert_eq!(tags.get_by_right("rust").unwrap().len(), 2);
+/// tags.remove_left_key("post-1");
+/// assert_eq!(tags.get_by_right("rust").unwrap().len(), 1);
+/// assert!(!tags.contains_right_key("maps"));
+/// ```
+#[derive(Default, Debug, Clone)]
+pub struct BiMultiMap<L, R> {
+    left: MultiMap<L>,
+    right: MultiMap<R>,
+}
+
+impl<L, R> BiMultiMap<L, R>
+where
+    L: DefaultMap,
+    R: DefaultMap,
+{
+    /// Creates a new, empty bidirectional multi-map.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use multi_containers::HashBiMultiMap;
+    /// let map: HashBiMultiMap<&str, i32> = HashBiMultiMap::new();
+    /// assert!(map.is_empty());
+    /// ```
+    pub fn new() -> Self {
+        BiMultiMap {
+            left: MultiMap::new(),
+            right: MultiMap::new(),
+        }
+    }
+}
+
+impl<L, R> BiMultiMap<L, R>
+where
+    L: Map,
+    L::Val: Set<Elem = R::Key>,
+    R: Map,
+    R::Val: Set<Elem = L::Key>,
+{
+    // Wraps two multi-maps which must be empty, such as ones configured by a builder.
+    pub(crate) fn from_empty_multimaps(left: MultiMap<L>, right: MultiMap<R>) -> Self {
+        debug_assert!(left.is_empty() && right.is_empty());
+        BiMultiMap { left, right }
+    }
+
+    /// Inserts a (left, right) mapping into both sides of the multi-map.
+    /// Returns `true` if it was not already present.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use multi_containers::HashBiMultiMap;
+    /// let mut map = HashBiMultiMap::new();
+    /// assert_eq!(map.insert("a", 1), true);
+    /// assert_eq!(map.insert("a", 1), false);
+    /// assert_eq!(map.insert("b", 1), true);
+    /// assert_eq!(map.num_mappings(), 2);
+    /// ```
+    pub fn insert(&mut self, left: L::Key, right: R::Key) -> bool
+    where
+        L::Key: Clone,
+        L::Val: Default,
+        R::Key: Clone,
+        R::Val: Default,
+    {
+        if self.left.insert(left.clone(), right.clone()) {
+            self.right.insert(right, left);
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Removes a (left, right) mapping from both sides of the multi-map.
+    /// Returns `true` if it was present.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use multi_containers::HashBiMultiMap;
+    /// let mut map = HashBiMultiMap::new();
+    /// map.insert("a", 1);
+    /// assert_eq!(map.remove("a", &1), true);
+    /// assert_eq!(map.remove("a", &1), false);
+    /// assert!(!map.contains_right_key(&1));
+    /// ```
+    pub fn remove<P, Q>(&mut self, left: &P, right: &Q) -> bool
+    where
+        L: Lookup<P>,
+        L::Key: Borrow<P>,
+        L::Val: Container<Q>,
+        R: Lookup<Q>,
+        R::Key: Borrow<Q>,
+        R::Val: Container<P>,
+        P: ?Sized,
+        Q: ?Sized,
+    {
+        if self.left.remove(left, right) {
+            self.right.remove(right, left);
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Removes a left key and all of its mappings from both sides of the multi-map.
+    /// Returns the right values it was mapped to, if it was present.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use multi_containers::HashBiMultiMap;
+    /// let mut map = HashBiMultiMap::new();
+    /// map.insert("a", 1);
+    /// map.insert("a", 2);
+    /// map.insert("b", 2);
+    /// assert_eq!(map.remove_left_key("a").map(|set| set.len()), Some(2));
+    /// assert!(!map.contains_right_key(&1));
+    /// assert_eq!(map.get_by_right(&2).unwrap().len(), 1);
+    /// ```
+    pub fn remove_left_key<P>(&mut self, left: &P) -> Option<L::Val>
+    where
+        L: Lookup<P>,
+        L::Key: Borrow<P>,
+        R: Lookup<<R as Map>::Key>,
+        R::Val: Container<P>,
+        P: ?Sized,
+    {
+        let set = self.left.remove_key(left)?;
+        for right in set.iter() {
+            self.right.remove(right, left);
+        }
+        Some(set)
+    }
+
+    /// Removes a right key and all of its mappings from both sides of the multi-map.
+    /// Returns the left values it was mapped to, if it was present.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use multi_containers::HashBiMultiMap;
+    /// let mut map = HashBiMultiMap::new();
+    /// map.insert("a", 1);
+    /// map.insert("b", 1);
+    /// map.insert("b", 2);
+    /// assert_eq!(map.remove_right_key(&1).map(|set| set.len()), Some(2));
+    /// assert!(!map.contains_left_key("a"));
+    /// assert_eq!(map.get_by_left("b").unwrap().len(), 1);
+    /// ```
+    pub fn remove_right_key<Q>(&mut self, right: &Q) -> Option<R::Val>
+    where
+        R: Lookup<Q>,
+        R::Key: Borrow<Q>,
+        L: Lookup<<L as Map>::Key>,
+        L::Val: Container<Q>,
+        Q: ?Sized,
+    {
+        let set = self.right.remove_key(right)?;
+        for left in set.iter() {
+            self.left.remove(left, right);
+        }
+        Some(set)
+    }
+
+    /// Returns the right values that a left key is mapped to, if it is present.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use multi_containers::HashBiMultiMap;
+    /// let mut map = HashBiMultiMap::new();
+    /// map.insert("a", 1);
+    /// map.insert("a", 2);
+    /// assert_eq!(map.get_by_left("a").unwrap().len(), 2);
+    /// assert_eq!(map.get_by_left("b"), None);
+    /// ```
+    pub fn get_by_left<P>(&self, left: &P) -> Option<&L::Val>
+    where
+        L: Lookup<P>,
+        L::Key: Borrow<P>,
+        P: ?Sized,
+    {
+        self.left.get(left)
+    }
+
+    /// Returns the left values that a right key is mapped to, if it is present.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use multi_containers::HashBiMultiMap;
+    /// let mut map = HashBiMultiMap::new();
+    /// map.insert("a", 1);
+    /// map.insert("b", 1);
+    /// assert_eq!(map.get_by_right(&1).unwrap().len(), 2);
+    /// assert_eq!(map.get_by_right(&2), None);
+    /// ```
+    pub fn get_by_right<Q>(&self, right: &Q) -> Option<&R::Val>
+    where
+        R: Lookup<Q>,
+        R::Key: Borrow<Q>,
+        Q: ?Sized,
+    {
+        self.right.get(right)
+    }
+
+    /// Returns `true` if the multi-map contains the given (left, right) mapping.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use multi_containers::HashBiMultiMap;
+    /// let mut map = HashBiMultiMap::new();
+    /// map.insert("a", 1);
+    /// assert_eq!(map.contains("a", &1), true);
+    /// assert_eq!(map.contains("a", &2), false);
+    /// ```
+    pub fn contains<P, Q>(&self, left: &P, right: &Q) -> bool
+    where
+        L: Lookup<P>,
+        L::Key: Borrow<P>,
+        L::Val: Container<Q>,
+        R::Key: Borrow<Q>,
+        P: ?Sized,
+        Q: ?Sized,
+    {
+        self.left
+            .get(left)
+            .is_some_and(|set| Container::contains(set, right))
+    }
+
+    /// Returns `true` if the multi-map contains the given left key.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use multi_containers::HashBiMultiMap;
+    /// let mut map = HashBiMultiMap::new();
+    /// map.insert("a", 1);
+    /// assert_eq!(map.contains_left_key("a"), true);
+    /// assert_eq!(map.contains_left_key("b"), false);
+    /// ```
+    pub fn contains_left_key<P>(&self, left: &P) -> bool
+    where
+        L: Lookup<P>,
+        L::Key: Borrow<P>,
+        P: ?Sized,
+    {
+        self.left.contains_key(left)
+    }
+
+    /// Returns `true` if the multi-map contains the given right key.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use multi_containers::HashBiMultiMap;
+    /// let mut map = HashBiMultiMap::new();
+    /// map.insert("a", 1);
+    /// assert_eq!(map.contains_right_key(&1), true);
+    /// assert_eq!(map.contains_right_key(&2), false);
+    /// ```
+    pub fn contains_right_key<Q>(&self, right: &Q) -> bool
+    where
+        R: Lookup<Q>,
+        R::Key: Borrow<Q>,
+        Q: ?Sized,
+    {
+        self.right.contains_key(right)
+    }
+
+    /// Returns the multi-map from left keys to right values.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use multi_containers::BTreeBiMultiMap;
+    /// let mut map = BTreeBiMultiMap::new();
+    /// map.insert("b", 1);
+    /// map.insert("a", 2);
+    /// assert!(map.left().keys().eq(&["a", "b"]));
+    /// ```
+    pub fn left(&self) -> &MultiMap<L> {
+        &self.left
+    }
+
+    /// Returns the multi-map from right keys to left values.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use multi_containers::BTreeBiMultiMap;
+    /// let mut map = BTreeBiMultiMap::new();
+    /// map.insert("b", 1);
+    /// map.insert("a", 2);
+    /// assert!(map.right().keys().eq(&[1, 2]));
+    /// ```
+    pub fn right(&self) -> &MultiMap<R> {
+        &self.right
+    }
+
+    /// Consumes the multi-map, returning its left-to-right and right-to-left sides.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use multi_containers::HashBiMultiMap;
+    /// let mut map = HashBiMultiMap::new();
+    /// map.insert("a", 1);
+    /// let (left, right) = map.into_inner();
+    /// assert!(left.contains_key("a"));
+    /// assert!(right.contains_key(&1));
+    /// ```
+    pub fn into_inner(self) -> (MultiMap<L>, MultiMap<R>) {
+        (self.left, self.right)
+    }
+
+    /// Returns an iterator over the (left, right) mappings, in the order of the left side.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use multi_containers::BTreeBiMultiMap;
+    /// let mut map = BTreeBiMultiMap::new();
+    /// map.insert("b", 1);
+    /// map.insert("a", 2);
+    /// assert_eq!(map.mappings().collect::<Vec<_>>(), vec![(&"a", &2), (&"b", &1)]);
+    /// ```
+    pub fn mappings(&self) -> Mappings<'_, L> {
+        self.left.mappings()
+    }
+
+    /// Returns `true` if the multi-map is empty.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use multi_containers::HashBiMultiMap;
+    /// let mut map = HashBiMultiMap::new();
+    /// assert!(map.is_empty());
+    /// map.insert("a", 1);
+    /// assert!(!map.is_empty());
+    /// ```
+    pub fn is_empty(&self) -> bool {
+        self.left.is_empty()
+    }
+
+    /// Returns the number of unique left keys.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use multi_containers::HashBiMultiMap;
+    /// let mut map = HashBiMultiMap::new();
+    /// map.insert("a", 1);
+    /// map.insert("a", 2);
+    /// assert_eq!(map.num_left_keys(), 1);
+    /// ```
+    pub fn num_left_keys(&self) -> usize {
+        self.left.num_keys()
+    }
+
+    /// Returns the number of unique right keys.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use multi_containers::HashBiMultiMap;
+    /// let mut map = HashBiMultiMap::new();
+    /// map.insert("a", 1);
+    /// map.insert("a", 2);
+    /// assert_eq!(map.num_right_keys(), 2);
+    /// ```
+    pub fn num_right_keys(&self) -> usize {
+        self.right.num_keys()
+    }
+
+    /// Returns the number of (left, right) mappings.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use multi_containers::HashBiMultiMap;
+    /// let mut map = HashBiMultiMap::new();
+    /// map.insert("a", 1);
+    /// map.insert("a", 2);
+    /// map.insert("b", 1);
+    /// assert_eq!(map.num_mappings(), 3);
+    /// ```
+    pub fn num_mappings(&self) -> usize {
+        self.left.num_mappings()
+    }
+
+    /// Panics if the two sides of the multi-map are out of sync:
+    /// both must be consistent, and every mapping on one side must be present on the other.
+    #[cfg(test)]
+    pub(crate) fn check_invariants(&self)
+    where
+        L: Lookup<<L as Map>::Key>,
+        L::Val: Container<R::Key>,
+        R: Lookup<<R as Map>::Key>,
+        R::Val: Container<L::Key>,
+    {
+        self.left.check_invariants();
+        self.right.check_invariants();
+        assert_eq!(
+            self.left.num_mappings(),
+            self.right.num_mappings(),
+            "bi-multi-map sides have different numbers of mappings"
+        );
+        for (left, right) in self.left.mappings() {
+            assert!(
+                self.right
+                    .get(right)
+                    .is_some_and(|set| Container::contains(set, left)),
+                "bi-multi-map mapping is missing from the right side"
+            );
+        }
+    }
+}
+
+// The right side is determined by the left side, so only the left sides are compared.
+impl<L, R> PartialEq for BiMultiMap<L, R>
+where
+    L: PartialEq,
+{
+    fn eq(&self, other: &Self) -> bool {
+        self.left == other.left
+    }
+}
+
+impl<L, R> Eq for BiMultiMap<L, R> where L: Eq {}
+
+impl<L, R> Extend<(L::Key, R::Key)> for BiMultiMap<L, R>
+where
+    L: Map,
+    L::Key: Clone,
+    L::Val: Set<Elem = R::Key> + Default,
+    R: Map,
+    R::Key: Clone,
+    R::Val: Set<Elem = L::Key> + Default,
+{
+    fn extend<I: IntoIterator<Item = (L::Key, R::Key)>>(&mut self, iter: I) {
+        for (left, right) in iter {
+            self.insert(left, right);
+        }
+    }
+}
+
+impl<L, R> FromIterator<(L::Key, R::Key)> for BiMultiMap<L, R>
+where
+    L: DefaultMap,
+    L::Key: Clone,
+    L::Val: Set<Elem = R::Key> + Default,
+    R: DefaultMap,
+    R::Key: Clone,
+    R::Val: Set<Elem = L::Key> + Default,
+{
+    fn from_iter<I: IntoIterator<Item = (L::Key, R::Key)>>(iter: I) -> Self {
+        let mut map = BiMultiMap::default();
+        map.extend(iter);
+        map
+    }
+}
+
+impl<L, R, const N: usize> From<[(L::Key, R::Key); N]> for BiMultiMap<L, R>
+where
+    L: DefaultMap,
+    L::Key: Clone,
+    L::Val: Set<Elem = R::Key> + Default,
+    R: DefaultMap,
+    R::Key: Clone,
+    R::Val: Set<Elem = L::Key> + Default,
+{
+    fn from(arr: [(L::Key, R::Key); N]) -> Self {
+        arr.into_iter().collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    macro_rules! base_test_suite {
+        ($mod_name:ident, $map_maker:expr) => {
+            mod $mod_name {
+                #[test]
+                fn insert_updates_both_sides() {
+                    let mut map = $map_maker;
+                    assert!(map.insert("a", 1));
+                    assert!(map.insert("a", 2));
+                    assert!(map.insert("b", 1));
+                    assert!(!map.insert("a", 1));
+                    map.check_invariants();
+                    assert_eq!(map.num_mappings(), 3);
+                    assert_eq!(map.num_left_keys(), 2);
+                    assert_eq!(map.num_right_keys(), 2);
+                    assert_eq!(map.get_by_left("a").unwrap().len(), 2);
+                    assert_eq!(map.get_by_right(&1).unwrap().len(), 2);
+                    assert!(map.contains("b", &1));
+                    assert!(!map.contains("b", &2));
+                }
+
+                #[test]
+                fn remove_updates_both_sides() {
+                    let mut map = $map_maker;
+                    map.extend([("a", 1), ("a", 2), ("b", 1)]);
+                    assert!(map.remove("a", &1));
+                    assert!(!map.remove("a", &1));
+                    assert!(!map.remove("c", &1));
+                    map.check_invariants();
+                    assert_eq!(map.get_by_right(&1).unwrap().len(), 1);
+                    assert!(map.remove("a", &2));
+                    map.check_invariants();
+                    assert!(!map.contains_left_key("a"));
+                    assert!(!map.contains_right_key(&2));
+                    assert_eq!(map.num_mappings(), 1);
+                }
+
+                #[test]
+                fn remove_keys_updates_both_sides() {
+                    let mut map = $map_maker;
+                    map.extend([("a", 1), ("a", 2), ("b", 1), ("b", 3), ("c", 3)]);
+                    assert_eq!(map.remove_left_key("b").map(|set| set.len()), Some(2));
+                    assert_eq!(map.remove_left_key("b"), None);
+                    map.check_invariants();
+                    assert_eq!(map.get_by_right(&1).unwrap().len(), 1);
+                    assert_eq!(map.get_by_right(&3).unwrap().len(), 1);
+                    assert_eq!(map.remove_right_key(&3).map(|set| set.len()), Some(1));
+                    assert_eq!(map.remove_right_key(&3), None);
+                    map.check_invariants();
+                    assert!(!map.contains_left_key("c"));
+                    assert_eq!(map.num_mappings(), 2);
+                    assert_eq!(map.remove_right_key(&1).map(|set| set.len()), Some(1));
+                    assert_eq!(map.remove_right_key(&2).map(|set| set.len()), Some(1));
+                    map.check_invariants();
+                    assert!(map.is_empty());
+                    assert!(map.right().is_empty());
+                }
+
+                #[test]
+                fn equality() {
+                    let mut a = $map_maker;
+                    let mut b = $map_maker;
+                    a.extend([("a", 1), ("b", 2)]);
+                    b.extend([("b", 2), ("a", 1)]);
+                    assert_eq!(a, b);
+                    b.insert("a", 2);
+                    assert_ne!(a, b);
+                }
+            }
+        };
+    }
+
+    base_test_suite!(hash_sides, crate::HashBiMultiMap::new());
+
+    base_test_suite!(sorted_sides, crate::BTreeBiMultiMap::new());
+
+    base_test_suite!(
+        mixed_sides,
+        crate::MultiMapBuilder::hash_keys()
+            .sorted_values()
+            .build_bidirectional(crate::MultiMapBuilder::sorted_keys().hash_values())
+    );
+}
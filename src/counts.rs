@@ -0,0 +1,124 @@
+use std::error::Error;
+use std::fmt::{self, Debug, Display};
+use std::num::{NonZeroU128, NonZeroU16, NonZeroU32, NonZeroU64, NonZeroU8, NonZeroUsize};
+
+/// A type that can count the occurrences of a value in a multi-set.
+/// Multi-sets take and return counts as `usize`, and convert them to and from this type when they are stored.
+/// A smaller type saves memory, at the cost of overflowing sooner.
+pub trait Count: Copy + Ord + Debug {
+    /// Converts a number of occurrences to a count. Returns `None` if it does not fit in this type.
+    /// Multi-sets never store a count of zero, so this is only called with non-zero numbers.
+    fn from_usize(count: usize) -> Option<Self>;
+
+    /// Converts a number of occurrences to a count, clamping it to the range of this type.
+    fn saturating_from_usize(count: usize) -> Self;
+
+    /// Converts a count to a number of occurrences.
+    /// Multi-sets keep the sum of their counts in a `usize`, so this never fails for the counts they store.
+    fn to_usize(self) -> usize;
+}
+
+macro_rules! impl_count {
+    ($($count:ty),*) => {
+        $(
+            impl Count for $count {
+                fn from_usize(count: usize) -> Option<Self> {
+                    Self::try_from(count).ok()
+                }
+
+                fn saturating_from_usize(count: usize) -> Self {
+                    Self::try_from(count).unwrap_or(Self::MAX)
+                }
+
+                fn to_usize(self) -> usize {
+                    usize::try_from(self).expect("count does not fit in a usize")
+                }
+            }
+        )*
+    };
+}
+
+impl_count!(u8, u16, u32, u64, u128, usize);
+
+macro_rules! impl_non_zero_count {
+    ($($count:ty => $int:ty),*) => {
+        $(
+            impl Count for $count {
+                fn from_usize(count: usize) -> Option<Self> {
+                    Self::new(<$int>::from_usize(count)?)
+                }
+
+                fn saturating_from_usize(count: usize) -> Self {
+                    Self::new(<$int>::saturating_from_usize(count)).unwrap_or(Self::MIN)
+                }
+
+                fn to_usize(self) -> usize {
+                    self.get().to_usize()
+                }
+            }
+        )*
+    };
+}
+
+impl_non_zero_count!(
+    NonZeroU8 => u8,
+    NonZeroU16 => u16,
+    NonZeroU32 => u32,
+    NonZeroU64 => u64,
+    NonZeroU128 => u128,
+    NonZeroUsize => usize
+);
+
+/// The error returned when a count does not fit in the count type of a multi-set,
+/// or the length of a multi-set does not fit in a `usize`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CountOverflow;
+
+impl Display for CountOverflow {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "count overflow")
+    }
+}
+
+impl Error for CountOverflow {}
+
+/// The error returned when a multi-set does not contain a value as many times as requested.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct InsufficientCount {
+    /// The number of occurrences that were requested.
+    pub requested: usize,
+    /// The number of occurrences that were available.
+    pub available: usize,
+}
+
+impl Display for InsufficientCount {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "insufficient count: requested {} but only {} available",
+            self.requested, self.available
+        )
+    }
+}
+
+impl Error for InsufficientCount {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn conversions() {
+        assert_eq!(u8::from_usize(255), Some(255));
+        assert_eq!(u8::from_usize(256), None);
+        assert_eq!(u8::saturating_from_usize(1000), 255);
+        assert_eq!(u128::from_usize(usize::MAX), Some(usize::MAX as u128));
+        assert_eq!(200_u8.to_usize(), 200);
+        assert_eq!(NonZeroU8::from_usize(0), None);
+        assert_eq!(NonZeroU8::from_usize(3), NonZeroU8::new(3));
+        assert_eq!(NonZeroU8::from_usize(256), None);
+        assert_eq!(NonZeroU8::saturating_from_usize(0), NonZeroU8::MIN);
+        assert_eq!(NonZeroU8::saturating_from_usize(256), NonZeroU8::MAX);
+        assert_eq!(NonZeroU64::new(7).unwrap().to_usize(), 7);
+    }
+}
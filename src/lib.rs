@@ -10,6 +10,8 @@
 //! - Python's `collections.defaultdict(set)` and `collections.Counter`.
 //! - C++'s `std::(unordered_)mutlimap` and `std::(unordered_)multiset`.
 //!
+//! The minimum supported Rust version is 1.92, for `BTreeMap::extract_if` and `btree_map::VacantEntry::insert_entry`.
+//!
 //! ## Usage
 //! The primary containers are `MultiMap` and `MultiSet`. See `examples.rs` for more examples.
 //!
@@ -17,8 +19,11 @@
 //! `MultiMap` is a wrapper around `Map<K, Set<V>>`.
 //! You can either use the provided `HashMultiMap` or `BTreeMultiMap`, or provide your own types with `MultiMapBuilder`.
 //! The API is similar to what you would expect from `HashMap<K, HashSet<V>>`, with some additional methods related to the multiple values.
-//! For bookkeeping reasons, the inner sets are not exposed mutably.
+//! For bookkeeping reasons, the inner sets are only exposed mutably through a guard (see `MultiMap::get_mut`).
 //! `MultiMap` also provides ways of iterating over `(&K, &Set<V>)`, or over `(&K, &V)`.
+//! `MultiMap::invert` and `MultiMap::into_inverted` build the inverse multi-map from values to keys, with any backend.
+//! When most keys have only a few values, `small_set::SmallSet` stores them inline instead of allocating a set per key.
+//! For data that is built once and then only queried, `SortedVecMultiMap` stores keys and values in sorted `Vec`s.
 //!
 //! ```rust
 //! use multi_containers::HashMultiMap;
@@ -30,9 +35,40 @@
 //! assert_eq!(map.get("b").unwrap().len(), 1);
 //! ```
 //!
+//! ### ListMultiMap
+//! `ListMultiMap` is a wrapper around `Map<K, VecDeque<V>>`.
+//! Unlike `MultiMap`, it keeps the values of each key in insertion order, including duplicates.
+//!
+//! ```rust
+//! use multi_containers::HashListMultiMap;
+//! let mut map = HashListMultiMap::new();
+//! map.push("a", 1);
+//! map.push("a", 1);
+//! map.push("a", 2);
+//! assert_eq!(map.get("a").unwrap()[2], 2);
+//! assert_eq!(map.pop_front("a"), Some(1));
+//! assert_eq!(map.num_mappings(), 2);
+//! ```
+//!
+//! ### BiMultiMap
+//! `BiMultiMap` keeps a `MultiMap` and its inverse in sync, so that it can be queried from either side.
+//! Each side may use its own backend (see `MultiMapBuilderWithKeysAndVals::build_bidirectional`).
+//!
+//! ```rust
+//! use multi_containers::HashBiMultiMap;
+//! let mut tags = HashBiMultiMap::new();
+//! tags.insert("post-1", "rust");
+//! tags.insert("post-2", "rust");
+//! assert_eq!(tags.get_by_right("rust").unwrap().len(), 2);
+//! tags.remove_left_key("post-1");
+//! assert_eq!(tags.get_by_right("rust").unwrap().len(), 1);
+//! ```
+//!
 //! ### MultiSet
 //! `MultiSet` is a wrapper around `Map<V, usize>`. It offers the semantics of a set, but allows for duplicate values.
 //! It offers iterators over unique `(&V, usize)`, and non-unique `&V`.
+//! Counts can be stored as any `Count` type instead of `usize`, such as `u8` to save memory (see `MultiSetBuilder`).
+//! The exception is `OrderStatisticMultiSet`, whose map always stores `usize` counts.
 //!
 //! ```rust
 //! use multi_containers::HashMultiSet;
@@ -43,6 +79,37 @@
 //! assert_eq!(set.count(&1), 2);
 //! assert_eq!(set.count(&2), 3);
 //! ```
+//!
+//! ### SignedMultiSet
+//! `SignedMultiSet` is a wrapper around `Map<V, isize>`, whose counts (called weights) may be negative.
+//! Weights that cancel out remove their value, which makes it suitable for representing changes to a `MultiSet`.
+//!
+//! ```rust
+//! use multi_containers::HashSignedMultiSet;
+//! let mut set = HashSignedMultiSet::new();
+//! set.add_delta("a", 3);
+//! set.add_delta("b", -1);
+//! set.add_delta("a", -3);
+//! assert_eq!(set.contains("a"), false);
+//! assert_eq!(set.total_weight(), -1);
+//! ```
+//!
+//! ### Macros
+//! The `multimap!` and `multiset!` macros create small containers from literals.
+//! The backend is inferred from the target type, or can be given explicitly.
+//!
+//! ```rust
+//! use multi_containers::{multimap, multiset, BTreeMultiSet, HashMultiMap};
+//! let map: HashMultiMap<_, _> = multimap! { "a" => [1, 2], "b" => [3] };
+//! assert_eq!(map.num_mappings(), 3);
+//! let set = multiset!(<BTreeMultiSet<_>>; 'x' => 3, 'y');
+//! assert_eq!(set.len(), 4);
+//! ```
+//!
+//! ## Optional features
+//! - `rand`: weighted random sampling from a `MultiSet`, with `MultiSet::sample`, `MultiSet::sample_many` and `MultiSet::sample_without_replacement`.
+//! - `serde`: `Serialize` and `Deserialize` for `MultiMap` and `MultiSet` (see the `serialization` module).
+//! - `indexmap`: `IndexMultiMap` and `IndexMultiSet`, backed by `IndexMap` and `IndexSet`, which iterate in insertion order.
 
 /// Defines the `MultiMap` type.
 pub mod multimap;
@@ -56,20 +123,59 @@ pub mod multiset;
 /// Provides a convenient way to construct multi-sets.
 pub mod multiset_builder;
 
+/// Defines the `SignedMultiSet` type.
+pub mod signed_multiset;
+
+/// Defines the `ListMultiMap` type.
+pub mod list_multimap;
+
+/// Defines the `BiMultiMap` type.
+pub mod bi_multimap;
+
 /// Traits for working with maps.
 pub mod maps;
 
 /// Traits for working with sets.
 pub mod sets;
 
+/// Defines a set that stores a few elements inline, for the value sets of multi-maps.
+pub mod small_set;
+
+/// Defines a sorted map backed by a `Vec`, for data that is built once and queried many times.
+pub mod sorted_vec_map;
+
+/// Defines a sorted set backed by a `Vec`, for data that is built once and queried many times.
+pub mod sorted_vec_set;
+
+/// Defines the `Count` trait for the counts of multi-sets, and the errors of fallible count updates.
+pub mod counts;
+
+/// Defines a sorted map that supports order statistics on its counts.
+pub mod order_statistic_map;
+
+/// Defines the `multimap!` and `multiset!` macros.
+mod macros;
+
+/// Implements serde support for multi-maps and multi-sets.
+#[cfg(feature = "serde")]
+pub mod serialization;
+
 mod examples;
 mod test_utils;
 
+pub use crate::bi_multimap::BiMultiMap;
+pub use crate::list_multimap::ListMultiMap;
 pub use crate::multimap::MultiMap;
 pub use crate::multimap_builder::MultiMapBuilder;
 pub use crate::multiset::MultiSet;
 pub use crate::multiset_builder::MultiSetBuilder;
-use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use crate::order_statistic_map::OrderStatisticMap;
+pub use crate::signed_multiset::SignedMultiSet;
+use crate::sorted_vec_map::SortedVecMap;
+use crate::sorted_vec_set::SortedVecSet;
+#[cfg(feature = "indexmap")]
+use indexmap::{IndexMap, IndexSet};
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet, VecDeque};
 use std::hash::RandomState;
 
 /// A multi-map that uses `HashMap` for the keys and `HashSet` for the values.
@@ -83,3 +189,42 @@ pub type BTreeMultiMap<K, V> = MultiMap<BTreeMap<K, BTreeSet<V>>>;
 
 /// A multi-set that uses `BTreeMap` for the keys.
 pub type BTreeMultiSet<K> = MultiSet<BTreeMap<K, usize>>;
+
+/// A list multi-map that uses `HashMap` for the keys and `VecDeque` for the values.
+pub type HashListMultiMap<K, V, S = RandomState> = ListMultiMap<HashMap<K, VecDeque<V>, S>>;
+
+/// A list multi-map that uses `BTreeMap` for the keys and `VecDeque` for the values.
+pub type BTreeListMultiMap<K, V> = ListMultiMap<BTreeMap<K, VecDeque<V>>>;
+
+/// A bidirectional multi-map that uses `HashMultiMap`s for both sides.
+pub type HashBiMultiMap<A, B, S = RandomState> =
+    BiMultiMap<HashMap<A, HashSet<B, S>, S>, HashMap<B, HashSet<A, S>, S>>;
+
+/// A bidirectional multi-map that uses `BTreeMultiMap`s for both sides.
+pub type BTreeBiMultiMap<A, B> = BiMultiMap<BTreeMap<A, BTreeSet<B>>, BTreeMap<B, BTreeSet<A>>>;
+
+/// A signed multi-set that uses `HashMap` for the keys.
+pub type HashSignedMultiSet<K, S = RandomState> = SignedMultiSet<HashMap<K, isize, S>>;
+
+/// A signed multi-set that uses `BTreeMap` for the keys.
+pub type BTreeSignedMultiSet<K> = SignedMultiSet<BTreeMap<K, isize>>;
+
+/// A multi-set that uses `OrderStatisticMap` for the keys.
+/// It is sorted like `BTreeMultiSet`, and answers order statistics queries (see `MultiSet::nth`) in `O(log n)`.
+pub type OrderStatisticMultiSet<K> = MultiSet<OrderStatisticMap<K>>;
+
+/// A multi-map that uses `SortedVecMap` for the keys and `SortedVecSet` for the values.
+/// It is sorted like `BTreeMultiMap`, but is faster to query and slower to update (see `BTreeMultiMap::freeze`).
+pub type SortedVecMultiMap<K, V> = MultiMap<SortedVecMap<K, SortedVecSet<V>>>;
+
+/// A multi-set that uses `SortedVecMap` for the keys.
+/// It is sorted like `BTreeMultiSet`, but is faster to query and slower to update (see `BTreeMultiSet::freeze`).
+pub type SortedVecMultiSet<K> = MultiSet<SortedVecMap<K, usize>>;
+
+/// A multi-map that uses `IndexMap` for the keys and `IndexSet` for the values, which keep them in insertion order.
+#[cfg(feature = "indexmap")]
+pub type IndexMultiMap<K, V, S = RandomState> = MultiMap<IndexMap<K, IndexSet<V, S>, S>>;
+
+/// A multi-set that uses `IndexMap` for the keys, which keeps them in insertion order.
+#[cfg(feature = "indexmap")]
+pub type IndexMultiSet<K, S = RandomState> = MultiSet<IndexMap<K, usize, S>>;
@@ -0,0 +1,548 @@
+use crate::maps::{DefaultMap, Lookup, Map};
+use std::borrow::Borrow;
+use std::collections::{HashMap, VecDeque};
+
+/// A multi-map whose values for each key are kept in a list, in insertion order.
+/// Unlike `MultiMap`, a key can hold the same value more than once.
+/// The multi-map is implemented as a map from keys to non-empty `VecDeque`s of values.
+/// The lists are only exposed immutably, so that the number of mappings stays up to date.
+#[derive(Default, Debug, PartialEq, Eq, Clone)]
+pub struct ListMultiMap<M> {
+    map: M,
+    // The total number of values in all lists.
+    length: usize,
+}
+
+impl<M> ListMultiMap<M>
+where
+    M: DefaultMap,
+{
+    /// Creates a new empty list multi-map.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use multi_containers::HashListMultiMap;
+    /// let mut map = HashListMultiMap::new();
+    /// assert!(map.is_empty());
+    /// map.push("a", 1);
+    /// assert!(!map.is_empty());
+    /// ```
+    pub fn new() -> Self {
+        ListMultiMap {
+            map: M::default(),
+            length: 0,
+        }
+    }
+}
+
+impl<K, V> ListMultiMap<HashMap<K, VecDeque<V>>> {
+    /// Creates a new empty list multi-map with space for at least `capacity` keys.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use multi_containers::HashListMultiMap;
+    /// let map: HashListMultiMap<&str, i32> = HashListMultiMap::with_capacity(10);
+    /// assert!(map.is_empty());
+    /// ```
+    pub fn with_capacity(capacity: usize) -> Self {
+        ListMultiMap {
+            map: HashMap::with_capacity(capacity),
+            length: 0,
+        }
+    }
+}
+
+impl<M, V> ListMultiMap<M>
+where
+    M: Map<Val = VecDeque<V>>,
+{
+    /// Appends a value to the end of the list of values for the given key.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use multi_containers::HashListMultiMap;
+    /// let mut map = HashListMultiMap::new();
+    /// map.push("a", 1);
+    /// map.push("a", 1);
+    /// map.push("a", 2);
+    /// assert_eq!(map.get("a").unwrap(), &[1, 1, 2]);
+    /// assert_eq!(map.num_mappings(), 3);
+    /// ```
+    pub fn push(&mut self, key: M::Key, value: V) {
+        self.map.get_or_insert(key, VecDeque::new).push_back(value);
+        self.length += 1;
+    }
+
+    /// Removes and returns the first value for the given key, if any.
+    /// The key is removed once its last value is removed.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use multi_containers::HashListMultiMap;
+    /// let mut map = HashListMultiMap::new();
+    /// map.push("a", 1);
+    /// map.push("a", 2);
+    /// assert_eq!(map.pop_front("a"), Some(1));
+    /// assert_eq!(map.pop_front("a"), Some(2));
+    /// assert_eq!(map.pop_front("a"), None);
+    /// assert_eq!(map.contains_key("a"), false);
+    /// ```
+    pub fn pop_front<Q>(&mut self, key: &Q) -> Option<V>
+    where
+        M: Lookup<Q>,
+        M::Key: Borrow<Q>,
+        Q: ?Sized,
+    {
+        self.remove_with(key, VecDeque::pop_front)
+    }
+
+    /// Removes and returns the last value for the given key, if any.
+    /// The key is removed once its last value is removed.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use multi_containers::HashListMultiMap;
+    /// let mut map = HashListMultiMap::new();
+    /// map.push("a", 1);
+    /// map.push("a", 2);
+    /// assert_eq!(map.pop_back("a"), Some(2));
+    /// assert_eq!(map.num_mappings(), 1);
+    /// ```
+    pub fn pop_back<Q>(&mut self, key: &Q) -> Option<V>
+    where
+        M: Lookup<Q>,
+        M::Key: Borrow<Q>,
+        Q: ?Sized,
+    {
+        self.remove_with(key, VecDeque::pop_back)
+    }
+
+    /// Removes the first occurrence of a value from the list of values for the given key.
+    /// Returns the removed value, if it was present.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use multi_containers::HashListMultiMap;
+    /// let mut map = HashListMultiMap::new();
+    /// map.push("a", 1);
+    /// map.push("a", 2);
+    /// map.push("a", 1);
+    /// assert_eq!(map.remove_first("a", &1), Some(1));
+    /// assert_eq!(map.get("a").unwrap(), &[2, 1]);
+    /// assert_eq!(map.remove_first("a", &3), None);
+    /// ```
+    pub fn remove_first<Q, R>(&mut self, key: &Q, value: &R) -> Option<V>
+    where
+        M: Lookup<Q>,
+        M::Key: Borrow<Q>,
+        Q: ?Sized,
+        V: Borrow<R>,
+        R: PartialEq + ?Sized,
+    {
+        self.remove_with(key, |list| {
+            let index = list.iter().position(|v| v.borrow() == value)?;
+            list.remove(index)
+        })
+    }
+
+    // Removes a value from the list of the given key with `remove`, and keeps the bookkeeping up to date.
+    fn remove_with<Q, F>(&mut self, key: &Q, remove: F) -> Option<V>
+    where
+        M: Lookup<Q>,
+        M::Key: Borrow<Q>,
+        Q: ?Sized,
+        F: FnOnce(&mut VecDeque<V>) -> Option<V>,
+    {
+        let list = self.map.get_mut(key)?;
+        let value = remove(list)?;
+        self.length -= 1;
+        if list.is_empty() {
+            self.map.remove(key);
+        }
+        Some(value)
+    }
+
+    /// Removes all values for the given key, returning them in insertion order.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use multi_containers::HashListMultiMap;
+    /// let mut map = HashListMultiMap::new();
+    /// map.push("a", 1);
+    /// map.push("a", 2);
+    /// assert_eq!(map.remove_key("a").unwrap(), [1, 2]);
+    /// assert_eq!(map.remove_key("a"), None);
+    /// assert_eq!(map.num_mappings(), 0);
+    /// ```
+    pub fn remove_key<Q>(&mut self, key: &Q) -> Option<VecDeque<V>>
+    where
+        M: Lookup<Q>,
+        M::Key: Borrow<Q>,
+        Q: ?Sized,
+    {
+        let list = self.map.remove(key)?;
+        self.length -= list.len();
+        Some(list)
+    }
+
+    /// Keeps only the (key, value) mappings for which the predicate returns `true`.
+    /// The remaining values keep their order, and keys that are left without values are removed.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use multi_containers::HashListMultiMap;
+    /// let mut map = HashListMultiMap::new();
+    /// map.extend([("a", 1), ("a", 2), ("a", 3), ("b", 1)]);
+    /// map.retain(|_k, v| v % 2 == 1);
+    /// assert_eq!(map.get("a").unwrap(), &[1, 3]);
+    /// assert_eq!(map.num_mappings(), 3);
+    /// ```
+    pub fn retain<F>(&mut self, mut f: F)
+    where
+        F: FnMut(&M::Key, &V) -> bool,
+    {
+        let mut length = 0;
+        self.map.retain(|key, list| {
+            list.retain(|value| f(key, value));
+            length += list.len();
+            !list.is_empty()
+        });
+        self.length = length;
+    }
+
+    /// Removes all mappings from the list multi-map, returning the keys and their lists as an iterator.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use multi_containers::BTreeListMultiMap;
+    /// let mut map = BTreeListMultiMap::from([(1, 'a'), (2, 'b'), (1, 'a')]);
+    /// let drained = map.drain().collect::<Vec<_>>();
+    /// assert_eq!(drained, vec![(1, ['a', 'a'].into()), (2, ['b'].into())]);
+    /// assert!(map.is_empty());
+    /// ```
+    pub fn drain(&mut self) -> M::Drain<'_> {
+        self.length = 0;
+        self.map.drain()
+    }
+
+    /// Returns `true` if the list multi-map contains the given (key, value) mapping.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use multi_containers::HashListMultiMap;
+    /// let mut map = HashListMultiMap::new();
+    /// map.push("a", 1);
+    /// assert_eq!(map.contains("a", &1), true);
+    /// assert_eq!(map.contains("a", &2), false);
+    /// ```
+    pub fn contains<Q, R>(&self, key: &Q, value: &R) -> bool
+    where
+        M: Lookup<Q>,
+        M::Key: Borrow<Q>,
+        Q: ?Sized,
+        V: Borrow<R>,
+        R: PartialEq + ?Sized,
+    {
+        self.map
+            .get(key)
+            .is_some_and(|list| list.iter().any(|v| v.borrow() == value))
+    }
+
+    /// Returns `true` if the list multi-map contains any mapping with the given key.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use multi_containers::HashListMultiMap;
+    /// let mut map = HashListMultiMap::new();
+    /// assert_eq!(map.contains_key("a"), false);
+    /// map.push("a", 1);
+    /// assert_eq!(map.contains_key("a"), true);
+    /// ```
+    pub fn contains_key<Q>(&self, key: &Q) -> bool
+    where
+        M: Lookup<Q>,
+        M::Key: Borrow<Q>,
+        Q: ?Sized,
+    {
+        self.map.contains_key(key)
+    }
+
+    /// Returns the list of values for the given key, in insertion order, if the key is present.
+    /// The list is never empty.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use multi_containers::HashListMultiMap;
+    /// let mut map = HashListMultiMap::new();
+    /// map.push("a", 1);
+    /// map.push("a", 2);
+    /// assert_eq!(map.get("a").unwrap()[1], 2);
+    /// assert_eq!(map.get("b"), None);
+    /// ```
+    pub fn get<Q>(&self, key: &Q) -> Option<&VecDeque<V>>
+    where
+        M: Lookup<Q>,
+        M::Key: Borrow<Q>,
+        Q: ?Sized,
+    {
+        self.map.get(key)
+    }
+
+    /// Returns an iterator over the keys of the list multi-map.
+    /// The keys are returned in the order specified by the underlying `Map` implementation.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use multi_containers::BTreeListMultiMap;
+    /// let map = BTreeListMultiMap::from([(2, 'a'), (1, 'b'), (2, 'c')]);
+    /// assert_eq!(map.keys().collect::<Vec<_>>(), vec![&1, &2]);
+    /// ```
+    pub fn keys(&self) -> M::KeyIter<'_> {
+        self.map.keys()
+    }
+
+    /// Returns an iterator over the keys and their lists of values.
+    /// The keys are returned in the order specified by the underlying `Map` implementation.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use multi_containers::BTreeListMultiMap;
+    /// let map = BTreeListMultiMap::from([(2, 'a'), (1, 'b'), (2, 'c')]);
+    /// let lists = map.lists().map(|(k, v)| (*k, v.iter().collect::<String>())).collect::<Vec<_>>();
+    /// assert_eq!(lists, vec![(1, "b".to_string()), (2, "ac".to_string())]);
+    /// ```
+    pub fn lists(&self) -> M::Iter<'_> {
+        self.map.iter()
+    }
+
+    /// Returns an iterator over the (key, value) mappings of the list multi-map.
+    /// The values of each key are returned in insertion order.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use multi_containers::BTreeListMultiMap;
+    /// let map = BTreeListMultiMap::from([(2, 'a'), (1, 'b'), (2, 'a')]);
+    /// assert_eq!(map.mappings().collect::<Vec<_>>(), vec![(&1, &'b'), (&2, &'a'), (&2, &'a')]);
+    /// ```
+    pub fn mappings<'a>(&'a self) -> impl Iterator<Item = (&'a M::Key, &'a V)> + 'a
+    where
+        V: 'a,
+    {
+        self.map
+            .iter()
+            .flat_map(|(key, list)| list.iter().map(move |value| (key, value)))
+    }
+
+    /// Consumes the list multi-map, returning an iterator over the keys and their owned lists of values.
+    /// The keys are returned in the order specified by the underlying `Map` implementation.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use multi_containers::BTreeListMultiMap;
+    /// let map = BTreeListMultiMap::from([(1, 'a'), (1, 'b')]);
+    /// assert_eq!(map.into_lists().collect::<Vec<_>>(), vec![(1, ['a', 'b'].into())]);
+    /// ```
+    pub fn into_lists(self) -> <M as IntoIterator>::IntoIter {
+        self.map.into_iter()
+    }
+
+    /// Returns `true` if the list multi-map is empty.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use multi_containers::HashListMultiMap;
+    /// let mut map = HashListMultiMap::new();
+    /// assert_eq!(map.is_empty(), true);
+    /// map.push("a", 1);
+    /// assert_eq!(map.is_empty(), false);
+    /// map.pop_front("a");
+    /// assert_eq!(map.is_empty(), true);
+    /// ```
+    pub fn is_empty(&self) -> bool {
+        self.map.is_empty()
+    }
+
+    /// Returns the number of keys in the list multi-map.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use multi_containers::HashListMultiMap;
+    /// let map: HashListMultiMap<_, _> = HashListMultiMap::from([("a", 1), ("a", 1), ("b", 2)]);
+    /// assert_eq!(map.num_keys(), 2);
+    /// ```
+    pub fn num_keys(&self) -> usize {
+        self.map.len()
+    }
+
+    /// Returns the number of (key, value) mappings in the list multi-map, counting duplicates.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use multi_containers::HashListMultiMap;
+    /// let map: HashListMultiMap<_, _> = HashListMultiMap::from([("a", 1), ("a", 1), ("b", 2)]);
+    /// assert_eq!(map.num_mappings(), 3);
+    /// ```
+    pub fn num_mappings(&self) -> usize {
+        self.length
+    }
+
+    /// Panics if the bookkeeping of the list multi-map is inconsistent:
+    /// every list must be non-empty, and the number of mappings must match the total length of the lists.
+    #[cfg(test)]
+    pub(crate) fn check_invariants(&self) {
+        assert!(
+            self.map.values().all(|list| !list.is_empty()),
+            "list multi-map contains an empty list"
+        );
+        assert_eq!(
+            self.length,
+            self.map.values().map(VecDeque::len).sum::<usize>(),
+            "list multi-map length does not match its lists"
+        );
+    }
+}
+
+impl<K, V, M> Extend<(K, V)> for ListMultiMap<M>
+where
+    M: Map<Key = K, Val = VecDeque<V>>,
+{
+    fn extend<I>(&mut self, iter: I)
+    where
+        I: IntoIterator<Item = (K, V)>,
+    {
+        for (key, value) in iter {
+            self.push(key, value);
+        }
+    }
+}
+
+impl<K, V, M> FromIterator<(K, V)> for ListMultiMap<M>
+where
+    M: DefaultMap<Key = K, Val = VecDeque<V>>,
+{
+    fn from_iter<I>(iter: I) -> Self
+    where
+        I: IntoIterator<Item = (K, V)>,
+    {
+        let mut map = ListMultiMap::default();
+        map.extend(iter);
+        map
+    }
+}
+
+impl<K, V, M, const N: usize> From<[(K, V); N]> for ListMultiMap<M>
+where
+    M: DefaultMap<Key = K, Val = VecDeque<V>>,
+{
+    fn from(array: [(K, V); N]) -> Self {
+        array.into_iter().collect::<Self>()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    macro_rules! base_test_suite {
+        ($mod_name:ident, $map_maker:expr) => {
+            mod $mod_name {
+                use crate::test_utils::unordered_elements_are;
+
+                #[test]
+                fn push_keeps_duplicates_in_order() {
+                    let mut map = $map_maker;
+                    map.push("a", 2);
+                    map.push("b", 1);
+                    map.push("a", 1);
+                    map.push("a", 2);
+                    map.check_invariants();
+                    assert_eq!(map.num_keys(), 2);
+                    assert_eq!(map.num_mappings(), 4);
+                    assert_eq!(map.get("a").unwrap(), &[2, 1, 2]);
+                    assert_eq!(map.get("a").unwrap()[1], 1);
+                    assert!(map.contains("a", &1));
+                    assert!(!map.contains("b", &2));
+                }
+
+                #[test]
+                fn pop_and_remove_first() {
+                    let mut map = $map_maker;
+                    map.extend([("a", 1), ("a", 2), ("a", 1), ("a", 3), ("b", 4)]);
+                    assert_eq!(map.remove_first("a", &1), Some(1));
+                    map.check_invariants();
+                    assert_eq!(map.get("a").unwrap(), &[2, 1, 3]);
+                    assert_eq!(map.remove_first("a", &4), None);
+                    assert_eq!(map.remove_first("c", &4), None);
+                    assert_eq!(map.pop_front("a"), Some(2));
+                    assert_eq!(map.pop_back("a"), Some(3));
+                    map.check_invariants();
+                    assert_eq!(map.num_mappings(), 2);
+                    assert_eq!(map.pop_front("a"), Some(1));
+                    assert_eq!(map.pop_front("a"), None);
+                    assert!(!map.contains_key("a"));
+                    assert_eq!(map.remove_first("b", &4), Some(4));
+                    map.check_invariants();
+                    assert!(map.is_empty());
+                    assert_eq!(map.num_mappings(), 0);
+                }
+
+                #[test]
+                fn retain_and_remove_key() {
+                    let mut map = $map_maker;
+                    map.extend([("a", 1), ("a", 2), ("a", 3), ("b", 2), ("c", 5)]);
+                    map.retain(|k, v| *k == "c" || v % 2 == 1);
+                    map.check_invariants();
+                    assert_eq!(map.num_keys(), 2);
+                    assert_eq!(map.get("a").unwrap(), &[1, 3]);
+                    assert_eq!(map.remove_key("a").unwrap(), [1, 3]);
+                    map.check_invariants();
+                    assert_eq!(map.num_mappings(), 1);
+                    assert_eq!(map.remove_key("a"), None);
+                }
+
+                #[test]
+                fn iterators() {
+                    let mut map = $map_maker;
+                    map.extend([("a", 1), ("b", 2), ("a", 1)]);
+                    assert!(unordered_elements_are(
+                        map.mappings().map(|(&k, &v)| (k, v)),
+                        vec![("a", 1), ("a", 1), ("b", 2)]
+                    ));
+                    assert!(unordered_elements_are(map.keys().copied(), vec!["a", "b"]));
+                    assert!(unordered_elements_are(
+                        map.lists().map(|(&k, v)| (k, v.len())),
+                        vec![("a", 2), ("b", 1)]
+                    ));
+                    assert!(unordered_elements_are(
+                        map.drain()
+                            .map(|(k, v)| (k, v.into_iter().collect::<Vec<_>>())),
+                        vec![("a", vec![1, 1]), ("b", vec![2])]
+                    ));
+                    map.check_invariants();
+                    assert!(map.is_empty());
+                }
+            }
+        };
+    }
+
+    base_test_suite!(hash_values, crate::HashListMultiMap::new());
+
+    base_test_suite!(sorted_values, crate::BTreeListMultiMap::new());
+}
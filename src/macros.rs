@@ -0,0 +1,160 @@
+/// Creates a `MultiMap` from a list of keys, each followed by a list of its values.
+/// The backend is inferred from the target type, or can be given explicitly as a type in angle brackets followed by `;`.
+/// Keys must implement `Clone`, as they are inserted once per value.
+/// Keys that are repeated have their values merged, and keys without values are not inserted.
+///
+/// # Example
+///
+/// ```
+/// use multi_containers::{multimap, BTreeMultiMap, HashMultiMap};
+/// let map: HashMultiMap<_, _> = multimap! {
+///     "a" => [1, 2],
+///     "b" => [3],
+/// };
+/// assert_eq!(map.num_mappings(), 3);
+///
+/// let map = multimap!(<BTreeMultiMap<_, _>>; "a" => [1, 2], "b" => [3]);
+/// assert!(map.mappings().eq([(&"a", &1), (&"a", &2), (&"b", &3)]));
+/// ```
+#[macro_export]
+macro_rules! multimap {
+    (<$backend:ty>; $($key:expr => [$($value:expr),* $(,)?]),* $(,)?) => {{
+        #[allow(unused_mut)]
+        let mut map: $backend = ::core::default::Default::default();
+        $({
+            #[allow(unused_variables)]
+            let key = $key;
+            $(
+                $crate::MultiMap::insert(&mut map, ::core::clone::Clone::clone(&key), $value);
+            )*
+        })*
+        map
+    }};
+    ($($key:expr => [$($value:expr),* $(,)?]),* $(,)?) => {{
+        #[allow(unused_mut)]
+        let mut map = $crate::MultiMap::default();
+        $({
+            #[allow(unused_variables)]
+            let key = $key;
+            $(
+                $crate::MultiMap::insert(&mut map, ::core::clone::Clone::clone(&key), $value);
+            )*
+        })*
+        map
+    }};
+}
+
+/// Creates a `MultiSet` from a list of values, each optionally followed by its count (which defaults to 1).
+/// The backend is inferred from the target type, or can be given explicitly as a type in angle brackets followed by `;`.
+/// Values that are repeated have their counts added up.
+///
+/// # Panics
+///
+/// Panics if a count does not fit in the count type of the multi-set.
+///
+/// # Example
+///
+/// ```
+/// use multi_containers::{multiset, BTreeMultiSet, HashMultiSet};
+/// let set: HashMultiSet<_> = multiset! { 'x' => 3, 'y' };
+/// assert_eq!(set.count(&'x'), 3);
+/// assert_eq!(set.count(&'y'), 1);
+///
+/// let set = multiset!(<BTreeMultiSet<_>>; 'x' => 3, 'y');
+/// assert!(set.iter().eq(&['x', 'x', 'x', 'y']));
+/// ```
+#[macro_export]
+macro_rules! multiset {
+    (@count) => {
+        1
+    };
+    (@count $count:expr) => {
+        $count
+    };
+    (<$backend:ty>; $($value:expr $(=> $count:expr)?),* $(,)?) => {{
+        #[allow(unused_mut)]
+        let mut set: $backend = ::core::default::Default::default();
+        $(
+            $crate::MultiSet::insert_some(&mut set, $value, $crate::multiset!(@count $($count)?));
+        )*
+        set
+    }};
+    ($($value:expr $(=> $count:expr)?),* $(,)?) => {{
+        #[allow(unused_mut)]
+        let mut set = $crate::MultiSet::default();
+        $(
+            $crate::MultiSet::insert_some(&mut set, $value, $crate::multiset!(@count $($count)?));
+        )*
+        set
+    }};
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{BTreeMultiMap, BTreeMultiSet, HashMultiMap, HashMultiSet, MultiSet};
+    use std::collections::BTreeMap;
+
+    #[test]
+    fn multimap_with_inferred_backend() {
+        let map: HashMultiMap<&str, i32> = multimap! {
+            "a" => [1, 2],
+            "b" => [3],
+            "a" => [2, 4],
+            "c" => [],
+        };
+        map.check_invariants();
+        assert_eq!(map.num_keys(), 2);
+        assert_eq!(map.num_mappings(), 4);
+        assert_eq!(map.get("a").unwrap().len(), 3);
+        assert!(!map.contains_key("c"));
+
+        let empty: BTreeMultiMap<i32, i32> = multimap! {};
+        assert!(empty.is_empty());
+    }
+
+    #[test]
+    fn multimap_with_explicit_backend() {
+        let map = multimap!(<BTreeMultiMap<String, i32>>; "a".to_string() => [1, 2], "b".to_string() => [3]);
+        map.check_invariants();
+        assert!(map
+            .mappings()
+            .map(|(k, v)| (k.as_str(), *v))
+            .eq([("a", 1), ("a", 2), ("b", 3)]));
+    }
+
+    #[test]
+    fn multimap_with_comparison_keys() {
+        let (a, b) = (1, 2);
+        let map: BTreeMultiMap<bool, i32> = multimap! { a < b => [1, 2], a > b => [3] };
+        map.check_invariants();
+        assert!(map.mappings().eq([(&false, &3), (&true, &1), (&true, &2)]));
+    }
+
+    #[test]
+    fn multiset_with_inferred_backend() {
+        let set: BTreeMultiSet<char> = multiset! { 'x' => 3, 'y', 'x', 'z' => 0 };
+        set.check_invariants();
+        assert_eq!(set.len(), 5);
+        assert_eq!(set.count(&'x'), 4);
+        assert_eq!(set.count(&'y'), 1);
+        assert!(!set.contains(&'z'));
+
+        let empty: HashMultiSet<char> = multiset! {};
+        assert!(empty.is_empty());
+    }
+
+    #[test]
+    fn multiset_with_comparison_keys() {
+        let n = 1;
+        let set: BTreeMultiSet<bool> = multiset! { n < 2 => 1, n > 2 => 2, <i32>::MAX > n };
+        set.check_invariants();
+        assert!(set.iter().eq(&[false, false, true, true]));
+    }
+
+    #[test]
+    fn multiset_with_explicit_backend() {
+        let set = multiset!(<MultiSet<BTreeMap<(i32, i32), u8>>>; (1, 2) => 2, (0, 0));
+        set.check_invariants();
+        assert!(set.iter().eq(&[(0, 0), (1, 2), (1, 2)]));
+    }
+}
@@ -1,10 +1,18 @@
+use crate::counts::Count;
+#[cfg(feature = "indexmap")]
+use indexmap::{map as index_map, IndexMap};
 use std::borrow::Borrow;
+use std::cmp::Ordering;
 use std::collections::{btree_map, hash_map, BTreeMap, HashMap};
-use std::hash::Hash;
-use std::ops::RangeBounds;
+use std::hash::{BuildHasher, Hash};
+use std::ops::{Bound, RangeBounds, RangeFull};
+
+/// A function that compares two keys, as returned by `Map::key_order`.
+pub type KeyOrder<K> = fn(&K, &K) -> Ordering;
 
 /// A map from keys to values.
-pub trait Map {
+/// Maps can be consumed into an iterator over their owned (key, value) entries.
+pub trait Map: IntoIterator<Item = (<Self as Map>::Key, <Self as Map>::Val)> {
     /// The type of keys in the map.
     type Key;
 
@@ -31,6 +39,32 @@ pub trait Map {
     where
         Self: 'a;
 
+    /// The type of iterator that removes and yields all entries of the map.
+    type Drain<'a>: Iterator<Item = (Self::Key, Self::Val)>
+    where
+        Self: 'a;
+
+    /// The type of iterator that removes and yields the entries of the map that match a predicate.
+    type ExtractIf<'a, F>: Iterator<Item = (Self::Key, Self::Val)>
+    where
+        Self: 'a,
+        F: FnMut(&Self::Key, &mut Self::Val) -> bool + 'a;
+
+    /// The type of a view into an occupied entry of the map.
+    type Occupied<'a>: OccupiedEntry<'a, Key = Self::Key, Val = Self::Val>
+    where
+        Self: 'a;
+
+    /// The type of a view into a vacant entry of the map.
+    type Vacant<'a>: VacantEntry<
+        'a,
+        Key = Self::Key,
+        Val = Self::Val,
+        Occupied = Self::Occupied<'a>,
+    >
+    where
+        Self: 'a;
+
     /// Inserts a value into the map. Returns the previous value if it existed.
     fn insert(&mut self, key: Self::Key, value: Self::Val) -> Option<Self::Val>;
 
@@ -41,6 +75,9 @@ pub trait Map {
         make_value: F,
     ) -> &mut Self::Val;
 
+    /// Returns a view into the entry for the given key, which may be occupied or vacant.
+    fn entry(&mut self, key: Self::Key) -> Entry<'_, Self>;
+
     /// Returns `true` if the map is empty.
     fn is_empty(&self) -> bool;
 
@@ -58,6 +95,124 @@ pub trait Map {
 
     /// Returns an iterator over the values of the map.
     fn values(&self) -> Self::ValIter<'_>;
+
+    /// Keeps only the entries for which the predicate returns `true`.
+    fn retain<F>(&mut self, f: F)
+    where
+        F: FnMut(&Self::Key, &mut Self::Val) -> bool;
+
+    /// Removes all entries from the map, returning them as an iterator.
+    fn drain(&mut self) -> Self::Drain<'_>;
+
+    /// Returns an iterator that removes and yields the entries for which the predicate returns `true`.
+    /// Entries are only removed as the iterator is advanced.
+    fn extract_if<'a, F>(&'a mut self, pred: F) -> Self::ExtractIf<'a, F>
+    where
+        F: FnMut(&Self::Key, &mut Self::Val) -> bool + 'a;
+
+    /// Returns the number of entries the map can hold without reallocating.
+    /// Maps without a notion of capacity report their length.
+    fn capacity(&self) -> usize {
+        self.len()
+    }
+
+    /// Reserves space for at least `additional` more entries. Does nothing by default.
+    fn reserve(&mut self, additional: usize) {
+        let _ = additional;
+    }
+
+    /// Shrinks the capacity of the map as much as possible. Does nothing by default.
+    fn shrink_to_fit(&mut self) {}
+
+    /// Returns the order in which the map keeps its keys, or `None` if it does not keep them sorted.
+    /// Maps that both keep their keys sorted can be walked together instead of looking up each key.
+    fn key_order() -> Option<KeyOrder<Self::Key>> {
+        None
+    }
+}
+
+/// A view into a single entry of a map, which may either be vacant or occupied.
+pub enum Entry<'a, M>
+where
+    M: Map + ?Sized + 'a,
+{
+    /// An entry for a key that is present in the map.
+    Occupied(M::Occupied<'a>),
+
+    /// An entry for a key that is not present in the map.
+    Vacant(M::Vacant<'a>),
+}
+
+impl<'a, M> Entry<'a, M>
+where
+    M: Map + ?Sized + 'a,
+{
+    /// Returns a reference to the key of this entry.
+    pub fn key(&self) -> &M::Key {
+        match self {
+            Entry::Occupied(entry) => entry.key(),
+            Entry::Vacant(entry) => entry.key(),
+        }
+    }
+
+    /// Inserts a value if the entry is vacant. Returns a mutable reference to the (maybe new) value.
+    pub fn or_insert_with<F>(self, make_value: F) -> &'a mut M::Val
+    where
+        F: FnOnce() -> M::Val,
+    {
+        match self {
+            Entry::Occupied(entry) => entry.into_mut(),
+            Entry::Vacant(entry) => entry.insert(make_value()),
+        }
+    }
+}
+
+/// A view into an occupied entry of a map, matching the API of `HashMap` and `BTreeMap` entries.
+pub trait OccupiedEntry<'a> {
+    /// The type of keys in the map.
+    type Key;
+
+    /// The type of values in the map.
+    type Val;
+
+    /// Returns a reference to the key of this entry.
+    fn key(&self) -> &Self::Key;
+
+    /// Returns a reference to the value of this entry.
+    fn get(&self) -> &Self::Val;
+
+    /// Returns a mutable reference to the value of this entry.
+    fn get_mut(&mut self) -> &mut Self::Val;
+
+    /// Converts the entry into a mutable reference to its value, with the lifetime of the map.
+    fn into_mut(self) -> &'a mut Self::Val;
+
+    /// Removes the entry from the map. Returns the key and value that were stored.
+    fn remove_entry(self) -> (Self::Key, Self::Val);
+}
+
+/// A view into a vacant entry of a map, matching the API of `HashMap` and `BTreeMap` entries.
+pub trait VacantEntry<'a> {
+    /// The type of keys in the map.
+    type Key;
+
+    /// The type of values in the map.
+    type Val;
+
+    /// The type of entry produced by inserting into this one.
+    type Occupied: OccupiedEntry<'a, Key = Self::Key, Val = Self::Val>;
+
+    /// Returns a reference to the key that would be used when inserting.
+    fn key(&self) -> &Self::Key;
+
+    /// Takes ownership of the key.
+    fn into_key(self) -> Self::Key;
+
+    /// Inserts a value into the map. Returns a mutable reference to it.
+    fn insert(self, value: Self::Val) -> &'a mut Self::Val;
+
+    /// Inserts a value into the map. Returns an occupied entry pointing to it.
+    fn insert_entry(self, value: Self::Val) -> Self::Occupied;
 }
 
 /// A helper trait that allows us to query the map more flexibly, and matches the API of `HashMap` and `BTreeMap`.
@@ -106,9 +261,41 @@ where
         R: RangeBounds<Q>;
 }
 
-impl<K, V> Map for HashMap<K, V>
+// Returns `true` if the key falls before the start bound of a range.
+pub(crate) fn is_before_start<Q: Ord + ?Sized>(key: &Q, start: Bound<&Q>) -> bool {
+    match start {
+        Bound::Included(start) => key < start,
+        Bound::Excluded(start) => key <= start,
+        Bound::Unbounded => false,
+    }
+}
+
+// Returns `true` if the key falls before the end bound of a range.
+pub(crate) fn is_before_end<Q: Ord + ?Sized>(key: &Q, end: Bound<&Q>) -> bool {
+    match end {
+        Bound::Included(end) => key <= end,
+        Bound::Excluded(end) => key < end,
+        Bound::Unbounded => true,
+    }
+}
+
+/// A map that can be created empty without choosing a hasher.
+/// Hash maps only implement this for the default `RandomState` hasher, so that `MultiMap::new`, `collect` and the
+/// other constructors that take no hasher infer it the way `HashMap::new` does.
+/// Multi-maps with a custom hasher are created with `with_hasher` instead, and filled with `extend`.
+pub trait DefaultMap: Map + Default {}
+
+impl<K, V> DefaultMap for HashMap<K, V> where K: Hash + Eq {}
+
+impl<K, V> DefaultMap for BTreeMap<K, V> where K: Ord {}
+
+#[cfg(feature = "indexmap")]
+impl<K, V> DefaultMap for IndexMap<K, V> where K: Hash + Eq {}
+
+impl<K, V, S> Map for HashMap<K, V, S>
 where
     K: Hash + Eq,
+    S: BuildHasher,
 {
     type Key = K;
     type Val = V;
@@ -116,6 +303,13 @@ where
     type IterMut<'a> = hash_map::IterMut<'a, K, V> where Self: 'a;
     type KeyIter<'a> = hash_map::Keys<'a, K, V> where Self: 'a;
     type ValIter<'a> = hash_map::Values<'a, K, V> where Self: 'a;
+    type Drain<'a> = hash_map::Drain<'a, K, V> where Self: 'a;
+    type ExtractIf<'a, F> = hash_map::ExtractIf<'a, K, V, F>
+    where
+        Self: 'a,
+        F: FnMut(&K, &mut V) -> bool + 'a;
+    type Occupied<'a> = hash_map::OccupiedEntry<'a, K, V> where Self: 'a;
+    type Vacant<'a> = hash_map::VacantEntry<'a, K, V> where Self: 'a;
 
     fn insert(&mut self, key: Self::Key, value: Self::Val) -> Option<Self::Val> {
         self.insert(key, value)
@@ -128,6 +322,13 @@ where
         self.entry(key).or_insert_with(make_value)
     }
 
+    fn entry(&mut self, key: Self::Key) -> Entry<'_, Self> {
+        match self.entry(key) {
+            hash_map::Entry::Occupied(entry) => Entry::Occupied(entry),
+            hash_map::Entry::Vacant(entry) => Entry::Vacant(entry),
+        }
+    }
+
     fn is_empty(&self) -> bool {
         self.is_empty()
     }
@@ -151,12 +352,43 @@ where
     fn values(&self) -> Self::ValIter<'_> {
         self.values()
     }
+
+    fn retain<F>(&mut self, f: F)
+    where
+        F: FnMut(&K, &mut V) -> bool,
+    {
+        self.retain(f)
+    }
+
+    fn drain(&mut self) -> Self::Drain<'_> {
+        self.drain()
+    }
+
+    fn extract_if<'a, F>(&'a mut self, pred: F) -> Self::ExtractIf<'a, F>
+    where
+        F: FnMut(&K, &mut V) -> bool + 'a,
+    {
+        self.extract_if(pred)
+    }
+
+    fn capacity(&self) -> usize {
+        self.capacity()
+    }
+
+    fn reserve(&mut self, additional: usize) {
+        self.reserve(additional)
+    }
+
+    fn shrink_to_fit(&mut self) {
+        self.shrink_to_fit()
+    }
 }
 
-impl<K, V, Q> Lookup<Q> for HashMap<K, V>
+impl<K, V, S, Q> Lookup<Q> for HashMap<K, V, S>
 where
     K: Eq + Hash + Borrow<Q>,
     Q: Hash + Eq + ?Sized,
+    S: BuildHasher,
 {
     fn contains_key(&self, key: &Q) -> bool {
         self.contains_key(key)
@@ -185,6 +417,14 @@ where
     type IterMut<'a> = btree_map::IterMut<'a, K, V> where Self: 'a;
     type KeyIter<'a> = btree_map::Keys<'a, K, V> where Self: 'a;
     type ValIter<'a> = btree_map::Values<'a, K, V> where Self: 'a;
+    // `BTreeMap` has no `drain`, so draining takes the whole map and iterates over it.
+    type Drain<'a> = btree_map::IntoIter<K, V> where Self: 'a;
+    type ExtractIf<'a, F> = btree_map::ExtractIf<'a, K, V, RangeFull, F>
+    where
+        Self: 'a,
+        F: FnMut(&K, &mut V) -> bool + 'a;
+    type Occupied<'a> = btree_map::OccupiedEntry<'a, K, V> where Self: 'a;
+    type Vacant<'a> = btree_map::VacantEntry<'a, K, V> where Self: 'a;
 
     fn insert(&mut self, key: Self::Key, value: Self::Val) -> Option<Self::Val> {
         self.insert(key, value)
@@ -197,6 +437,13 @@ where
         self.entry(key).or_insert_with(make_value)
     }
 
+    fn entry(&mut self, key: Self::Key) -> Entry<'_, Self> {
+        match self.entry(key) {
+            btree_map::Entry::Occupied(entry) => Entry::Occupied(entry),
+            btree_map::Entry::Vacant(entry) => Entry::Vacant(entry),
+        }
+    }
+
     fn is_empty(&self) -> bool {
         self.is_empty()
     }
@@ -220,6 +467,28 @@ where
     fn values(&self) -> Self::ValIter<'_> {
         self.values()
     }
+
+    fn retain<F>(&mut self, f: F)
+    where
+        F: FnMut(&K, &mut V) -> bool,
+    {
+        self.retain(f)
+    }
+
+    fn drain(&mut self) -> Self::Drain<'_> {
+        std::mem::take(self).into_iter()
+    }
+
+    fn extract_if<'a, F>(&'a mut self, pred: F) -> Self::ExtractIf<'a, F>
+    where
+        F: FnMut(&K, &mut V) -> bool + 'a,
+    {
+        self.extract_if(.., pred)
+    }
+
+    fn key_order() -> Option<KeyOrder<K>> {
+        Some(K::cmp)
+    }
 }
 
 impl<K, V, Q> SortedMap<Q> for BTreeMap<K, V>
@@ -266,3 +535,349 @@ where
         self.remove(key)
     }
 }
+
+impl<'a, K, V> OccupiedEntry<'a> for hash_map::OccupiedEntry<'a, K, V>
+where
+    K: Hash + Eq,
+{
+    type Key = K;
+    type Val = V;
+
+    fn key(&self) -> &K {
+        self.key()
+    }
+
+    fn get(&self) -> &V {
+        self.get()
+    }
+
+    fn get_mut(&mut self) -> &mut V {
+        self.get_mut()
+    }
+
+    fn into_mut(self) -> &'a mut V {
+        self.into_mut()
+    }
+
+    fn remove_entry(self) -> (K, V) {
+        self.remove_entry()
+    }
+}
+
+impl<'a, K, V> VacantEntry<'a> for hash_map::VacantEntry<'a, K, V>
+where
+    K: Hash + Eq,
+{
+    type Key = K;
+    type Val = V;
+    type Occupied = hash_map::OccupiedEntry<'a, K, V>;
+
+    fn key(&self) -> &K {
+        self.key()
+    }
+
+    fn into_key(self) -> K {
+        self.into_key()
+    }
+
+    fn insert(self, value: V) -> &'a mut V {
+        self.insert(value)
+    }
+
+    fn insert_entry(self, value: V) -> Self::Occupied {
+        self.insert_entry(value)
+    }
+}
+
+impl<'a, K, V> OccupiedEntry<'a> for btree_map::OccupiedEntry<'a, K, V>
+where
+    K: Ord,
+{
+    type Key = K;
+    type Val = V;
+
+    fn key(&self) -> &K {
+        self.key()
+    }
+
+    fn get(&self) -> &V {
+        self.get()
+    }
+
+    fn get_mut(&mut self) -> &mut V {
+        self.get_mut()
+    }
+
+    fn into_mut(self) -> &'a mut V {
+        self.into_mut()
+    }
+
+    fn remove_entry(self) -> (K, V) {
+        self.remove_entry()
+    }
+}
+
+impl<'a, K, V> VacantEntry<'a> for btree_map::VacantEntry<'a, K, V>
+where
+    K: Ord,
+{
+    type Key = K;
+    type Val = V;
+    type Occupied = btree_map::OccupiedEntry<'a, K, V>;
+
+    fn key(&self) -> &K {
+        self.key()
+    }
+
+    fn into_key(self) -> K {
+        self.into_key()
+    }
+
+    fn insert(self, value: V) -> &'a mut V {
+        self.insert(value)
+    }
+
+    fn insert_entry(self, value: V) -> Self::Occupied {
+        self.insert_entry(value)
+    }
+}
+
+/// A map from keys to counts that can find the key at a given position, counting each key as many times as its count.
+/// The default implementation walks the map in linear time.
+pub trait Select: Map<Val: Count> {
+    /// Returns the key at the given position, in iteration order, when each key is repeated as many times as its count.
+    /// Returns `None` if the position is at least the sum of the counts.
+    fn select(&self, index: usize) -> Option<&Self::Key> {
+        let mut index = index;
+        self.iter().find_map(|(key, count)| {
+            let count = count.to_usize();
+            if index < count {
+                Some(key)
+            } else {
+                index -= count;
+                None
+            }
+        })
+    }
+}
+
+/// A sorted map from keys to counts that can sum the counts below a key, or within a range of keys.
+/// The default implementations sum over a range of the map in linear time.
+pub trait Rank<Q>: SortedMap<Q> + Select
+where
+    Q: ?Sized,
+    Self::Key: Borrow<Q>,
+{
+    /// Returns the sum of the counts of the keys less than the given key.
+    fn rank(&self, key: &Q) -> usize {
+        self.count_in_range((Bound::Unbounded, Bound::Excluded(key)))
+    }
+
+    /// Returns the sum of the counts of the keys within a range.
+    fn count_in_range<R>(&self, range: R) -> usize
+    where
+        R: RangeBounds<Q>,
+    {
+        self.range(range).map(|(_, count)| count.to_usize()).sum()
+    }
+}
+
+impl<K, C, S> Select for HashMap<K, C, S>
+where
+    K: Hash + Eq,
+    C: Count,
+    S: BuildHasher,
+{
+}
+
+impl<K, C> Select for BTreeMap<K, C>
+where
+    K: Ord,
+    C: Count,
+{
+}
+
+impl<K, C, Q> Rank<Q> for BTreeMap<K, C>
+where
+    K: Ord + Borrow<Q>,
+    C: Count,
+    Q: Ord + ?Sized,
+{
+}
+
+// `IndexMap` keeps its entries in insertion order. Removing through the `Map` and `Lookup` traits shifts the
+// following entries to preserve that order, at the cost of `O(n)` time.
+#[cfg(feature = "indexmap")]
+impl<K, V, S> Map for IndexMap<K, V, S>
+where
+    K: Hash + Eq,
+    S: BuildHasher,
+{
+    type Key = K;
+    type Val = V;
+    type Iter<'a> = index_map::Iter<'a, K, V> where Self: 'a;
+    type IterMut<'a> = index_map::IterMut<'a, K, V> where Self: 'a;
+    type KeyIter<'a> = index_map::Keys<'a, K, V> where Self: 'a;
+    type ValIter<'a> = index_map::Values<'a, K, V> where Self: 'a;
+    type Drain<'a> = index_map::Drain<'a, K, V> where Self: 'a;
+    type ExtractIf<'a, F> = index_map::ExtractIf<'a, K, V, F>
+    where
+        Self: 'a,
+        F: FnMut(&K, &mut V) -> bool + 'a;
+    type Occupied<'a> = index_map::OccupiedEntry<'a, K, V> where Self: 'a;
+    type Vacant<'a> = index_map::VacantEntry<'a, K, V> where Self: 'a;
+
+    fn insert(&mut self, key: Self::Key, value: Self::Val) -> Option<Self::Val> {
+        self.insert(key, value)
+    }
+
+    fn get_or_insert<F>(&mut self, key: Self::Key, make_value: F) -> &mut Self::Val
+    where
+        F: FnOnce() -> Self::Val,
+    {
+        self.entry(key).or_insert_with(make_value)
+    }
+
+    fn entry(&mut self, key: Self::Key) -> Entry<'_, Self> {
+        match self.entry(key) {
+            index_map::Entry::Occupied(entry) => Entry::Occupied(entry),
+            index_map::Entry::Vacant(entry) => Entry::Vacant(entry),
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.is_empty()
+    }
+
+    fn len(&self) -> usize {
+        self.len()
+    }
+
+    fn iter(&self) -> Self::Iter<'_> {
+        self.iter()
+    }
+
+    fn iter_mut(&mut self) -> Self::IterMut<'_> {
+        self.iter_mut()
+    }
+
+    fn keys(&self) -> Self::KeyIter<'_> {
+        self.keys()
+    }
+
+    fn values(&self) -> Self::ValIter<'_> {
+        self.values()
+    }
+
+    fn retain<F>(&mut self, f: F)
+    where
+        F: FnMut(&K, &mut V) -> bool,
+    {
+        self.retain(f)
+    }
+
+    fn drain(&mut self) -> Self::Drain<'_> {
+        self.drain(..)
+    }
+
+    fn extract_if<'a, F>(&'a mut self, pred: F) -> Self::ExtractIf<'a, F>
+    where
+        F: FnMut(&K, &mut V) -> bool + 'a,
+    {
+        self.extract_if(.., pred)
+    }
+
+    fn capacity(&self) -> usize {
+        self.capacity()
+    }
+
+    fn reserve(&mut self, additional: usize) {
+        self.reserve(additional)
+    }
+
+    fn shrink_to_fit(&mut self) {
+        self.shrink_to_fit()
+    }
+}
+
+#[cfg(feature = "indexmap")]
+impl<K, V, S, Q> Lookup<Q> for IndexMap<K, V, S>
+where
+    K: Eq + Hash + Borrow<Q>,
+    Q: Hash + Eq + ?Sized,
+    S: BuildHasher,
+{
+    fn contains_key(&self, key: &Q) -> bool {
+        self.contains_key(key)
+    }
+
+    fn get(&self, key: &Q) -> Option<&V> {
+        self.get(key)
+    }
+
+    fn get_mut(&mut self, key: &Q) -> Option<&mut V> {
+        self.get_mut(key)
+    }
+
+    fn remove(&mut self, key: &Q) -> Option<V> {
+        self.shift_remove(key)
+    }
+}
+
+#[cfg(feature = "indexmap")]
+impl<'a, K, V> OccupiedEntry<'a> for index_map::OccupiedEntry<'a, K, V> {
+    type Key = K;
+    type Val = V;
+
+    fn key(&self) -> &K {
+        self.key()
+    }
+
+    fn get(&self) -> &V {
+        self.get()
+    }
+
+    fn get_mut(&mut self) -> &mut V {
+        self.get_mut()
+    }
+
+    fn into_mut(self) -> &'a mut V {
+        self.into_mut()
+    }
+
+    fn remove_entry(self) -> (K, V) {
+        self.shift_remove_entry()
+    }
+}
+
+#[cfg(feature = "indexmap")]
+impl<'a, K, V> VacantEntry<'a> for index_map::VacantEntry<'a, K, V> {
+    type Key = K;
+    type Val = V;
+    type Occupied = index_map::OccupiedEntry<'a, K, V>;
+
+    fn key(&self) -> &K {
+        self.key()
+    }
+
+    fn into_key(self) -> K {
+        self.into_key()
+    }
+
+    fn insert(self, value: V) -> &'a mut V {
+        self.insert(value)
+    }
+
+    fn insert_entry(self, value: V) -> Self::Occupied {
+        self.insert_entry(value)
+    }
+}
+
+#[cfg(feature = "indexmap")]
+impl<K, C, S> Select for IndexMap<K, C, S>
+where
+    K: Hash + Eq,
+    C: Count,
+    S: BuildHasher,
+{
+}
@@ -1,23 +1,34 @@
+use crate::maps;
 use crate::maps::*;
+use crate::maps::{OccupiedEntry as _, VacantEntry as _};
 use crate::sets::*;
+use crate::sorted_vec_map::SortedVecMap;
+use crate::sorted_vec_set::SortedVecSet;
+#[cfg(feature = "indexmap")]
+use indexmap::IndexMap;
 use std::borrow::Borrow;
+use std::collections::{BTreeMap, BTreeSet, HashMap, VecDeque};
 use std::fmt::Debug;
-use std::ops::RangeBounds;
+use std::hash::{BuildHasher, Hash};
+use std::iter::{FusedIterator, Peekable};
+use std::ops::{Deref, DerefMut, RangeBounds};
 
 /// A multi-map from keys to values.
 /// This can be thought of as an ergonomic wrapper around `Map<K, Set<V>>`.
 /// The semantics of a multi-map is that it can contain multiple values for a single key.
 /// The multi-map is implemented as a managed map from keys to sets of values. For bookkeeping, the
-/// value sets are queryable, but not modifiable except through the multi-map API.
-#[derive(Default, Debug, PartialEq, Eq, Clone)]
+/// value sets are queryable, but only modifiable through the multi-map API and the guards it hands out.
+#[derive(Default, Clone)]
 pub struct MultiMap<M> {
     map: M,
     length: usize,
+    // A hint for how many values newly created value sets should have space for.
+    value_capacity: usize,
 }
 
 impl<M> MultiMap<M>
 where
-    M: Default,
+    M: DefaultMap,
 {
     /// Creates a new, empty multi-map.
     ///
@@ -35,15 +46,262 @@ where
         MultiMap {
             map: Default::default(),
             length: 0,
+            value_capacity: 0,
         }
     }
 }
 
+impl<K, V> MultiMap<HashMap<K, V>> {
+    /// Creates a new, empty multi-map with space for at least `capacity` keys.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use multi_containers::HashMultiMap;
+    /// let map: HashMultiMap<&str, i32> = HashMultiMap::with_capacity(10);
+    /// assert!(map.capacity() >= 10);
+    /// ```
+    pub fn with_capacity(capacity: usize) -> Self {
+        MultiMap {
+            map: HashMap::with_capacity(capacity),
+            length: 0,
+            value_capacity: 0,
+        }
+    }
+}
+
+impl<K, V, S> MultiMap<HashMap<K, V, S>>
+where
+    K: Hash + Eq,
+    V: Set,
+    S: BuildHasher,
+{
+    /// Creates a new, empty multi-map which will use the given hasher to hash keys.
+    /// Value sets are created with their `Default` implementation, so for a `HashMultiMap` they use
+    /// `S::default()`.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use multi_containers::HashMultiMap;
+    /// use std::collections::hash_map::DefaultHasher;
+    /// use std::hash::BuildHasherDefault;
+    /// type Hasher = BuildHasherDefault<DefaultHasher>;
+    /// let mut map: HashMultiMap<&str, i32, Hasher> = HashMultiMap::with_hasher(Hasher::default());
+    /// map.insert("a", 1);
+    /// assert_eq!(map.num_mappings(), 1);
+    /// ```
+    pub fn with_hasher(hasher: S) -> Self {
+        Self::from_empty_map(HashMap::with_hasher(hasher), 0)
+    }
+
+    /// Creates a new, empty multi-map with space for at least `capacity` keys, which will use the given
+    /// hasher to hash keys.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use multi_containers::HashMultiMap;
+    /// use std::collections::hash_map::DefaultHasher;
+    /// use std::hash::BuildHasherDefault;
+    /// type Hasher = BuildHasherDefault<DefaultHasher>;
+    /// let mut map: HashMultiMap<&str, i32, Hasher> =
+    ///     HashMultiMap::with_capacity_and_hasher(10, Hasher::default());
+    /// map.insert("a", 1);
+    /// assert_eq!(map.num_mappings(), 1);
+    /// ```
+    pub fn with_capacity_and_hasher(capacity: usize, hasher: S) -> Self {
+        Self::from_empty_map(HashMap::with_capacity_and_hasher(capacity, hasher), 0)
+    }
+}
+
+impl<K, V> MultiMap<BTreeMap<K, BTreeSet<V>>>
+where
+    K: Ord,
+    V: Ord,
+{
+    /// Converts the multi-map into a `SortedVecMultiMap`, which is faster to query but slower to update.
+    /// This takes `O(n)` time, as the keys and values are already sorted.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use multi_containers::BTreeMultiMap;
+    /// let mut map = BTreeMultiMap::new();
+    /// map.insert("b", 2);
+    /// map.insert("a", 1);
+    /// map.insert("b", 3);
+    /// let frozen = map.freeze();
+    /// assert_eq!(frozen.num_mappings(), 3);
+    /// assert!(frozen.get("b").unwrap().iter().eq(&[2, 3]));
+    /// assert_eq!(frozen.value_sets_in_range("b"..).count(), 1);
+    /// ```
+    pub fn freeze(self) -> MultiMap<SortedVecMap<K, SortedVecSet<V>>> {
+        let entries = self
+            .map
+            .into_iter()
+            .map(|(key, set)| {
+                (
+                    key,
+                    SortedVecSet::from_sorted_vec(set.into_iter().collect()),
+                )
+            })
+            .collect();
+        MultiMap {
+            map: SortedVecMap::from_sorted_vec(entries),
+            length: self.length,
+            value_capacity: 0,
+        }
+    }
+}
+
+impl<K, V> MultiMap<SortedVecMap<K, SortedVecSet<V>>>
+where
+    K: Ord,
+    V: Ord,
+{
+    /// Creates a multi-map from (key, value) mappings in any order, in `O(n log n)` time.
+    /// Duplicate mappings are only stored once.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use multi_containers::SortedVecMultiMap;
+    /// let map = SortedVecMultiMap::from_unsorted([(2, 'b'), (1, 'a'), (2, 'a'), (1, 'a')]);
+    /// assert_eq!(map.num_keys(), 2);
+    /// assert_eq!(map.num_mappings(), 3);
+    /// assert!(map.get(&2).unwrap().iter().eq(&['a', 'b']));
+    /// ```
+    pub fn from_unsorted<I>(mappings: I) -> Self
+    where
+        I: IntoIterator<Item = (K, V)>,
+    {
+        let mut mappings = mappings.into_iter().collect::<Vec<_>>();
+        mappings.sort_unstable();
+        mappings.dedup();
+        let length = mappings.len();
+        let mut entries: Vec<(K, Vec<V>)> = Vec::new();
+        for (key, value) in mappings {
+            match entries.last_mut() {
+                Some((last, values)) if *last == key => values.push(value),
+                _ => entries.push((key, vec![value])),
+            }
+        }
+        let entries = entries
+            .into_iter()
+            .map(|(key, values)| (key, SortedVecSet::from_sorted_vec(values)))
+            .collect();
+        MultiMap {
+            map: SortedVecMap::from_sorted_vec(entries),
+            length,
+            value_capacity: 0,
+        }
+    }
+}
+
+#[cfg(feature = "indexmap")]
+impl<K, V, S> MultiMap<IndexMap<K, V, S>>
+where
+    K: Hash + Eq,
+    V: Set,
+    S: BuildHasher,
+{
+    /// Returns the key at the given position in insertion order, along with its value set.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use multi_containers::IndexMultiMap;
+    /// let map: IndexMultiMap<_, _> = IndexMultiMap::from([("b", 1), ("a", 2), ("b", 3)]);
+    /// let (key, values) = map.get_index(0).unwrap();
+    /// assert_eq!(*key, "b");
+    /// assert_eq!(values.len(), 2);
+    /// assert!(map.get_index(2).is_none());
+    /// ```
+    pub fn get_index(&self, index: usize) -> Option<(&K, &V)> {
+        self.map.get_index(index)
+    }
+
+    /// Returns the position of a key in insertion order, if it is present.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use multi_containers::IndexMultiMap;
+    /// let map: IndexMultiMap<_, _> = IndexMultiMap::from([("b", 1), ("a", 2)]);
+    /// assert_eq!(map.get_index_of("a"), Some(1));
+    /// assert_eq!(map.get_index_of("c"), None);
+    /// ```
+    pub fn get_index_of<Q>(&self, key: &Q) -> Option<usize>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.map.get_index_of(key)
+    }
+
+    /// Removes all mappings with the given key, by swapping the last key into its position.
+    /// This takes `O(1)` time, but changes the order of the keys. Returns the set of values that were removed, if any.
+    /// `remove_key` and the other removal methods preserve the order instead, like `shift_remove_key`.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use multi_containers::IndexMultiMap;
+    /// let mut map: IndexMultiMap<_, _> = IndexMultiMap::from([("a", 1), ("b", 2), ("c", 3)]);
+    /// assert_eq!(map.swap_remove_key("a").unwrap().len(), 1);
+    /// assert_eq!(map.keys().collect::<Vec<_>>(), vec![&"c", &"b"]);
+    /// assert_eq!(map.num_mappings(), 2);
+    /// ```
+    pub fn swap_remove_key<Q>(&mut self, key: &Q) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let set = self.map.swap_remove(key)?;
+        self.length -= set.len();
+        Some(set)
+    }
+
+    /// Removes all mappings with the given key, by shifting the following keys down.
+    /// This takes `O(n)` time, but preserves the order of the keys. Returns the set of values that were removed,
+    /// if any.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use multi_containers::IndexMultiMap;
+    /// let mut map: IndexMultiMap<_, _> = IndexMultiMap::from([("a", 1), ("b", 2), ("c", 3)]);
+    /// assert_eq!(map.shift_remove_key("a").unwrap().len(), 1);
+    /// assert_eq!(map.keys().collect::<Vec<_>>(), vec![&"b", &"c"]);
+    /// assert_eq!(map.num_mappings(), 2);
+    /// ```
+    pub fn shift_remove_key<Q>(&mut self, key: &Q) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let set = self.map.shift_remove(key)?;
+        self.length -= set.len();
+        Some(set)
+    }
+}
+
 impl<M> MultiMap<M>
 where
     M: Map,
     M::Val: Set,
 {
+    // Wraps a map which must be empty, such as one configured by a builder.
+    pub(crate) fn from_empty_map(map: M, value_capacity: usize) -> Self {
+        debug_assert!(map.is_empty());
+        MultiMap {
+            map,
+            length: 0,
+            value_capacity,
+        }
+    }
+
     /// Inserts a (key, value) mapping into the multi-map.
     /// Returns `true` if it was not already present.
     ///
@@ -63,7 +321,12 @@ where
     where
         M::Val: Default,
     {
-        if self.map.get_or_insert(key, Default::default).insert(value) {
+        let value_capacity = self.value_capacity;
+        if self
+            .map
+            .get_or_insert(key, || M::Val::with_capacity(value_capacity))
+            .insert(value)
+        {
             self.length += 1;
             true
         } else {
@@ -71,6 +334,38 @@ where
         }
     }
 
+    /// Returns a view into the values for the given key, which may be occupied or vacant.
+    /// The entry keeps the number of mappings up to date, and removes the key once its last value is removed.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use multi_containers::HashMultiMap;
+    /// use multi_containers::multimap::Entry;
+    /// let mut map = HashMultiMap::new();
+    /// let mut entry = map.entry("a").or_insert_values([1, 2]);
+    /// assert_eq!(entry.insert_value(3), true);
+    /// assert_eq!(entry.values().len(), 3);
+    /// drop(entry);
+    /// if let Entry::Occupied(mut entry) = map.entry("a") {
+    ///     assert_eq!(entry.remove_value(&1), true);
+    /// }
+    /// assert_eq!(map.num_mappings(), 2);
+    /// ```
+    pub fn entry(&mut self, key: M::Key) -> Entry<'_, M> {
+        match self.map.entry(key) {
+            maps::Entry::Occupied(entry) => Entry::Occupied(OccupiedEntry {
+                entry: Some(entry),
+                length: &mut self.length,
+            }),
+            maps::Entry::Vacant(entry) => Entry::Vacant(VacantEntry {
+                entry,
+                length: &mut self.length,
+                value_capacity: self.value_capacity,
+            }),
+        }
+    }
+
     /// Returns `true` if the multi-map contains the given (key, value) mapping.
     ///
     /// # Example
@@ -91,7 +386,7 @@ where
         <<M as Map>::Val as Set>::Elem: Borrow<R>,
         R: ?Sized,
     {
-        self.map.get(key).map_or(false, |set| set.contains(value))
+        self.map.get(key).is_some_and(|set| set.contains(value))
     }
 
     /// Returns `true` if the multi-map contains any mapping with the given key.
@@ -163,6 +458,7 @@ where
     /// assert_eq!(map.insert("a".to_string(), 2), true);
     /// assert_eq!(map.remove_key("a").unwrap().contains(&1), true);
     /// assert_eq!(map.remove_key("a").is_some(), false);
+    /// assert_eq!(map.num_mappings(), 0);
     /// ```
     pub fn remove_key<Q>(&mut self, key: &Q) -> Option<M::Val>
     where
@@ -170,7 +466,128 @@ where
         M::Key: Borrow<Q>,
         Q: ?Sized,
     {
-        self.map.remove(key)
+        let set = self.map.remove(key)?;
+        self.length -= set.len();
+        Some(set)
+    }
+
+    /// Keeps only the (key, value) mappings for which the predicate returns `true`.
+    /// Keys that are left without values are removed.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use multi_containers::HashMultiMap;
+    /// let mut map = HashMultiMap::new();
+    /// map.insert("a", 1);
+    /// map.insert("a", 2);
+    /// map.insert("b", 3);
+    /// map.retain(|_k, v| v % 2 == 0);
+    /// assert_eq!(map.num_keys(), 1);
+    /// assert_eq!(map.num_mappings(), 1);
+    /// assert_eq!(map.contains("a", &2), true);
+    /// ```
+    pub fn retain<F>(&mut self, mut f: F)
+    where
+        F: FnMut(&M::Key, &<<M as Map>::Val as Set>::Elem) -> bool,
+    {
+        let length = &mut self.length;
+        self.map.retain(|k, set| {
+            let before = set.len();
+            set.retain(|v| f(k, v));
+            *length -= before - set.len();
+            !set.is_empty()
+        });
+    }
+
+    /// Keeps only the keys for which the predicate returns `true`, along with all of their values.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use multi_containers::HashMultiMap;
+    /// let mut map = HashMultiMap::new();
+    /// map.insert("a", 1);
+    /// map.insert("a", 2);
+    /// map.insert("b", 3);
+    /// map.retain_keys(|_k, set| set.len() > 1);
+    /// assert_eq!(map.num_keys(), 1);
+    /// assert_eq!(map.num_mappings(), 2);
+    /// ```
+    pub fn retain_keys<F>(&mut self, mut f: F)
+    where
+        F: FnMut(&M::Key, &M::Val) -> bool,
+    {
+        let length = &mut self.length;
+        self.map.retain(|k, set| {
+            let keep = f(k, set);
+            if !keep {
+                *length -= set.len();
+            }
+            keep
+        });
+    }
+
+    /// Removes all mappings from the multi-map, returning them as an iterator of owned (key, value) pairs.
+    /// The key is cloned for each of its values.
+    /// If the iterator is dropped before it is fully consumed, the remaining mappings are dropped.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use multi_containers::BTreeMultiMap;
+    /// let mut map = BTreeMultiMap::new();
+    /// map.insert("a", 1);
+    /// map.insert("a", 2);
+    /// map.insert("b", 3);
+    /// assert_eq!(map.drain().collect::<Vec<_>>(), vec![("a", 1), ("a", 2), ("b", 3)]);
+    /// assert_eq!(map.is_empty(), true);
+    /// assert_eq!(map.num_mappings(), 0);
+    /// ```
+    pub fn drain(&mut self) -> impl Iterator<Item = (M::Key, <<M as Map>::Val as Set>::Elem)> + '_
+    where
+        M::Key: Clone,
+    {
+        self.length = 0;
+        self.map
+            .drain()
+            .flat_map(|(k, set)| set.into_iter().map(move |v| (k.clone(), v)))
+    }
+
+    /// Returns an iterator that removes and yields the (key, value) mappings for which the predicate returns `true`.
+    /// The keys are visited in a single pass as the iterator is advanced, and keys that are left without values
+    /// are removed. The entries are moved out of the multi-map and back in as they are visited, so if the iterator
+    /// is dropped early, the mappings that were not yielded yet are kept.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use multi_containers::BTreeMultiMap;
+    /// let mut map = BTreeMultiMap::new();
+    /// map.insert("a", 1);
+    /// map.insert("a", 2);
+    /// map.insert("b", 4);
+    /// map.insert("c", 5);
+    /// let evens = map.extract_if(|_k, v| v % 2 == 0).collect::<Vec<_>>();
+    /// assert_eq!(evens, vec![("a", 2), ("b", 4)]);
+    /// assert_eq!(map.num_keys(), 2);
+    /// assert_eq!(map.num_mappings(), 2);
+    /// ```
+    pub fn extract_if<F>(&mut self, pred: F) -> ExtractIf<'_, M, F>
+    where
+        M: Default,
+        M::Key: Clone,
+        F: FnMut(&M::Key, &<<M as Map>::Val as Set>::Elem) -> bool,
+    {
+        let entries = std::mem::take(&mut self.map).into_iter();
+        ExtractIf {
+            map: &mut self.map,
+            length: &mut self.length,
+            entries,
+            current: None,
+            values: VecDeque::new(),
+            pred,
+        }
     }
 
     /// Returns a reference to the set of values for the given key, if there are any.
@@ -196,6 +613,39 @@ where
         self.map.get(key)
     }
 
+    /// Returns a guard that gives mutable access to the set of values for the given key, if there are any.
+    /// When the guard is dropped, the number of mappings is updated, and the key is removed if its set was left empty.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use multi_containers::HashMultiMap;
+    /// let mut map = HashMultiMap::new();
+    /// assert!(map.get_mut("a").is_none());
+    /// map.insert("a", 1);
+    /// map.insert("a", 2);
+    /// map.insert("a", 3);
+    /// map.get_mut("a").unwrap().retain(|v| v % 2 == 1);
+    /// assert_eq!(map.num_mappings(), 2);
+    /// map.get_mut("a").unwrap().clear();
+    /// assert_eq!(map.contains_key("a"), false);
+    /// assert_eq!(map.num_mappings(), 0);
+    /// ```
+    pub fn get_mut<'a, Q>(&'a mut self, key: &'a Q) -> Option<ValueSetMut<'a, Q, M>>
+    where
+        M: Lookup<Q>,
+        M::Key: Borrow<Q>,
+        Q: ?Sized,
+    {
+        let before = self.map.get(key)?.len();
+        Some(ValueSetMut {
+            map: &mut self.map,
+            key,
+            length: &mut self.length,
+            before,
+        })
+    }
+
     /// Returns an iterator over the keys of the multi-map.
     /// The keys are returned in the order specified by the underlying `Map` implementation.
     ///
@@ -252,10 +702,38 @@ where
     /// map.insert(2, 3);
     /// map.insert(2, 4);
     /// assert_eq!(map.mappings().collect::<Vec<_>>(), vec![(&1, &2), (&1, &3), (&2, &3), (&2, &4)]);
+    /// assert_eq!(map.mappings().len(), 4);
+    /// assert_eq!(map.mappings().rev().collect::<Vec<_>>(), vec![(&2, &4), (&2, &3), (&1, &3), (&1, &2)]);
     /// ```
-    pub fn mappings(&self) -> impl Iterator<Item = (&M::Key, &<<M as Map>::Val as Set>::Elem)> {
-        self.value_sets()
-            .flat_map(|(k, s)| s.iter().map(move |v| (k, v)))
+    pub fn mappings(&self) -> Mappings<'_, M> {
+        Mappings {
+            iter: self.map.iter(),
+            front: None,
+            back: None,
+            length: self.length,
+        }
+    }
+
+    /// Consumes the multi-map, returning an iterator over the keys and their owned value sets.
+    /// The keys are returned in the order specified by the underlying `Map` implementation.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use multi_containers::BTreeMultiMap;
+    /// let mut map = BTreeMultiMap::<u32, u32>::new();
+    /// map.insert(1, 2);
+    /// map.insert(2, 3);
+    /// map.insert(2, 4);
+    /// assert_eq!(
+    ///     map.into_value_sets()
+    ///         .map(|(k, v)| (k, v.into_iter().collect::<Vec<_>>()))
+    ///         .collect::<Vec<_>>(),
+    ///     vec![(1, vec![2]), (2, vec![3, 4])]
+    /// );
+    /// ```
+    pub fn into_value_sets(self) -> <M as IntoIterator>::IntoIter {
+        self.map.into_iter()
     }
 
     /// Returns `true` if the multi-map is empty.
@@ -318,64 +796,885 @@ where
         self.length
     }
 
-    /// Returns an iterator over the keys and value sets in the multi-map within a range of keys.
-    /// The keys are returned in sorted order, as this method is only available for multi-maps with sorted keys.
+    /// Returns the number of keys the multi-map can hold without reallocating.
+    /// For maps without a notion of capacity, this is the number of keys.
     ///
     /// # Example
     ///
     /// ```
-    /// use multi_containers::BTreeMultiMap;
-    /// let mut map = BTreeMultiMap::<u32, u32>::new();
-    /// map.insert(1, 2);
-    /// map.insert(2, 3);
-    /// map.insert(2, 4);
-    /// assert_eq!(map.value_sets_in_range(1..3).map(|(k, _v)| k).collect::<Vec<_>>(), vec![&1, &2]);
+    /// use multi_containers::HashMultiMap;
+    /// let mut map: HashMultiMap<&str, i32> = HashMultiMap::new();
+    /// map.reserve(10);
+    /// assert!(map.capacity() >= 10);
     /// ```
-    pub fn value_sets_in_range<Q, R>(&self, range: R) -> M::RangeIter<'_>
-    where
-        M: SortedMap<Q>,
-        M::Key: Borrow<Q>,
-        Q: ?Sized,
-        R: RangeBounds<Q>,
-    {
-        self.map.range(range)
+    pub fn capacity(&self) -> usize {
+        self.map.capacity()
     }
 
-    /// Returns an iterator over the keys and values in the multi-map within a range of keys.
-    /// The keys are returned in sorted order, as this method is only available for multi-maps with sorted keys.
+    /// Reserves space for at least `additional` more keys.
+    /// This does nothing for maps without a notion of capacity.
     ///
     /// # Example
     ///
     /// ```
-    /// use multi_containers::BTreeMultiMap;
-    /// let mut map = BTreeMultiMap::<u32, u32>::new();
-    /// map.insert(1, 2);
-    /// map.insert(1, 3);
-    /// map.insert(2, 3);
-    /// map.insert(2, 4);
-    /// assert_eq!(map.mappings_in_range(1..3).map(|(k, _v)| k).collect::<Vec<_>>(), vec![&1, &1, &2, &2]);
+    /// use multi_containers::HashMultiMap;
+    /// let mut map = HashMultiMap::new();
+    /// map.insert("a", 1);
+    /// map.reserve(10);
+    /// assert!(map.capacity() >= 11);
     /// ```
-    pub fn mappings_in_range<Q, R>(
-        &self,
-        range: R,
-    ) -> impl Iterator<Item = (&M::Key, &<<M as Map>::Val as Set>::Elem)>
-    where
-        M: SortedMap<Q>,
-        M::Key: Borrow<Q>,
-        Q: ?Sized,
-        R: RangeBounds<Q>,
-    {
-        self.map
-            .range(range)
-            .flat_map(|(k, s)| s.iter().map(move |v| (k, v)))
+    pub fn reserve(&mut self, additional: usize) {
+        self.map.reserve(additional)
     }
-}
 
-impl <T, M> Extend<T> for MultiMap<M>
-where M: Map,
-      M::Val: Set + Default,
-      T: Into<(M::Key, <<M as Map>::Val as Set>::Elem)> {
-    fn extend<I: IntoIterator<Item = T>>(&mut self, iter: I) {
+    /// Shrinks the capacity of the multi-map and of each of its value sets as much as possible.
+    /// This does nothing for maps and sets without a notion of capacity.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use multi_containers::HashMultiMap;
+    /// let mut map = HashMultiMap::with_capacity(100);
+    /// map.insert("a", 1);
+    /// map.shrink_to_fit();
+    /// assert!(map.capacity() < 100);
+    /// ```
+    pub fn shrink_to_fit(&mut self) {
+        self.map.shrink_to_fit();
+        for (_, set) in self.map.iter_mut() {
+            set.shrink_to_fit();
+        }
+    }
+
+    /// Returns the inverse of the multi-map, which maps each value to the keys it is mapped from.
+    /// The inverse may use any backend, which is chosen by its type.
+    /// Keys and values are cloned; use `into_inverted` to move them instead.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use multi_containers::{BTreeMultiMap, HashMultiMap};
+    /// let mut map = HashMultiMap::new();
+    /// map.insert("a", 1);
+    /// map.insert("a", 2);
+    /// map.insert("b", 1);
+    /// let inverted: BTreeMultiMap<_, _> = map.invert();
+    /// assert!(inverted.get(&1).unwrap().iter().eq(&["a", "b"]));
+    /// assert!(inverted.get(&2).unwrap().iter().eq(&["a"]));
+    /// ```
+    pub fn invert<T>(&self) -> MultiMap<T>
+    where
+        M::Key: Clone,
+        <<M as Map>::Val as Set>::Elem: Clone,
+        T: Map<Key = <<M as Map>::Val as Set>::Elem> + Default,
+        T::Val: Set<Elem = M::Key> + Default,
+    {
+        let mut inverted = MultiMap::default();
+        self.invert_into(&mut inverted);
+        inverted
+    }
+
+    /// Inserts the inverse of the multi-map into another multi-map, which maps each value to the keys it is
+    /// mapped from. This accumulates into the existing mappings of `other`.
+    /// Keys and values are cloned; use `move_inverted_into` to move them instead.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use multi_containers::{BTreeMultiMap, HashMultiMap};
+    /// let mut inverted = BTreeMultiMap::new();
+    /// inverted.insert(1, "c");
+    /// let mut map = HashMultiMap::new();
+    /// map.insert("a", 1);
+    /// map.insert("b", 2);
+    /// map.invert_into(&mut inverted);
+    /// assert!(inverted.get(&1).unwrap().iter().eq(&["a", "c"]));
+    /// assert_eq!(inverted.num_mappings(), 3);
+    /// ```
+    pub fn invert_into<T>(&self, other: &mut MultiMap<T>)
+    where
+        M::Key: Clone,
+        <<M as Map>::Val as Set>::Elem: Clone,
+        T: Map<Key = <<M as Map>::Val as Set>::Elem>,
+        T::Val: Set<Elem = M::Key> + Default,
+    {
+        for (key, value) in self.mappings() {
+            other.insert(value.clone(), key.clone());
+        }
+    }
+
+    /// Consumes the multi-map, returning its inverse, which maps each value to the keys it is mapped from.
+    /// The inverse may use any backend, which is chosen by its type.
+    /// Values are moved rather than cloned, and each key is cloned once for every value after its first.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use multi_containers::{BTreeMultiMap, HashMultiMap};
+    /// let mut map = BTreeMultiMap::new();
+    /// map.insert("a", vec![1]);
+    /// map.insert("b", vec![1]);
+    /// map.insert("b", vec![2]);
+    /// let inverted: HashMultiMap<_, _> = map.into_inverted();
+    /// assert_eq!(inverted.get(&vec![1]).unwrap().len(), 2);
+    /// assert_eq!(inverted.num_mappings(), 3);
+    /// ```
+    pub fn into_inverted<T>(self) -> MultiMap<T>
+    where
+        M::Key: Clone,
+        T: Map<Key = <<M as Map>::Val as Set>::Elem> + Default,
+        T::Val: Set<Elem = M::Key> + Default,
+    {
+        let mut inverted = MultiMap::default();
+        self.move_inverted_into(&mut inverted);
+        inverted
+    }
+
+    /// Consumes the multi-map, inserting its inverse into another multi-map, which maps each value to the keys it
+    /// is mapped from. This accumulates into the existing mappings of `other`.
+    /// Values are moved rather than cloned, and each key is cloned once for every value after its first.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use multi_containers::HashMultiMap;
+    /// let mut inverted = HashMultiMap::new();
+    /// let mut map = HashMultiMap::new();
+    /// map.insert("a", 1);
+    /// map.move_inverted_into(&mut inverted);
+    /// let mut map = HashMultiMap::new();
+    /// map.insert("b", 1);
+    /// map.move_inverted_into(&mut inverted);
+    /// assert_eq!(inverted.get(&1).unwrap().len(), 2);
+    /// ```
+    pub fn move_inverted_into<T>(self, other: &mut MultiMap<T>)
+    where
+        M::Key: Clone,
+        T: Map<Key = <<M as Map>::Val as Set>::Elem>,
+        T::Val: Set<Elem = M::Key> + Default,
+    {
+        for (key, set) in self.map {
+            let mut values = set.into_iter();
+            // Value sets are never empty, and the last value takes the key itself instead of a clone.
+            let Some(mut last) = values.next() else {
+                continue;
+            };
+            for value in values {
+                other.insert(std::mem::replace(&mut last, value), key.clone());
+            }
+            other.insert(last, key);
+        }
+    }
+
+    /// Panics if the bookkeeping of the multi-map is inconsistent:
+    /// every value set must be non-empty, and the number of mappings must match the sizes of the value sets.
+    #[cfg(test)]
+    pub(crate) fn check_invariants(&self) {
+        assert!(
+            self.map.values().all(|set| !set.is_empty()),
+            "multi-map contains an empty value set"
+        );
+        assert_eq!(
+            self.length,
+            self.map.values().map(|set| set.len()).sum::<usize>(),
+            "multi-map length does not match its value sets"
+        );
+    }
+
+    /// Returns an iterator over the keys and value sets in the multi-map within a range of keys.
+    /// The keys are returned in sorted order, as this method is only available for multi-maps with sorted keys.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use multi_containers::BTreeMultiMap;
+    /// let mut map = BTreeMultiMap::<u32, u32>::new();
+    /// map.insert(1, 2);
+    /// map.insert(2, 3);
+    /// map.insert(2, 4);
+    /// assert_eq!(map.value_sets_in_range(1..3).map(|(k, _v)| k).collect::<Vec<_>>(), vec![&1, &2]);
+    /// ```
+    pub fn value_sets_in_range<Q, R>(&self, range: R) -> M::RangeIter<'_>
+    where
+        M: SortedMap<Q>,
+        M::Key: Borrow<Q>,
+        Q: ?Sized,
+        R: RangeBounds<Q>,
+    {
+        self.map.range(range)
+    }
+
+    /// Returns an iterator over the keys and values in the multi-map within a range of keys.
+    /// The keys are returned in sorted order, as this method is only available for multi-maps with sorted keys.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use multi_containers::BTreeMultiMap;
+    /// let mut map = BTreeMultiMap::<u32, u32>::new();
+    /// map.insert(1, 2);
+    /// map.insert(1, 3);
+    /// map.insert(2, 3);
+    /// map.insert(2, 4);
+    /// assert_eq!(map.mappings_in_range(1..3).map(|(k, _v)| k).collect::<Vec<_>>(), vec![&1, &1, &2, &2]);
+    /// ```
+    pub fn mappings_in_range<Q, R>(
+        &self,
+        range: R,
+    ) -> impl Iterator<Item = (&M::Key, &<<M as Map>::Val as Set>::Elem)>
+    where
+        M: SortedMap<Q>,
+        M::Key: Borrow<Q>,
+        Q: ?Sized,
+        R: RangeBounds<Q>,
+    {
+        self.map
+            .range(range)
+            .flat_map(|(k, s)| s.iter().map(move |v| (k, v)))
+    }
+}
+
+// The type of iterator over the values for a single key.
+type ValueIter<'a, M> = <<M as Map>::Val as Set>::Iter<'a>;
+
+/// An iterator over the (key, value) mappings of a multi-map.
+/// This is constructed by `MultiMap::mappings`.
+/// It knows its exact length, and can be iterated from the back if the underlying map and sets can.
+pub struct Mappings<'a, M>
+where
+    M: Map + 'a,
+    M::Val: Set,
+{
+    iter: M::Iter<'a>,
+    front: Option<(&'a M::Key, ValueIter<'a, M>)>,
+    back: Option<(&'a M::Key, ValueIter<'a, M>)>,
+    length: usize,
+}
+
+impl<'a, M> Iterator for Mappings<'a, M>
+where
+    M: Map + 'a,
+    M::Val: Set,
+{
+    type Item = (&'a M::Key, &'a <<M as Map>::Val as Set>::Elem);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some((key, values)) = &mut self.front {
+                if let Some(value) = values.next() {
+                    self.length -= 1;
+                    return Some((*key, value));
+                }
+            }
+            match self.iter.next() {
+                Some((key, set)) => self.front = Some((key, set.iter())),
+                None => {
+                    // The rest of the mappings may have been taken by the back.
+                    let (key, values) = self.back.as_mut()?;
+                    let value = values.next()?;
+                    self.length -= 1;
+                    return Some((*key, value));
+                }
+            }
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.length, Some(self.length))
+    }
+}
+
+impl<'a, M> DoubleEndedIterator for Mappings<'a, M>
+where
+    M: Map + 'a,
+    M::Val: Set,
+    M::Iter<'a>: DoubleEndedIterator,
+    ValueIter<'a, M>: DoubleEndedIterator,
+{
+    fn next_back(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some((key, values)) = &mut self.back {
+                if let Some(value) = values.next_back() {
+                    self.length -= 1;
+                    return Some((*key, value));
+                }
+            }
+            match self.iter.next_back() {
+                Some((key, set)) => self.back = Some((key, set.iter())),
+                None => {
+                    // The rest of the mappings may have been taken by the front.
+                    let (key, values) = self.front.as_mut()?;
+                    let value = values.next_back()?;
+                    self.length -= 1;
+                    return Some((*key, value));
+                }
+            }
+        }
+    }
+}
+
+impl<'a, M> ExactSizeIterator for Mappings<'a, M>
+where
+    M: Map + 'a,
+    M::Val: Set,
+{
+}
+
+impl<'a, M> FusedIterator for Mappings<'a, M>
+where
+    M: Map + 'a,
+    M::Val: Set,
+    M::Iter<'a>: FusedIterator,
+    ValueIter<'a, M>: FusedIterator,
+{
+}
+
+/// An owning iterator over the (key, value) mappings of a multi-map.
+/// The key is cloned for each of its values except the last one.
+/// This is constructed by `MultiMap::into_iter`.
+pub struct IntoIter<M>
+where
+    M: Map,
+    M::Val: Set,
+{
+    iter: <M as IntoIterator>::IntoIter,
+    key: Option<M::Key>,
+    values: Option<Peekable<<<M as Map>::Val as IntoIterator>::IntoIter>>,
+    length: usize,
+}
+
+impl<M> Iterator for IntoIter<M>
+where
+    M: Map,
+    M::Key: Clone,
+    M::Val: Set,
+{
+    type Item = (M::Key, <<M as Map>::Val as Set>::Elem);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(values) = &mut self.values {
+                if let Some(value) = values.next() {
+                    self.length -= 1;
+                    let key = if values.peek().is_some() {
+                        self.key.clone()
+                    } else {
+                        self.key.take()
+                    };
+                    return Some((key.expect("key is set along with its values"), value));
+                }
+            }
+            let (key, set) = self.iter.next()?;
+            self.key = Some(key);
+            self.values = Some(set.into_iter().peekable());
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.length, Some(self.length))
+    }
+}
+
+/// An iterator that removes and yields the (key, value) mappings of a multi-map that match a predicate.
+/// This is constructed by `MultiMap::extract_if`.
+/// When the iterator is dropped, the mappings that were not yielded are put back into the multi-map.
+pub struct ExtractIf<'a, M, F>
+where
+    M: Map,
+    M::Val: Set,
+{
+    // The multi-map's own map, which the visited entries are moved back into.
+    map: &'a mut M,
+    length: &'a mut usize,
+    // The entries that were not visited yet.
+    entries: <M as IntoIterator>::IntoIter,
+    // The key being visited, with the values left in its set, while it has removed values waiting to be yielded.
+    current: Option<(M::Key, M::Val)>,
+    values: VecDeque<<<M as Map>::Val as Set>::Elem>,
+    pred: F,
+}
+
+impl<'a, M, F> Iterator for ExtractIf<'a, M, F>
+where
+    M: Map,
+    M::Key: Clone,
+    M::Val: Set,
+    F: FnMut(&M::Key, &<<M as Map>::Val as Set>::Elem) -> bool,
+{
+    type Item = (M::Key, <<M as Map>::Val as Set>::Elem);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(value) = self.values.pop_front() {
+                *self.length -= 1;
+                if !self.values.is_empty() {
+                    let (key, _) = self
+                        .current
+                        .as_ref()
+                        .expect("removed values are kept along with their key");
+                    return Some((key.clone(), value));
+                }
+                let (key, set) = self
+                    .current
+                    .take()
+                    .expect("removed values are kept along with their key");
+                if !set.is_empty() {
+                    self.map.insert(key.clone(), set);
+                }
+                return Some((key, value));
+            }
+            let (key, mut set) = self.entries.next()?;
+            let pred = &mut self.pred;
+            self.values.extend(set.extract_if(|v| pred(&key, v)));
+            if self.values.is_empty() {
+                self.map.insert(key, set);
+            } else {
+                self.current = Some((key, set));
+            }
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.values.len(), Some(*self.length))
+    }
+}
+
+impl<'a, M, F> Drop for ExtractIf<'a, M, F>
+where
+    M: Map,
+    M::Val: Set,
+{
+    fn drop(&mut self) {
+        if let Some((key, mut set)) = self.current.take() {
+            for value in self.values.drain(..) {
+                set.insert(value);
+            }
+            self.map.insert(key, set);
+        }
+        for (key, set) in &mut self.entries {
+            self.map.insert(key, set);
+        }
+    }
+}
+
+/// A guard that gives mutable access to the set of values for a key of a multi-map.
+/// This is constructed by `MultiMap::get_mut`.
+/// When the guard is dropped, the number of mappings is updated, and the key is removed if its set was left empty.
+pub struct ValueSetMut<'a, Q, M>
+where
+    Q: ?Sized,
+    M: Lookup<Q>,
+    M::Key: Borrow<Q>,
+    M::Val: Set,
+{
+    map: &'a mut M,
+    key: &'a Q,
+    length: &'a mut usize,
+    before: usize,
+}
+
+impl<Q, M> Deref for ValueSetMut<'_, Q, M>
+where
+    Q: ?Sized,
+    M: Lookup<Q>,
+    M::Key: Borrow<Q>,
+    M::Val: Set,
+{
+    type Target = M::Val;
+
+    fn deref(&self) -> &M::Val {
+        self.map
+            .get(self.key)
+            .expect("key is present until the guard is dropped")
+    }
+}
+
+impl<Q, M> DerefMut for ValueSetMut<'_, Q, M>
+where
+    Q: ?Sized,
+    M: Lookup<Q>,
+    M::Key: Borrow<Q>,
+    M::Val: Set,
+{
+    fn deref_mut(&mut self) -> &mut M::Val {
+        self.map
+            .get_mut(self.key)
+            .expect("key is present until the guard is dropped")
+    }
+}
+
+impl<Q, M> Drop for ValueSetMut<'_, Q, M>
+where
+    Q: ?Sized,
+    M: Lookup<Q>,
+    M::Key: Borrow<Q>,
+    M::Val: Set,
+{
+    fn drop(&mut self) {
+        let after = self.len();
+        *self.length = *self.length - self.before + after;
+        if after == 0 {
+            self.map.remove(self.key);
+        }
+    }
+}
+
+/// A view into the values for a single key of a multi-map, which may either be vacant or occupied.
+/// This is constructed by `MultiMap::entry`.
+pub enum Entry<'a, M>
+where
+    M: Map + 'a,
+    M::Val: Set,
+{
+    /// An entry for a key that has at least one value.
+    Occupied(OccupiedEntry<'a, M>),
+
+    /// An entry for a key that has no values.
+    Vacant(VacantEntry<'a, M>),
+}
+
+impl<'a, M> Entry<'a, M>
+where
+    M: Map + 'a,
+    M::Val: Set,
+{
+    /// Returns a reference to the key of this entry.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use multi_containers::HashMultiMap;
+    /// let mut map = HashMultiMap::<&str, i32>::new();
+    /// assert_eq!(map.entry("a").key(), &"a");
+    /// ```
+    pub fn key(&self) -> &M::Key {
+        match self {
+            Entry::Occupied(entry) => entry.key(),
+            Entry::Vacant(entry) => entry.key(),
+        }
+    }
+
+    /// Inserts the given values if the entry is vacant. Returns an occupied entry for the key.
+    /// If the entry is vacant and no values are given, the key is not kept once the returned entry is dropped.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use multi_containers::HashMultiMap;
+    /// let mut map = HashMultiMap::new();
+    /// map.insert("a", 1);
+    /// assert_eq!(map.entry("a").or_insert_values([2, 3]).values().len(), 1);
+    /// assert_eq!(map.entry("b").or_insert_values([2, 3]).values().len(), 2);
+    /// assert_eq!(map.num_mappings(), 3);
+    /// ```
+    pub fn or_insert_values<I>(self, values: I) -> OccupiedEntry<'a, M>
+    where
+        M::Val: Default,
+        I: IntoIterator<Item = <<M as Map>::Val as Set>::Elem>,
+    {
+        match self {
+            Entry::Occupied(entry) => entry,
+            Entry::Vacant(entry) => entry.insert_values(values),
+        }
+    }
+
+    /// Returns an occupied entry for the key, creating an empty value set if the entry is vacant.
+    /// If no values are inserted through the returned entry, the key is not kept once it is dropped.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use multi_containers::HashMultiMap;
+    /// let mut map = HashMultiMap::new();
+    /// let mut entry = map.entry("a").or_default();
+    /// entry.insert_value(1);
+    /// entry.insert_value(2);
+    /// drop(entry);
+    /// map.entry("b").or_default();
+    /// assert_eq!(map.num_keys(), 1);
+    /// assert_eq!(map.num_mappings(), 2);
+    /// ```
+    pub fn or_default(self) -> OccupiedEntry<'a, M>
+    where
+        M::Val: Default,
+    {
+        self.or_insert_values(std::iter::empty())
+    }
+}
+
+/// A view into the values for a key that is present in a multi-map.
+/// If the value set is left empty when the entry is dropped, the key is removed from the multi-map.
+pub struct OccupiedEntry<'a, M>
+where
+    M: Map + 'a,
+    M::Val: Set,
+{
+    // This is only `None` once the entry has been removed from the map.
+    entry: Option<M::Occupied<'a>>,
+    length: &'a mut usize,
+}
+
+impl<'a, M> OccupiedEntry<'a, M>
+where
+    M: Map + 'a,
+    M::Val: Set,
+{
+    fn inner(&self) -> &M::Occupied<'a> {
+        self.entry.as_ref().expect("entry is present until dropped")
+    }
+
+    fn inner_mut(&mut self) -> &mut M::Occupied<'a> {
+        self.entry.as_mut().expect("entry is present until dropped")
+    }
+
+    /// Returns a reference to the key of this entry.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use multi_containers::HashMultiMap;
+    /// let mut map = HashMultiMap::new();
+    /// assert_eq!(map.entry("a").or_insert_values([1]).key(), &"a");
+    /// ```
+    pub fn key(&self) -> &M::Key {
+        self.inner().key()
+    }
+
+    /// Returns a reference to the set of values for this entry.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use multi_containers::HashMultiMap;
+    /// let mut map = HashMultiMap::new();
+    /// map.insert("a", 1);
+    /// assert_eq!(map.entry("a").or_default().values().contains(&1), true);
+    /// ```
+    pub fn values(&self) -> &M::Val {
+        self.inner().get()
+    }
+
+    /// Inserts a value for this entry's key.
+    /// Returns `true` if it was not already present.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use multi_containers::HashMultiMap;
+    /// let mut map = HashMultiMap::new();
+    /// let mut entry = map.entry("a").or_default();
+    /// assert_eq!(entry.insert_value(1), true);
+    /// assert_eq!(entry.insert_value(1), false);
+    /// drop(entry);
+    /// assert_eq!(map.num_mappings(), 1);
+    /// ```
+    pub fn insert_value(&mut self, value: <<M as Map>::Val as Set>::Elem) -> bool {
+        if self.inner_mut().get_mut().insert(value) {
+            *self.length += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Removes a value for this entry's key.
+    /// Returns `true` if it was present.
+    /// If the last value is removed, the key is removed from the multi-map when the entry is dropped.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use multi_containers::HashMultiMap;
+    /// let mut map = HashMultiMap::new();
+    /// let mut entry = map.entry("a").or_insert_values([1]);
+    /// assert_eq!(entry.remove_value(&1), true);
+    /// assert_eq!(entry.remove_value(&1), false);
+    /// drop(entry);
+    /// assert_eq!(map.contains_key("a"), false);
+    /// ```
+    pub fn remove_value<R>(&mut self, value: &R) -> bool
+    where
+        M::Val: Container<R>,
+        <<M as Map>::Val as Set>::Elem: Borrow<R>,
+        R: ?Sized,
+    {
+        if self.inner_mut().get_mut().remove(value) {
+            *self.length -= 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Removes the key and all of its values from the multi-map.
+    /// Returns the key and the set of values that were removed.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use multi_containers::HashMultiMap;
+    /// let mut map = HashMultiMap::new();
+    /// let (key, values) = map.entry("a").or_insert_values([1, 2]).remove_entry();
+    /// assert_eq!(key, "a");
+    /// assert_eq!(values.len(), 2);
+    /// assert_eq!(map.num_mappings(), 0);
+    /// ```
+    pub fn remove_entry(mut self) -> (M::Key, M::Val) {
+        let (key, values) = self
+            .entry
+            .take()
+            .expect("entry is present until dropped")
+            .remove_entry();
+        *self.length -= values.len();
+        (key, values)
+    }
+}
+
+impl<'a, M> Drop for OccupiedEntry<'a, M>
+where
+    M: Map + 'a,
+    M::Val: Set,
+{
+    fn drop(&mut self) {
+        if let Some(entry) = self.entry.take() {
+            if entry.get().is_empty() {
+                entry.remove_entry();
+            }
+        }
+    }
+}
+
+/// A view into the values for a key that is not present in a multi-map.
+pub struct VacantEntry<'a, M>
+where
+    M: Map + 'a,
+    M::Val: Set,
+{
+    entry: M::Vacant<'a>,
+    length: &'a mut usize,
+    value_capacity: usize,
+}
+
+impl<'a, M> VacantEntry<'a, M>
+where
+    M: Map + 'a,
+    M::Val: Set,
+{
+    /// Returns a reference to the key of this entry.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use multi_containers::HashMultiMap;
+    /// use multi_containers::multimap::Entry;
+    /// let mut map = HashMultiMap::<&str, i32>::new();
+    /// if let Entry::Vacant(entry) = map.entry("a") {
+    ///     assert_eq!(entry.key(), &"a");
+    /// }
+    /// assert_eq!(map.is_empty(), true);
+    /// ```
+    pub fn key(&self) -> &M::Key {
+        self.entry.key()
+    }
+
+    /// Takes ownership of the key, leaving the multi-map unchanged.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use multi_containers::HashMultiMap;
+    /// use multi_containers::multimap::Entry;
+    /// let mut map = HashMultiMap::<&str, i32>::new();
+    /// if let Entry::Vacant(entry) = map.entry("a") {
+    ///     assert_eq!(entry.into_key(), "a");
+    /// }
+    /// assert_eq!(map.is_empty(), true);
+    /// ```
+    pub fn into_key(self) -> M::Key {
+        self.entry.into_key()
+    }
+
+    /// Inserts a value for this entry's key. Returns an occupied entry for the key.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use multi_containers::HashMultiMap;
+    /// use multi_containers::multimap::Entry;
+    /// let mut map = HashMultiMap::new();
+    /// if let Entry::Vacant(entry) = map.entry("a") {
+    ///     entry.insert_value(1).insert_value(2);
+    /// }
+    /// assert_eq!(map.num_mappings(), 2);
+    /// ```
+    pub fn insert_value(self, value: <<M as Map>::Val as Set>::Elem) -> OccupiedEntry<'a, M>
+    where
+        M::Val: Default,
+    {
+        self.insert_values(std::iter::once(value))
+    }
+
+    /// Inserts several values for this entry's key. Returns an occupied entry for the key.
+    /// If no values are given, the key is not kept once the returned entry is dropped.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use multi_containers::HashMultiMap;
+    /// use multi_containers::multimap::Entry;
+    /// let mut map = HashMultiMap::new();
+    /// if let Entry::Vacant(entry) = map.entry("a") {
+    ///     assert_eq!(entry.insert_values([1, 2, 2]).values().len(), 2);
+    /// }
+    /// assert_eq!(map.num_mappings(), 2);
+    /// ```
+    pub fn insert_values<I>(self, values: I) -> OccupiedEntry<'a, M>
+    where
+        M::Val: Default,
+        I: IntoIterator<Item = <<M as Map>::Val as Set>::Elem>,
+    {
+        let mut set = M::Val::with_capacity(self.value_capacity);
+        for value in values {
+            set.insert(value);
+        }
+        *self.length += set.len();
+        OccupiedEntry {
+            entry: Some(self.entry.insert_entry(set)),
+            length: self.length,
+        }
+    }
+}
+
+// The value capacity is only a hint, so it is left out of the debug output.
+impl<M> Debug for MultiMap<M>
+where
+    M: Debug,
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("MultiMap")
+            .field("map", &self.map)
+            .field("length", &self.length)
+            .finish()
+    }
+}
+
+// The value capacity is only a hint, so it does not take part in equality.
+impl<M> PartialEq for MultiMap<M>
+where
+    M: PartialEq,
+{
+    fn eq(&self, other: &Self) -> bool {
+        self.map == other.map
+    }
+}
+
+impl<M> Eq for MultiMap<M> where M: Eq {}
+
+impl <T, M> Extend<T> for MultiMap<M>
+where M: Map,
+      M::Val: Set + Default,
+      T: Into<(M::Key, <<M as Map>::Val as Set>::Elem)> {
+    fn extend<I: IntoIterator<Item = T>>(&mut self, iter: I) {
         for t in iter {
             let (k, v) = t.into();
             self.insert(k, v);
@@ -383,26 +1682,75 @@ where M: Map,
     }
 }
 
-impl<T, M> FromIterator<T> for MultiMap<M>
+impl<T, M> FromIterator<T> for MultiMap<M>
+where
+    M: DefaultMap,
+    M::Val: Set + Default,
+    T: Into<(M::Key, <<M as Map>::Val as Set>::Elem)>,
+{
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        let mut map = MultiMap::default();
+        map.extend(iter);
+        map
+    }
+}
+
+impl<M, const N: usize> From<[(M::Key, <<M as Map>::Val as Set>::Elem); N]> for MultiMap<M>
+where
+    M: DefaultMap,
+    M::Val: Set + Default,
+{
+    fn from(arr: [(M::Key, <<M as Map>::Val as Set>::Elem); N]) -> Self {
+        arr.into_iter().collect()
+    }
+}
+
+impl<M> ExactSizeIterator for IntoIter<M>
+where
+    M: Map,
+    M::Key: Clone,
+    M::Val: Set,
+{
+}
+
+impl<M> FusedIterator for IntoIter<M>
 where
-    M: Map + Default,
-    M::Val: Set + Default,
-    T: Into<(M::Key, <<M as Map>::Val as Set>::Elem)>,
+    M: Map,
+    M::Key: Clone,
+    M::Val: Set,
+    <M as IntoIterator>::IntoIter: FusedIterator,
 {
-    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
-        let mut map = MultiMap::new();
-        map.extend(iter);
-        map
+}
+
+impl<M> IntoIterator for MultiMap<M>
+where
+    M: Map,
+    M::Key: Clone,
+    M::Val: Set,
+{
+    type Item = (M::Key, <<M as Map>::Val as Set>::Elem);
+    type IntoIter = IntoIter<M>;
+
+    fn into_iter(self) -> IntoIter<M> {
+        IntoIter {
+            iter: self.map.into_iter(),
+            key: None,
+            values: None,
+            length: self.length,
+        }
     }
 }
 
-impl<M, const N: usize> From<[(M::Key, <<M as Map>::Val as Set>::Elem); N]> for MultiMap<M>
+impl<'a, M> IntoIterator for &'a MultiMap<M>
 where
-    M: Map + Default,
-    M::Val: Set + Default,
+    M: Map,
+    M::Val: Set,
 {
-    fn from(arr: [(M::Key, <<M as Map>::Val as Set>::Elem); N]) -> Self {
-        arr.into_iter().collect()
+    type Item = (&'a M::Key, &'a <<M as Map>::Val as Set>::Elem);
+    type IntoIter = Mappings<'a, M>;
+
+    fn into_iter(self) -> Mappings<'a, M> {
+        self.mappings()
     }
 }
 
@@ -411,6 +1759,7 @@ mod tests {
     macro_rules! base_test_suite {
         ($mod_name:ident, $map_maker:expr) => {
             mod $mod_name {
+                use crate::multimap::Entry;
                 use crate::test_utils::unordered_elements_are;
                 use crate::MultiMapBuilder;
 
@@ -418,20 +1767,51 @@ mod tests {
                 fn test_insert() {
                     let mut map = $map_maker;
                     assert_eq!(map.insert(1, 2), true);
+                    map.check_invariants();
                     assert_eq!(map.insert(1, 2), false);
+                    map.check_invariants();
                     assert_eq!(map.insert(1, 3), true);
+                    map.check_invariants();
                     assert_eq!(map.insert(2, 3), true);
+                    map.check_invariants();
                     assert_eq!(map.insert(2, 3), false);
+                    map.check_invariants();
+                }
+
+                #[test]
+                fn test_invert() {
+                    let mut map = $map_maker;
+                    map.extend([(1, 2), (1, 3), (2, 3), (4, 5)]);
+                    let inverted: crate::BTreeMultiMap<_, _> = map.invert();
+                    inverted.check_invariants();
+                    assert!(inverted
+                        .mappings()
+                        .eq([(&2, &1), (&3, &1), (&3, &2), (&5, &4)]));
+                    let mut accumulated: crate::HashMultiMap<_, _> = crate::HashMultiMap::new();
+                    accumulated.insert(3, 7);
+                    map.invert_into(&mut accumulated);
+                    accumulated.check_invariants();
+                    assert_eq!(accumulated.num_mappings(), 5);
+                    assert_eq!(accumulated.get(&3).unwrap().len(), 3);
+                    let mut moved: crate::HashMultiMap<_, _> = crate::HashMultiMap::new();
+                    moved.insert(3, 7);
+                    map.clone().move_inverted_into(&mut moved);
+                    assert_eq!(moved, accumulated);
+                    let inverted_again: crate::BTreeMultiMap<_, _> = map.into_inverted();
+                    inverted_again.check_invariants();
+                    assert_eq!(inverted_again, inverted);
                 }
 
                 #[test]
                 fn test_contains() {
                     let mut map = $map_maker;
                     assert_eq!(map.insert(1, 2), true);
+                    map.check_invariants();
                     assert_eq!(map.contains(&1, &2), true);
                     assert_eq!(map.contains(&1, &3), false);
                     assert_eq!(map.contains(&2, &2), false);
                     assert_eq!(map.insert(1, 3), true);
+                    map.check_invariants();
                     assert_eq!(map.contains(&1, &3), true);
                 }
 
@@ -439,9 +1819,11 @@ mod tests {
                 fn test_contains_key() {
                     let mut map = $map_maker;
                     assert_eq!(map.insert(1, 2), true);
+                    map.check_invariants();
                     assert_eq!(map.contains_key(&1), true);
                     assert_eq!(map.contains_key(&2), false);
                     assert_eq!(map.insert(2, 3), true);
+                    map.check_invariants();
                     assert_eq!(map.contains_key(&2), true);
                 }
 
@@ -449,40 +1831,326 @@ mod tests {
                 fn test_remove() {
                     let mut map = $map_maker;
                     assert_eq!(map.insert(1, 2), true);
+                    map.check_invariants();
                     assert_eq!(map.remove(&1, &2), true);
+                    map.check_invariants();
                     assert_eq!(map.remove(&1, &2), false);
+                    map.check_invariants();
                     assert_eq!(map.insert(1, 2), true);
+                    map.check_invariants();
                     assert_eq!(map.insert(1, 3), true);
+                    map.check_invariants();
                     assert_eq!(map.remove(&1, &2), true);
+                    map.check_invariants();
                     assert_eq!(map.remove(&1, &3), true);
+                    map.check_invariants();
                     assert_eq!(map.remove(&1, &2), false);
+                    map.check_invariants();
                 }
 
                 #[test]
                 fn test_remove_key() {
                     let mut map = $map_maker;
                     assert_eq!(map.insert(1, 2), true);
+                    map.check_invariants();
                     assert_eq!(map.remove_key(&1).unwrap().contains(&2), true);
+                    map.check_invariants();
                     assert_eq!(map.remove_key(&1), None);
+                    map.check_invariants();
+                }
+
+                #[test]
+                fn test_remove_key_num_mappings() {
+                    let mut map = $map_maker;
+                    assert_eq!(map.insert(1, 2), true);
+                    map.check_invariants();
+                    assert_eq!(map.insert(1, 3), true);
+                    map.check_invariants();
+                    assert_eq!(map.insert(2, 3), true);
+                    map.check_invariants();
+                    assert_eq!(map.remove_key(&1).unwrap().len(), 2);
+                    map.check_invariants();
+                    assert_eq!(map.num_mappings(), 1);
+                    assert_eq!(map.remove_key(&2).unwrap().len(), 1);
+                    map.check_invariants();
+                    assert_eq!(map.num_mappings(), 0);
+                }
+
+                #[test]
+                fn test_reserve_and_shrink_to_fit() {
+                    let mut map = $map_maker;
+                    map.reserve(100);
+                    assert!(map.capacity() >= map.num_keys());
+                    assert_eq!(map.insert(1, 2), true);
+                    map.check_invariants();
+                    assert_eq!(map.insert(1, 3), true);
+                    map.check_invariants();
+                    assert_eq!(map.insert(2, 3), true);
+                    map.check_invariants();
+                    map.shrink_to_fit();
+                    map.check_invariants();
+                    assert!(map.capacity() >= map.num_keys());
+                    assert_eq!(map.num_mappings(), 3);
+                    assert_eq!(map.get(&1).unwrap().len(), 2);
+                }
+
+                #[test]
+                fn test_entry() {
+                    let mut map = $map_maker;
+                    assert_eq!(map.insert(1, 2), true);
+                    map.check_invariants();
+                    match map.entry(1) {
+                        Entry::Occupied(mut entry) => {
+                            assert_eq!(entry.key(), &1);
+                            assert_eq!(entry.insert_value(3), true);
+                            assert_eq!(entry.insert_value(3), false);
+                            assert_eq!(entry.remove_value(&2), true);
+                            assert_eq!(entry.remove_value(&2), false);
+                        }
+                        Entry::Vacant(_) => panic!("expected an occupied entry"),
+                    }
+                    map.check_invariants();
+                    assert_eq!(map.num_mappings(), 1);
+                    match map.entry(2) {
+                        Entry::Occupied(_) => panic!("expected a vacant entry"),
+                        Entry::Vacant(entry) => {
+                            assert_eq!(entry.insert_values([4, 5]).values().len(), 2);
+                        }
+                    }
+                    map.check_invariants();
+                    assert_eq!(map.num_keys(), 2);
+                    assert_eq!(map.num_mappings(), 3);
+                }
+
+                #[test]
+                fn test_entry_removes_empty_keys() {
+                    let mut map = $map_maker;
+                    assert_eq!(map.insert(1, 2), true);
+                    map.check_invariants();
+                    let mut entry = map.entry(1).or_default();
+                    assert_eq!(entry.remove_value(&2), true);
+                    drop(entry);
+                    map.check_invariants();
+                    assert_eq!(map.contains_key(&1), false);
+                    map.entry(2).or_insert_values([]);
+                    map.check_invariants();
+                    assert_eq!(map.contains_key(&2), false);
+                    assert_eq!(map.is_empty(), true);
+                    assert_eq!(map.num_mappings(), 0);
+                }
+
+                #[test]
+                fn test_entry_remove_entry() {
+                    let mut map = $map_maker;
+                    assert_eq!(map.insert(1, 2), true);
+                    map.check_invariants();
+                    assert_eq!(map.insert(1, 3), true);
+                    map.check_invariants();
+                    assert_eq!(map.insert(2, 3), true);
+                    map.check_invariants();
+                    let (key, values) = map.entry(1).or_default().remove_entry();
+                    map.check_invariants();
+                    assert_eq!(key, 1);
+                    assert_eq!(values.len(), 2);
+                    assert_eq!(map.num_keys(), 1);
+                    assert_eq!(map.num_mappings(), 1);
+                }
+
+                #[test]
+                fn test_entry_or_insert_values() {
+                    let mut map = $map_maker;
+                    assert_eq!(map.insert(1, 2), true);
+                    map.check_invariants();
+                    assert_eq!(map.entry(1).or_insert_values([3, 4]).values().len(), 1);
+                    map.check_invariants();
+                    assert_eq!(map.entry(2).or_insert_values([3, 4]).values().len(), 2);
+                    map.check_invariants();
+                    assert_eq!(map.num_mappings(), 3);
+                }
+
+                #[test]
+                fn test_retain() {
+                    let mut map = $map_maker;
+                    assert_eq!(map.insert(1, 2), true);
+                    map.check_invariants();
+                    assert_eq!(map.insert(1, 3), true);
+                    map.check_invariants();
+                    assert_eq!(map.insert(2, 4), true);
+                    map.check_invariants();
+                    map.retain(|k, v| k + v != 3 && *v != 4);
+                    map.check_invariants();
+                    assert!(unordered_elements_are(
+                        map.mappings().map(|(k, v)| (k.clone(), v.clone())),
+                        vec![(1, 3)]
+                    ));
+                    assert_eq!(map.num_keys(), 1);
+                }
+
+                #[test]
+                fn test_retain_keys() {
+                    let mut map = $map_maker;
+                    assert_eq!(map.insert(1, 2), true);
+                    map.check_invariants();
+                    assert_eq!(map.insert(1, 3), true);
+                    map.check_invariants();
+                    assert_eq!(map.insert(2, 4), true);
+                    map.check_invariants();
+                    map.retain_keys(|k, _set| *k == 2);
+                    map.check_invariants();
+                    assert_eq!(map.num_keys(), 1);
+                    assert_eq!(map.num_mappings(), 1);
+                    map.retain_keys(|_k, set| set.len() > 1);
+                    map.check_invariants();
+                    assert_eq!(map.is_empty(), true);
+                }
+
+                #[test]
+                fn test_drain() {
+                    let mut map = $map_maker;
+                    assert_eq!(map.insert(1, 2), true);
+                    map.check_invariants();
+                    assert_eq!(map.insert(1, 3), true);
+                    map.check_invariants();
+                    assert_eq!(map.insert(2, 4), true);
+                    map.check_invariants();
+                    assert!(unordered_elements_are(
+                        map.drain(),
+                        vec![(1, 2), (1, 3), (2, 4)]
+                    ));
+                    map.check_invariants();
+                    assert_eq!(map.is_empty(), true);
+                    assert_eq!(map.num_mappings(), 0);
+                }
+
+                #[test]
+                fn test_extract_if() {
+                    let mut map = $map_maker;
+                    assert_eq!(map.insert(1, 2), true);
+                    map.check_invariants();
+                    assert_eq!(map.insert(1, 3), true);
+                    map.check_invariants();
+                    assert_eq!(map.insert(2, 4), true);
+                    map.check_invariants();
+                    assert_eq!(map.insert(3, 5), true);
+                    map.check_invariants();
+                    assert!(unordered_elements_are(
+                        map.extract_if(|_k, v| v % 2 == 0),
+                        vec![(1, 2), (2, 4)]
+                    ));
+                    map.check_invariants();
+                    assert_eq!(map.num_keys(), 2);
+                    assert_eq!(map.num_mappings(), 2);
+                }
+
+                #[test]
+                fn test_extract_if_without_emptying_keys() {
+                    let mut map = $map_maker;
+                    for k in 0..20 {
+                        assert_eq!(map.insert(k, 2 * k), true);
+                        assert_eq!(map.insert(k, 2 * k + 1), true);
+                    }
+                    map.check_invariants();
+                    assert!(unordered_elements_are(
+                        map.extract_if(|_k, v| v % 2 == 1),
+                        (0..20).map(|k| (k, 2 * k + 1)).collect::<Vec<_>>()
+                    ));
+                    map.check_invariants();
+                    assert_eq!(map.num_keys(), 20);
+                    assert_eq!(map.num_mappings(), 20);
+                }
+
+                #[test]
+                fn test_extract_if_dropped_early() {
+                    let mut map = $map_maker;
+                    assert_eq!(map.insert(1, 2), true);
+                    map.check_invariants();
+                    assert_eq!(map.insert(2, 4), true);
+                    map.check_invariants();
+                    assert_eq!(map.extract_if(|_k, _v| true).take(1).count(), 1);
+                    map.check_invariants();
+                    assert_eq!(map.num_mappings(), 1);
+                }
+
+                #[test]
+                fn test_extract_if_dropped_early_keeps_unyielded_values() {
+                    let mut map = $map_maker;
+                    for v in [2, 4, 6] {
+                        assert_eq!(map.insert(1, v), true);
+                        map.check_invariants();
+                    }
+                    assert_eq!(map.insert(2, 8), true);
+                    map.check_invariants();
+                    let taken = map
+                        .extract_if(|_k, v| v % 2 == 0)
+                        .take(1)
+                        .collect::<Vec<_>>();
+                    assert_eq!(taken.len(), 1);
+                    map.check_invariants();
+                    assert_eq!(map.num_mappings(), 3);
+                    assert_eq!(map.num_keys(), if taken[0].0 == 2 { 1 } else { 2 });
+                    assert_eq!(map.contains(&taken[0].0, &taken[0].1), false);
                 }
 
                 #[test]
                 fn test_get() {
                     let mut map = $map_maker;
                     assert_eq!(map.insert(1, 2), true);
+                    map.check_invariants();
                     assert_eq!(map.get(&1).unwrap().contains(&2), true);
                     assert_eq!(map.get(&1).unwrap().contains(&3), false);
                     assert_eq!(map.get(&2), None);
                     assert_eq!(map.insert(1, 3), true);
+                    map.check_invariants();
                     assert_eq!(map.get(&1).unwrap().contains(&3), true);
                 }
 
+                #[test]
+                fn test_get_mut() {
+                    let mut map = $map_maker;
+                    assert!(map.get_mut(&1).is_none());
+                    map.check_invariants();
+                    assert_eq!(map.insert(1, 2), true);
+                    map.check_invariants();
+                    assert_eq!(map.insert(1, 3), true);
+                    map.check_invariants();
+                    assert_eq!(map.insert(2, 3), true);
+                    map.check_invariants();
+                    {
+                        let mut set = map.get_mut(&1).unwrap();
+                        assert_eq!(set.len(), 2);
+                        set.insert(4);
+                        set.insert(5);
+                        set.retain(|v| *v != 2);
+                    }
+                    map.check_invariants();
+                    assert_eq!(map.num_mappings(), 4);
+                    assert_eq!(map.contains(&1, &2), false);
+                    assert_eq!(map.contains(&1, &4), true);
+                }
+
+                #[test]
+                fn test_get_mut_removes_empty_keys() {
+                    let mut map = $map_maker;
+                    assert_eq!(map.insert(1, 2), true);
+                    map.check_invariants();
+                    assert_eq!(map.insert(2, 3), true);
+                    map.check_invariants();
+                    map.get_mut(&1).unwrap().clear();
+                    map.check_invariants();
+                    assert_eq!(map.contains_key(&1), false);
+                    assert_eq!(map.num_keys(), 1);
+                    assert_eq!(map.num_mappings(), 1);
+                }
+
                 #[test]
                 fn test_keys() {
                     let mut map = $map_maker;
                     assert_eq!(map.insert(1, 2), true);
+                    map.check_invariants();
                     assert_eq!(map.insert(2, 3), true);
+                    map.check_invariants();
                     assert_eq!(map.insert(2, 4), true);
+                    map.check_invariants();
                     assert!(unordered_elements_are(map.keys().cloned(), vec![1, 2]))
                 }
 
@@ -490,7 +2158,9 @@ mod tests {
                 fn test_value_sets() {
                     let mut map = $map_maker;
                     assert_eq!(map.insert(1, 2), true);
+                    map.check_invariants();
                     assert_eq!(map.insert(2, 3), true);
+                    map.check_invariants();
                     assert!(unordered_elements_are(
                         map.value_sets()
                             .map(|(k, v)| (k.clone(), Vec::from_iter(v.iter().cloned()))),
@@ -502,22 +2172,72 @@ mod tests {
                 fn test_mappings() {
                     let mut map = $map_maker;
                     assert_eq!(map.insert(1, 2), true);
+                    map.check_invariants();
                     assert_eq!(map.insert(1, 3), true);
+                    map.check_invariants();
                     assert_eq!(map.insert(2, 3), true);
+                    map.check_invariants();
                     assert_eq!(map.insert(2, 4), true);
+                    map.check_invariants();
                     assert!(unordered_elements_are(
                         map.mappings().map(|(k, v)| (k.clone(), v.clone())),
                         vec![(1, 2), (1, 3), (2, 3), (2, 4)]
                     ));
                 }
 
+                #[test]
+                fn test_into_iter() {
+                    let mut map = $map_maker;
+                    assert_eq!(map.insert(1, 2), true);
+                    assert_eq!(map.insert(1, 3), true);
+                    assert_eq!(map.insert(2, 4), true);
+                    assert!(unordered_elements_are(
+                        (&map).into_iter().map(|(k, v)| (k.clone(), v.clone())),
+                        vec![(1, 2), (1, 3), (2, 4)]
+                    ));
+                    let iter = map.into_iter();
+                    assert_eq!(iter.size_hint(), (3, Some(3)));
+                    assert!(unordered_elements_are(iter, vec![(1, 2), (1, 3), (2, 4)]));
+                }
+
+                #[test]
+                fn test_into_value_sets() {
+                    let mut map = $map_maker;
+                    assert_eq!(map.insert(1, 2), true);
+                    assert_eq!(map.insert(1, 3), true);
+                    assert_eq!(map.insert(2, 4), true);
+                    assert!(unordered_elements_are(
+                        map.into_value_sets().map(|(k, v)| (k, v.len())),
+                        vec![(1, 2), (2, 1)]
+                    ));
+                }
+
+                #[test]
+                fn test_mappings_len() {
+                    let mut map = $map_maker;
+                    assert_eq!(map.insert(1, 2), true);
+                    assert_eq!(map.insert(1, 3), true);
+                    assert_eq!(map.insert(2, 4), true);
+                    let mut mappings = map.mappings();
+                    assert_eq!(mappings.len(), 3);
+                    mappings.next();
+                    assert_eq!(mappings.len(), 2);
+                    mappings.next();
+                    mappings.next();
+                    assert_eq!(mappings.len(), 0);
+                    assert_eq!(mappings.next(), None);
+                    assert_eq!(mappings.next(), None);
+                }
+
                 #[test]
                 fn test_is_empty() {
                     let mut map = $map_maker;
                     assert_eq!(map.is_empty(), true);
                     assert_eq!(map.insert(1, 2), true);
+                    map.check_invariants();
                     assert_eq!(map.is_empty(), false);
                     assert_eq!(map.remove(&1, &2), true);
+                    map.check_invariants();
                     assert_eq!(map.is_empty(), true);
                 }
 
@@ -526,14 +2246,19 @@ mod tests {
                     let mut map = $map_maker;
                     assert_eq!(map.num_keys(), 0);
                     assert_eq!(map.insert(1, 2), true);
+                    map.check_invariants();
                     assert_eq!(map.num_keys(), 1);
                     assert_eq!(map.insert(2, 3), true);
+                    map.check_invariants();
                     assert_eq!(map.num_keys(), 2);
                     assert_eq!(map.insert(2, 4), true);
+                    map.check_invariants();
                     assert_eq!(map.num_keys(), 2);
                     assert!(map.remove_key(&1).is_some());
+                    map.check_invariants();
                     assert_eq!(map.num_keys(), 1);
                     assert!(map.remove_key(&1).is_none());
+                    map.check_invariants();
                     assert_eq!(map.num_keys(), 1);
                 }
 
@@ -542,20 +2267,28 @@ mod tests {
                     let mut map = $map_maker;
                     assert_eq!(map.num_mappings(), 0);
                     assert_eq!(map.insert(1, 2), true);
+                    map.check_invariants();
                     assert_eq!(map.num_mappings(), 1);
                     assert_eq!(map.insert(1, 3), true);
+                    map.check_invariants();
                     assert_eq!(map.num_mappings(), 2);
                     assert_eq!(map.insert(2, 3), true);
+                    map.check_invariants();
                     assert_eq!(map.num_mappings(), 3);
                     assert_eq!(map.insert(2, 4), true);
+                    map.check_invariants();
                     assert_eq!(map.num_mappings(), 4);
                     assert_eq!(map.remove(&1, &2), true);
+                    map.check_invariants();
                     assert_eq!(map.num_mappings(), 3);
                     assert_eq!(map.remove(&1, &3), true);
+                    map.check_invariants();
                     assert_eq!(map.num_mappings(), 2);
                     assert_eq!(map.remove(&2, &3), true);
+                    map.check_invariants();
                     assert_eq!(map.num_mappings(), 1);
                     assert_eq!(map.remove(&2, &4), true);
+                    map.check_invariants();
                     assert_eq!(map.num_mappings(), 0);
                 }
             }
@@ -642,6 +2375,42 @@ mod tests {
         };
     }
 
+    macro_rules! sorted_keys_and_values_test_suite {
+        ($map_name:ident, $map_maker:expr) => {
+            mod $map_name {
+                use crate::MultiMapBuilder;
+
+                #[test]
+                fn test_mappings_rev() {
+                    let mut map = $map_maker;
+                    assert_eq!(map.insert(1, 2), true);
+                    assert_eq!(map.insert(1, 3), true);
+                    assert_eq!(map.insert(2, 4), true);
+                    assert_eq!(map.insert(2, 5), true);
+                    assert_eq!(
+                        map.mappings().rev().collect::<Vec<_>>(),
+                        vec![(&2, &5), (&2, &4), (&1, &3), (&1, &2)]
+                    );
+                }
+
+                #[test]
+                fn test_mappings_both_ends() {
+                    let mut map = $map_maker;
+                    assert_eq!(map.insert(1, 2), true);
+                    assert_eq!(map.insert(1, 3), true);
+                    assert_eq!(map.insert(1, 4), true);
+                    let mut mappings = map.mappings();
+                    assert_eq!(mappings.next(), Some((&1, &2)));
+                    assert_eq!(mappings.next_back(), Some((&1, &4)));
+                    assert_eq!(mappings.len(), 1);
+                    assert_eq!(mappings.next_back(), Some((&1, &3)));
+                    assert_eq!(mappings.next(), None);
+                    assert_eq!(mappings.next_back(), None);
+                }
+            }
+        };
+    }
+
     base_test_suite!(
         hash_values_hash_keys,
         MultiMapBuilder::hash_keys().hash_values().build()
@@ -681,4 +2450,199 @@ mod tests {
         sorted_values_sorted_keys_sorted_values_tests,
         MultiMapBuilder::sorted_keys().sorted_values().build()
     );
+
+    sorted_keys_and_values_test_suite!(
+        sorted_values_sorted_keys_sorted_keys_and_values_tests,
+        MultiMapBuilder::sorted_keys().sorted_values().build()
+    );
+
+    base_test_suite!(
+        hash_values_hash_keys_with_value_capacity,
+        MultiMapBuilder::hash_keys()
+            .hash_values()
+            .value_capacity(8)
+            .build()
+    );
+
+    base_test_suite!(
+        sorted_values_sorted_keys_with_value_capacity,
+        MultiMapBuilder::sorted_keys()
+            .sorted_values()
+            .value_capacity(8)
+            .build()
+    );
+
+    #[cfg(feature = "indexmap")]
+    base_test_suite!(
+        insertion_ordered_values_insertion_ordered_keys,
+        MultiMapBuilder::insertion_ordered_keys()
+            .insertion_ordered_values()
+            .build()
+    );
+
+    #[test]
+    #[cfg(feature = "indexmap")]
+    fn test_insertion_order() {
+        use crate::IndexMultiMap;
+        let mut map = IndexMultiMap::new();
+        for (key, value) in [("c", 3), ("a", 1), ("c", 1), ("b", 2), ("a", 0), ("d", 4)] {
+            map.insert(key, value);
+        }
+        assert!(map
+            .mappings()
+            .eq([("c", 3), ("c", 1), ("a", 1), ("a", 0), ("b", 2), ("d", 4)]
+                .iter()
+                .map(|(k, v)| (k, v))));
+        assert_eq!(map.get_index(1).map(|(k, _)| *k), Some("a"));
+        assert_eq!(map.get_index_of("b"), Some(2));
+        map.remove("c", &3);
+        assert!(map.get("c").unwrap().iter().eq(&[1]));
+        assert_eq!(map.shift_remove_key("c").map(|set| set.len()), Some(1));
+        map.check_invariants();
+        assert!(map.keys().eq(&["a", "b", "d"]));
+        assert_eq!(map.swap_remove_key("a").map(|set| set.len()), Some(2));
+        map.check_invariants();
+        assert!(map.keys().eq(&["d", "b"]));
+        assert_eq!(map.swap_remove_key("a"), None);
+        assert_eq!(map.num_mappings(), 2);
+    }
+
+    base_test_suite!(
+        small_values_hash_keys,
+        MultiMapBuilder::hash_keys::<_, crate::small_set::SmallSet<_, 2>>()
+            .with_set_type()
+            .build()
+    );
+
+    base_test_suite!(
+        small_values_sorted_keys,
+        MultiMapBuilder::sorted_keys::<
+            _,
+            crate::small_set::SmallSet<_, 1, std::collections::BTreeSet<_>>,
+        >()
+        .with_set_type()
+        .build()
+    );
+
+    base_test_suite!(
+        sorted_vec_values_sorted_vec_keys,
+        MultiMapBuilder::sorted_vec_keys()
+            .sorted_vec_values()
+            .build()
+    );
+
+    sorted_keys_test_suite!(
+        sorted_vec_values_sorted_vec_keys_sorted_key_tests,
+        MultiMapBuilder::sorted_vec_keys()
+            .sorted_vec_values()
+            .build()
+    );
+
+    sorted_values_test_suite!(
+        sorted_vec_values_sorted_vec_keys_sorted_values_tests,
+        MultiMapBuilder::sorted_vec_keys()
+            .sorted_vec_values()
+            .build()
+    );
+
+    sorted_keys_and_values_test_suite!(
+        sorted_vec_values_sorted_vec_keys_sorted_keys_and_values_tests,
+        MultiMapBuilder::sorted_vec_keys()
+            .sorted_vec_values()
+            .build()
+    );
+
+    #[test]
+    fn test_freeze_and_from_unsorted() {
+        use crate::{BTreeMultiMap, SortedVecMultiMap};
+        let mappings = (0..200)
+            .map(|i| ((i * 7) % 13, (i * 11) % 17))
+            .collect::<Vec<_>>();
+        let mut map = BTreeMultiMap::new();
+        map.extend(mappings.iter().copied());
+        let frozen = map.clone().freeze();
+        frozen.check_invariants();
+        let unsorted = SortedVecMultiMap::from_unsorted(mappings);
+        unsorted.check_invariants();
+        assert_eq!(frozen, unsorted);
+        assert!(frozen.mappings().eq(map.mappings()));
+        assert!(frozen
+            .value_sets_in_range(3..=5)
+            .map(|(k, set)| (k, set.iter().collect::<Vec<_>>()))
+            .eq(map
+                .value_sets_in_range(3..=5)
+                .map(|(k, set)| (k, set.iter().collect::<Vec<_>>()))));
+    }
+
+    #[test]
+    fn test_reserve_grows_capacity() {
+        use crate::{HashMultiMap, SortedVecMultiMap};
+        let mut map: HashMultiMap<i32, i32> = HashMultiMap::new();
+        map.reserve(100);
+        assert!(map.capacity() >= 100);
+        let mut map = SortedVecMultiMap::<i32, i32>::new();
+        map.reserve(100);
+        assert!(map.capacity() >= 100);
+        #[cfg(feature = "indexmap")]
+        {
+            let mut map: crate::IndexMultiMap<i32, i32> = crate::IndexMultiMap::new();
+            map.reserve(100);
+            assert!(map.capacity() >= 100);
+        }
+    }
+
+    #[test]
+    fn test_value_capacity() {
+        use crate::MultiMapBuilder;
+        let mut map = MultiMapBuilder::hash_keys()
+            .hash_values()
+            .value_capacity(8)
+            .build();
+        map.insert(1, 2);
+        map.entry(2).or_insert_values([3]);
+        assert!(map.get(&1).unwrap().capacity() >= 8);
+        assert!(map.get(&2).unwrap().capacity() >= 8);
+        map.shrink_to_fit();
+        assert!(map.get(&1).unwrap().capacity() < 8);
+    }
+
+    #[test]
+    fn test_value_capacity_does_not_affect_equality_or_debug() {
+        use crate::MultiMapBuilder;
+        let mut a = MultiMapBuilder::hash_keys().hash_values().build();
+        let mut b = MultiMapBuilder::hash_keys()
+            .hash_values()
+            .value_capacity(8)
+            .build();
+        a.insert(1, 2);
+        b.insert(1, 2);
+        assert_eq!(a, b);
+        assert_eq!(format!("{:?}", a), format!("{:?}", b));
+    }
+
+    type DeterministicHasher =
+        std::hash::BuildHasherDefault<std::collections::hash_map::DefaultHasher>;
+
+    base_test_suite!(
+        hash_values_hash_keys_with_hasher,
+        MultiMapBuilder::hash_keys_with_hasher(super::DeterministicHasher::default())
+            .hash_values_with_hasher_type::<_, super::DeterministicHasher>()
+            .build()
+    );
+
+    #[test]
+    fn test_with_hasher_is_deterministic() {
+        use crate::HashMultiMap;
+        let make_map = || {
+            let mut map: HashMultiMap<i32, i32, DeterministicHasher> =
+                HashMultiMap::with_capacity_and_hasher(16, DeterministicHasher::default());
+            for i in 0..100 {
+                map.insert(i % 17, i);
+            }
+            map
+        };
+        let a = make_map();
+        let b = make_map();
+        assert!(a.mappings().eq(b.mappings()));
+    }
 }
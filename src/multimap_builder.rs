@@ -1,8 +1,13 @@
+use crate::bi_multimap::BiMultiMap;
 use crate::maps::Map;
 use crate::sets::Set;
+use crate::sorted_vec_map::SortedVecMap;
+use crate::sorted_vec_set::SortedVecSet;
 use crate::MultiMap;
+#[cfg(feature = "indexmap")]
+use indexmap::{IndexMap, IndexSet};
 use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
-use std::hash::Hash;
+use std::hash::{BuildHasher, Hash};
 
 /// A builder for a multi-map. This struct does nothing by itself, but it is used to chain method calls to
 /// configure the multi-map before building it.
@@ -17,6 +22,28 @@ impl MultiMapBuilder {
         Self::with_map_type()
     }
 
+    /// Configures the multi-map to use a hashmap with the given hasher for keys.
+    pub fn hash_keys_with_hasher<K, S, H>(hasher: H) -> MultiMapBuilderWithKeys<HashMap<K, S, H>>
+    where
+        K: Hash + Eq,
+        H: BuildHasher,
+    {
+        Self::with_map(HashMap::with_hasher(hasher))
+    }
+
+    /// Configures the multi-map to use a hashmap with the given hasher for keys, with space for at least
+    /// `capacity` keys.
+    pub fn hash_keys_with_capacity_and_hasher<K, S, H>(
+        capacity: usize,
+        hasher: H,
+    ) -> MultiMapBuilderWithKeys<HashMap<K, S, H>>
+    where
+        K: Hash + Eq,
+        H: BuildHasher,
+    {
+        Self::with_map(HashMap::with_capacity_and_hasher(capacity, hasher))
+    }
+
     /// Configures the multi-map to use a sorted map.
     pub fn sorted_keys<K, S>() -> MultiMapBuilderWithKeys<BTreeMap<K, S>>
     where
@@ -25,14 +52,36 @@ impl MultiMapBuilder {
         Self::with_map_type()
     }
 
+    /// Configures the multi-map to use a sorted `Vec` for keys, which is fast to query but slow to update.
+    pub fn sorted_vec_keys<K, S>() -> MultiMapBuilderWithKeys<SortedVecMap<K, S>>
+    where
+        K: Ord,
+    {
+        Self::with_map_type()
+    }
+
+    /// Configures the multi-map to use a map that keeps keys in insertion order.
+    #[cfg(feature = "indexmap")]
+    pub fn insertion_ordered_keys<K, S>() -> MultiMapBuilderWithKeys<IndexMap<K, S>>
+    where
+        K: Hash + Eq,
+    {
+        Self::with_map_type()
+    }
+
     /// An advanced method that allows you to specify the type of map to use for keys.
     pub fn with_map_type<M>() -> MultiMapBuilderWithKeys<M>
+    where
+        M: Map + Default,
+    {
+        Self::with_map(M::default())
+    }
+
+    fn with_map<M>(map: M) -> MultiMapBuilderWithKeys<M>
     where
         M: Map,
     {
-        MultiMapBuilderWithKeys {
-            _m: std::marker::PhantomData,
-        }
+        MultiMapBuilderWithKeys { map }
     }
 }
 
@@ -41,7 +90,7 @@ pub struct MultiMapBuilderWithKeys<M>
 where
     M: Map,
 {
-    _m: std::marker::PhantomData<M>,
+    map: M,
 }
 
 impl<M> MultiMapBuilderWithKeys<M>
@@ -57,6 +106,17 @@ where
         self.with_set_type()
     }
 
+    /// Configures the multi-map to use a hash set with a custom hasher type for values.
+    /// Each value set is created with its own `H::default()`.
+    pub fn hash_values_with_hasher_type<V, H>(self) -> MultiMapBuilderWithKeysAndVals<M>
+    where
+        M: Map<Val = HashSet<V, H>>,
+        V: Hash + Eq,
+        H: BuildHasher + Default,
+    {
+        self.with_set_type()
+    }
+
     /// Configures the multi-map to use a sorted set for values.
     pub fn sorted_values<V>(self) -> MultiMapBuilderWithKeysAndVals<M>
     where
@@ -66,6 +126,25 @@ where
         self.with_set_type()
     }
 
+    /// Configures the multi-map to use a sorted `Vec` for values, which is fast to query but slow to update.
+    pub fn sorted_vec_values<V>(self) -> MultiMapBuilderWithKeysAndVals<M>
+    where
+        M: Map<Val = SortedVecSet<V>>,
+        V: Ord,
+    {
+        self.with_set_type()
+    }
+
+    /// Configures the multi-map to use a set that keeps values in insertion order.
+    #[cfg(feature = "indexmap")]
+    pub fn insertion_ordered_values<V>(self) -> MultiMapBuilderWithKeysAndVals<M>
+    where
+        M: Map<Val = IndexSet<V>>,
+        V: Hash + Eq,
+    {
+        self.with_set_type()
+    }
+
     /// An advanced method that allows you to specify the type of set to use for values.
     pub fn with_set_type(self) -> MultiMapBuilderWithKeysAndVals<M>
     where
@@ -73,22 +152,48 @@ where
         M::Val: Set,
     {
         MultiMapBuilderWithKeysAndVals {
-            _m: std::marker::PhantomData,
+            map: self.map,
+            value_capacity: 0,
         }
     }
 }
 
 /// A builder for a multi-map that has a known type for keys and values.
 pub struct MultiMapBuilderWithKeysAndVals<M> {
-    _m: std::marker::PhantomData<M>,
+    map: M,
+    value_capacity: usize,
 }
 
 impl<M> MultiMapBuilderWithKeysAndVals<M>
 where
-    M: Default,
+    M: Map,
+    M::Val: Set,
 {
+    /// Configures the multi-map to create value sets with space for at least `capacity` values.
+    /// This is only a hint, and does nothing for sets without a notion of capacity.
+    pub fn value_capacity(self, capacity: usize) -> Self {
+        MultiMapBuilderWithKeysAndVals {
+            value_capacity: capacity,
+            ..self
+        }
+    }
+
     /// Builds a multi-map.
     pub fn build(self) -> MultiMap<M> {
-        Default::default()
+        MultiMap::from_empty_map(self.map, self.value_capacity)
+    }
+
+    /// Builds a bidirectional multi-map, which uses this configuration for its left-to-right side,
+    /// and `inverse` for its right-to-left side.
+    pub fn build_bidirectional<R>(
+        self,
+        inverse: MultiMapBuilderWithKeysAndVals<R>,
+    ) -> BiMultiMap<M, R>
+    where
+        M::Val: Set<Elem = R::Key>,
+        R: Map,
+        R::Val: Set<Elem = M::Key>,
+    {
+        BiMultiMap::from_empty_multimaps(self.build(), inverse.build())
     }
 }
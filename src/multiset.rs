@@ -1,12 +1,25 @@
-use crate::maps::{Lookup, Map, SortedMap};
+use crate::counts::{Count, CountOverflow, InsufficientCount};
+use crate::maps::{
+    DefaultMap, Entry, Lookup, Map, OccupiedEntry, Rank, Select, SortedMap, VacantEntry,
+};
+use crate::sorted_vec_map::SortedVecMap;
+#[cfg(feature = "rand")]
+use rand::{seq::index, Rng};
 use std::borrow::Borrow;
-use std::mem::replace;
-use std::ops::RangeBounds;
+use std::cmp::{Ordering, Reverse};
+use std::collections::BinaryHeap;
+use std::collections::{BTreeMap, HashMap};
+use std::hash::{BuildHasher, Hash};
+use std::iter::FusedIterator;
+use std::ops::{
+    Add, AddAssign, BitAnd, BitAndAssign, BitOr, BitOrAssign, RangeBounds, Sub, SubAssign,
+};
 
 /// A set that allows duplicate elements.
 /// The set is implemented as a map from elements to their counts.
 /// However, the API is designed to be more like a set,
 /// including the ability to iterate over duplicate elements multiple times.
+/// Counts are taken and returned as `usize`, but may be stored as any `Count` type.
 #[derive(Default, Debug, PartialEq, Eq, Clone)]
 pub struct MultiSet<M> {
     map: M,
@@ -15,7 +28,7 @@ pub struct MultiSet<M> {
 
 impl<M> MultiSet<M>
 where
-    M: Default,
+    M: DefaultMap,
 {
     /// Creates a new empty multi-set.
     ///
@@ -36,10 +49,140 @@ where
     }
 }
 
+impl<K, C> MultiSet<HashMap<K, C>> {
+    /// Creates a new empty multi-set with space for at least `capacity` unique values.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use multi_containers::HashMultiSet;
+    /// let set: HashMultiSet<i32> = HashMultiSet::with_capacity(10);
+    /// assert!(set.capacity() >= 10);
+    /// ```
+    pub fn with_capacity(capacity: usize) -> Self {
+        MultiSet {
+            map: HashMap::with_capacity(capacity),
+            length: 0,
+        }
+    }
+}
+
+impl<K, C, S> MultiSet<HashMap<K, C, S>>
+where
+    K: Hash + Eq,
+    C: Count,
+    S: BuildHasher,
+{
+    /// Creates a new empty multi-set which will use the given hasher to hash values.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use multi_containers::HashMultiSet;
+    /// use std::collections::hash_map::DefaultHasher;
+    /// use std::hash::BuildHasherDefault;
+    /// type Hasher = BuildHasherDefault<DefaultHasher>;
+    /// let mut set: HashMultiSet<i32, Hasher> = HashMultiSet::with_hasher(Hasher::default());
+    /// set.insert(1);
+    /// assert_eq!(set.count(&1), 1);
+    /// ```
+    pub fn with_hasher(hasher: S) -> Self {
+        Self::from_empty_map(HashMap::with_hasher(hasher))
+    }
+
+    /// Creates a new empty multi-set with space for at least `capacity` unique values, which will use the
+    /// given hasher to hash values.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use multi_containers::HashMultiSet;
+    /// use std::collections::hash_map::DefaultHasher;
+    /// use std::hash::BuildHasherDefault;
+    /// type Hasher = BuildHasherDefault<DefaultHasher>;
+    /// let mut set: HashMultiSet<i32, Hasher> =
+    ///     HashMultiSet::with_capacity_and_hasher(10, Hasher::default());
+    /// set.insert(1);
+    /// assert_eq!(set.count(&1), 1);
+    /// ```
+    pub fn with_capacity_and_hasher(capacity: usize, hasher: S) -> Self {
+        Self::from_empty_map(HashMap::with_capacity_and_hasher(capacity, hasher))
+    }
+}
+
+impl<K, C> MultiSet<BTreeMap<K, C>>
+where
+    K: Ord,
+    C: Count,
+{
+    /// Converts the multi-set into a `SortedVecMultiSet`, which is faster to query but slower to update.
+    /// This takes `O(n)` time, as the values are already sorted.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use multi_containers::BTreeMultiSet;
+    /// let mut set = BTreeMultiSet::new();
+    /// set.insert_some('b', 2);
+    /// set.insert('a');
+    /// let frozen = set.freeze();
+    /// assert_eq!(frozen.len(), 3);
+    /// assert_eq!(frozen.range_counts('b'..).collect::<Vec<_>>(), vec![(&'b', &2)]);
+    /// ```
+    pub fn freeze(self) -> MultiSet<SortedVecMap<K, C>> {
+        MultiSet {
+            map: SortedVecMap::from_sorted_vec(self.map.into_iter().collect()),
+            length: self.length,
+        }
+    }
+}
+
+impl<K> MultiSet<SortedVecMap<K, usize>>
+where
+    K: Ord,
+{
+    /// Creates a multi-set from values in any order, in `O(n log n)` time.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use multi_containers::SortedVecMultiSet;
+    /// let set = SortedVecMultiSet::from_unsorted(['c', 'a', 'c', 'b', 'c']);
+    /// assert_eq!(set.len(), 5);
+    /// assert_eq!(set.count(&'c'), 3);
+    /// assert_eq!(set.iter().collect::<String>(), "abccc");
+    /// ```
+    pub fn from_unsorted<I>(values: I) -> Self
+    where
+        I: IntoIterator<Item = K>,
+    {
+        let mut values = values.into_iter().collect::<Vec<_>>();
+        values.sort_unstable();
+        let length = values.len();
+        let mut entries: Vec<(K, usize)> = Vec::new();
+        for value in values {
+            match entries.last_mut() {
+                Some((last, count)) if *last == value => *count += 1,
+                _ => entries.push((value, 1)),
+            }
+        }
+        MultiSet {
+            map: SortedVecMap::from_sorted_vec(entries),
+            length,
+        }
+    }
+}
+
 impl<M> MultiSet<M>
 where
-    M: Map<Val = usize>,
+    M: Map<Val: Count>,
 {
+    // Wraps a map which must be empty, such as one configured by a builder.
+    pub(crate) fn from_empty_map(map: M) -> Self {
+        debug_assert!(map.is_empty());
+        MultiSet { map, length: 0 }
+    }
+
     /// Inserts a value into the multi-set.
     /// Returns the previous count of the value.
     /// If the value was not present, the previous count is 0.
@@ -73,11 +216,86 @@ where
     /// assert_eq!(set.insert_some(2, 3), 0);
     /// assert_eq!(set.insert_some(2, 3), 3);
     /// assert_eq!(set.insert_some(2, 3), 6);
+    /// assert_eq!(set.insert_some(3, 0), 0);
+    /// assert_eq!(set.contains(&3), false);
     /// ```
+    ///
+    /// # Panics
+    ///
+    /// Panics if the new count does not fit in the count type of the multi-set, or the new length does not
+    /// fit in a `usize`. See `MultiSet::try_insert_some` and `MultiSet::saturating_insert_some`.
     pub fn insert_some(&mut self, value: M::Key, count: usize) -> usize {
-        self.length += count;
-        let have = self.map.get_or_insert(value, || 0_usize);
-        replace(have, *have + count)
+        self.add_some(value, count, false).expect("count overflow")
+    }
+
+    /// Like `MultiSet::insert_some`, but returns an error instead of panicking if the new count does not fit.
+    /// The multi-set is left unchanged in that case.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use multi_containers::counts::CountOverflow;
+    /// use multi_containers::MultiSetBuilder;
+    /// let mut set = MultiSetBuilder::hash_values_with_count_type::<_, u8>().build();
+    /// assert_eq!(set.try_insert_some(1, 200), Ok(0));
+    /// assert_eq!(set.try_insert_some(1, 100), Err(CountOverflow));
+    /// assert_eq!(set.count(&1), 200);
+    /// ```
+    pub fn try_insert_some(&mut self, value: M::Key, count: usize) -> Result<usize, CountOverflow> {
+        self.add_some(value, count, false)
+    }
+
+    /// Like `MultiSet::insert_some`, but inserts only as many occurrences as fit instead of panicking.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use multi_containers::MultiSetBuilder;
+    /// let mut set = MultiSetBuilder::hash_values_with_count_type::<_, u8>().build();
+    /// assert_eq!(set.saturating_insert_some(1, 200), 0);
+    /// assert_eq!(set.saturating_insert_some(1, 100), 200);
+    /// assert_eq!(set.count(&1), 255);
+    /// assert_eq!(set.len(), 255);
+    /// ```
+    pub fn saturating_insert_some(&mut self, value: M::Key, count: usize) -> usize {
+        self.add_some(value, count, true)
+            .expect("saturating insertion cannot overflow")
+    }
+
+    // Adds `count` occurrences of a value, or only as many as fit if `saturating` is set.
+    // Returns the previous count of the value. Nothing is added if the occurrences do not fit otherwise.
+    fn add_some(
+        &mut self,
+        value: M::Key,
+        count: usize,
+        saturating: bool,
+    ) -> Result<usize, CountOverflow> {
+        let entry = self.map.entry(value);
+        let have = match &entry {
+            Entry::Occupied(entry) => entry.get().to_usize(),
+            Entry::Vacant(_) => 0,
+        };
+        // The length of the multi-set must fit in a `usize` as well as the count.
+        let fits = count.min(usize::MAX - self.length);
+        if fits < count && !saturating {
+            return Err(CountOverflow);
+        }
+        if fits == 0 {
+            return Ok(have);
+        }
+        let new = match M::Val::from_usize(have + fits) {
+            Some(new) => new,
+            None if saturating => M::Val::saturating_from_usize(have + fits),
+            None => return Err(CountOverflow),
+        };
+        self.length += new.to_usize() - have;
+        match entry {
+            Entry::Occupied(mut entry) => *entry.get_mut() = new,
+            Entry::Vacant(entry) => {
+                entry.insert(new);
+            }
+        }
+        Ok(have)
     }
 
     /// Sets the count of a value in the multi-set.
@@ -96,19 +314,46 @@ where
     /// assert_eq!(set.set_count(2, 0), 3);
     /// assert_eq!(set.set_count(2, 0), 0);
     /// ```
+    ///
+    /// # Panics
+    ///
+    /// Panics if the count does not fit in the count type of the multi-set, or the new length does not fit in
+    /// a `usize`. See `MultiSet::try_set_count`.
     pub fn set_count(&mut self, value: M::Key, count: usize) -> usize
+    where
+        M: Lookup<<M as Map>::Key>,
+    {
+        self.try_set_count(value, count).expect("count overflow")
+    }
+
+    /// Like `MultiSet::set_count`, but returns an error instead of panicking if the count does not fit.
+    /// The multi-set is left unchanged in that case.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use multi_containers::counts::CountOverflow;
+    /// use multi_containers::MultiSetBuilder;
+    /// let mut set = MultiSetBuilder::sorted_values_with_count_type::<_, u8>().build();
+    /// assert_eq!(set.try_set_count(1, 255), Ok(0));
+    /// assert_eq!(set.try_set_count(1, 256), Err(CountOverflow));
+    /// assert_eq!(set.count(&1), 255);
+    /// ```
+    pub fn try_set_count(&mut self, value: M::Key, count: usize) -> Result<usize, CountOverflow>
     where
         M: Lookup<<M as Map>::Key>,
     {
         if count == 0 {
-            return self.remove_all(&value);
+            return Ok(self.remove_all(&value));
         }
 
-        let have = self.map.get_or_insert(value, || 0_usize);
-        let prev = replace(have, count);
-        self.length += *have;
-        self.length -= prev;
-        prev
+        let have = self.count(&value);
+        let new = M::Val::from_usize(count).ok_or(CountOverflow)?;
+        self.length = (self.length - have)
+            .checked_add(count)
+            .ok_or(CountOverflow)?;
+        self.map.insert(value, new);
+        Ok(have)
     }
 
     /// Removes a value from the multi-set. Returns the previous count of the value.
@@ -164,12 +409,14 @@ where
     {
         match self.map.get_mut(value) {
             Some(count) => {
-                let prev = *count;
-                let removed = (*count).min(max);
-                *count -= removed;
+                let prev = count.to_usize();
+                let removed = prev.min(max);
                 self.length -= removed;
-                if *count == 0 {
-                    self.map.remove(value);
+                match M::Val::from_usize(prev - removed) {
+                    Some(new) if removed < prev => *count = new,
+                    _ => {
+                        self.map.remove(value);
+                    }
                 }
                 prev
             }
@@ -177,6 +424,78 @@ where
         }
     }
 
+    /// Removes exactly `n` occurrences of a value from the multi-set, or nothing if it has fewer.
+    /// Returns the previous count of the value, or an error if the value does not occur `n` times.
+    /// If the count of a value is reduced to 0, the value is removed from the multi-set.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use multi_containers::counts::InsufficientCount;
+    /// use multi_containers::HashMultiSet;
+    /// let mut set = HashMultiSet::new();
+    /// set.insert_some(1, 3);
+    /// assert_eq!(set.try_remove_exact(&1, 2), Ok(3));
+    /// assert_eq!(
+    ///     set.try_remove_exact(&1, 2),
+    ///     Err(InsufficientCount { requested: 2, available: 1 })
+    /// );
+    /// assert_eq!(set.count(&1), 1);
+    /// ```
+    pub fn try_remove_exact<Q>(&mut self, value: &Q, n: usize) -> Result<usize, InsufficientCount>
+    where
+        M: Lookup<Q>,
+        M::Key: Borrow<Q>,
+        Q: ?Sized,
+    {
+        let available = self.count(value);
+        if available < n {
+            return Err(InsufficientCount {
+                requested: n,
+                available,
+            });
+        }
+        Ok(self.remove_at_most(value, n))
+    }
+
+    /// Removes every value of `other` from the multi-set as many times as it occurs in `other`,
+    /// or nothing if any value occurs fewer times in `self` than in `other`.
+    /// The error describes the first such value found.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use multi_containers::counts::InsufficientCount;
+    /// use multi_containers::BTreeMultiSet;
+    /// let mut stock = BTreeMultiSet::from(["apple", "apple", "pear"]);
+    /// assert_eq!(stock.try_remove_all_of(&BTreeMultiSet::from(["apple", "pear"])), Ok(()));
+    /// assert_eq!(
+    ///     stock.try_remove_all_of(&BTreeMultiSet::from(["apple", "pear"])),
+    ///     Err(InsufficientCount { requested: 1, available: 0 })
+    /// );
+    /// assert_eq!(stock.iter().collect::<Vec<_>>(), vec![&"apple"]);
+    /// ```
+    pub fn try_remove_all_of<N>(&mut self, other: &MultiSet<N>) -> Result<(), InsufficientCount>
+    where
+        M: Lookup<<M as Map>::Key>,
+        N: Map<Key = M::Key, Val: Count>,
+    {
+        // Check everything before removing anything, so that the multi-set is unchanged on failure.
+        for (value, count) in other.counts() {
+            let available = self.count(value);
+            if available < count.to_usize() {
+                return Err(InsufficientCount {
+                    requested: count.to_usize(),
+                    available,
+                });
+            }
+        }
+        for (value, count) in other.counts() {
+            self.remove_at_most(value, count.to_usize());
+        }
+        Ok(())
+    }
+
     /// Removes all occurrences of a value from the multi-set.
     /// Returns the previous count of the value.
     /// If the value was not present, the previous count is 0.
@@ -201,13 +520,103 @@ where
     {
         match self.map.remove(value) {
             Some(count) => {
-                self.length -= count;
-                count
+                self.length -= count.to_usize();
+                count.to_usize()
             }
             None => 0,
         }
     }
 
+    /// Updates the count of every value in the multi-set with the result of `f`.
+    /// The closure receives each value with its current count, and returns the count to keep:
+    /// returning the current count keeps the value as is, and returning 0 removes it.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use multi_containers::HashMultiSet;
+    /// let mut set = HashMultiSet::new();
+    /// set.insert_some(1, 2);
+    /// set.insert_some(2, 3);
+    /// set.insert_some(3, 4);
+    /// set.retain(|&v, count| if v == 1 { count } else { count - v });
+    /// assert_eq!(set.count(&1), 2);
+    /// assert_eq!(set.count(&2), 1);
+    /// assert_eq!(set.count(&3), 1);
+    /// set.retain(|&v, count| if v == 2 { 0 } else { count });
+    /// assert_eq!(set.contains(&2), false);
+    /// assert_eq!(set.len(), 3);
+    /// ```
+    ///
+    /// # Panics
+    ///
+    /// Panics if a new count does not fit in the count type of the multi-set, or the new length does not fit
+    /// in a `usize`.
+    pub fn retain<F>(&mut self, mut f: F)
+    where
+        F: FnMut(&M::Key, usize) -> usize,
+    {
+        let length = &mut self.length;
+        self.map.retain(|value, count| {
+            let new = f(value, count.to_usize());
+            *length = (*length - count.to_usize())
+                .checked_add(new)
+                .expect("count overflow");
+            if new == 0 {
+                return false;
+            }
+            *count = M::Val::from_usize(new).expect("count overflow");
+            true
+        });
+    }
+
+    /// Removes all values from the multi-set, returning them as an iterator of owned (value, count) pairs.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use multi_containers::BTreeMultiSet;
+    /// let mut set = BTreeMultiSet::new();
+    /// set.insert_some(1, 2);
+    /// set.insert_some(2, 3);
+    /// assert_eq!(set.drain().collect::<Vec<_>>(), vec![(1, 2), (2, 3)]);
+    /// assert_eq!(set.is_empty(), true);
+    /// ```
+    pub fn drain(&mut self) -> M::Drain<'_> {
+        self.length = 0;
+        self.map.drain()
+    }
+
+    /// Returns an iterator that removes and yields the values for which the predicate returns `true`,
+    /// along with their counts. Values are only removed as the iterator is advanced.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use multi_containers::BTreeMultiSet;
+    /// let mut set = BTreeMultiSet::new();
+    /// set.insert_some(1, 2);
+    /// set.insert_some(2, 3);
+    /// set.insert_some(3, 4);
+    /// assert_eq!(set.extract_if(|_v, count| count > 2).collect::<Vec<_>>(), vec![(2, 3), (3, 4)]);
+    /// assert_eq!(set.len(), 2);
+    /// ```
+    pub fn extract_if<'a, F>(
+        &'a mut self,
+        mut pred: F,
+    ) -> impl Iterator<Item = (M::Key, usize)> + 'a
+    where
+        F: FnMut(&M::Key, usize) -> bool + 'a,
+    {
+        let length = &mut self.length;
+        self.map
+            .extract_if(move |value, count| pred(value, count.to_usize()))
+            .map(move |(value, count)| {
+                *length -= count.to_usize();
+                (value, count.to_usize())
+            })
+    }
+
     /// Returns `true` if the multi-set contains the given value.
     ///
     /// # Example
@@ -249,7 +658,7 @@ where
         M::Key: Borrow<Q>,
         Q: ?Sized,
     {
-        self.map.get(value).copied().unwrap_or(0_usize)
+        self.map.get(value).map_or(0, |count| count.to_usize())
     }
 
     /// Returns `true` if the multi-set is empty.
@@ -291,6 +700,53 @@ where
         self.length
     }
 
+    /// Returns the number of unique values the multi-set can hold without reallocating.
+    /// For maps without a notion of capacity, this is the number of unique values.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use multi_containers::HashMultiSet;
+    /// let mut set: HashMultiSet<i32> = HashMultiSet::new();
+    /// set.reserve(10);
+    /// assert!(set.capacity() >= 10);
+    /// ```
+    pub fn capacity(&self) -> usize {
+        self.map.capacity()
+    }
+
+    /// Reserves space for at least `additional` more unique values.
+    /// This does nothing for maps without a notion of capacity.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use multi_containers::HashMultiSet;
+    /// let mut set = HashMultiSet::new();
+    /// set.insert_some(1, 5);
+    /// set.reserve(10);
+    /// assert!(set.capacity() >= 11);
+    /// ```
+    pub fn reserve(&mut self, additional: usize) {
+        self.map.reserve(additional)
+    }
+
+    /// Shrinks the capacity of the multi-set as much as possible.
+    /// This does nothing for maps without a notion of capacity.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use multi_containers::HashMultiSet;
+    /// let mut set = HashMultiSet::with_capacity(100);
+    /// set.insert(1);
+    /// set.shrink_to_fit();
+    /// assert!(set.capacity() < 100);
+    /// ```
+    pub fn shrink_to_fit(&mut self) {
+        self.map.shrink_to_fit()
+    }
+
     /// Returns an iterator over the values of the multi-set, including duplicates.
     /// The iterator yields each value `count` times, where `count` is the number of occurrences of the value in the multi-set.
     /// The order of the values depends on the underlying map implementation.
@@ -304,11 +760,31 @@ where
     /// set.insert_some(1, 2);
     /// set.insert_some(2, 3);
     /// assert_eq!(set.iter().collect::<Vec<_>>(), vec![&1, &1, &2, &2, &2]);
+    /// assert_eq!(set.iter().len(), 5);
+    /// assert_eq!(set.iter().rev().collect::<Vec<_>>(), vec![&2, &2, &2, &1, &1]);
     /// ```
-    pub fn iter(&self) -> impl Iterator<Item = &M::Key> {
-        self.map
-            .iter()
-            .flat_map(|(k, &v)| std::iter::repeat(k).take(v))
+    pub fn iter(&self) -> Iter<'_, M> {
+        Iter {
+            inner: Occurrences::new(self.map.iter()),
+            length: self.length,
+        }
+    }
+
+    /// Consumes the multi-set, returning an iterator over the unique values with their counts.
+    /// The order of the values depends on the underlying map implementation.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// // `BTreeMultiSet` is used here so that iterators are sorted.
+    /// use multi_containers::BTreeMultiSet;
+    /// let mut set = BTreeMultiSet::new();
+    /// set.insert_some("a", 2);
+    /// set.insert_some("b", 3);
+    /// assert_eq!(set.into_counts().collect::<Vec<_>>(), vec![("a", 2), ("b", 3)]);
+    /// ```
+    pub fn into_counts(self) -> <M as IntoIterator>::IntoIter {
+        self.map.into_iter()
     }
 
     /// Returns an iterator over the unique values of the multi-set, with their counts.
@@ -328,6 +804,24 @@ where
         self.map.iter()
     }
 
+    /// Panics if the bookkeeping of the multi-set is inconsistent:
+    /// every count must be non-zero, and the length must match the sum of the counts.
+    #[cfg(test)]
+    pub(crate) fn check_invariants(&self) {
+        assert!(
+            self.map.values().all(|count| count.to_usize() > 0),
+            "multi-set contains a zero count"
+        );
+        assert_eq!(
+            self.length,
+            self.map
+                .values()
+                .map(|count| count.to_usize())
+                .sum::<usize>(),
+            "multi-set length does not match its counts"
+        );
+    }
+
     /// Returns an iterator over the entries of the multi-set within a given range, including duplicates.
     /// The iterator yields each value `count` times, where `count` is the number of occurrences of the value in the multi-set.
     /// Values will be yielded in sorted order, as this method is only available for sorted multisets.
@@ -342,17 +836,18 @@ where
     /// set.insert_some(3, 4);
     /// set.insert_some(5, 6);
     /// assert_eq!(set.range(2..4).collect::<Vec<_>>(), vec![&2, &2, &2, &3, &3, &3, &3]);
+    /// assert_eq!(set.range(2..4).rev().collect::<Vec<_>>(), vec![&3, &3, &3, &3, &2, &2, &2]);
     /// ```
-    pub fn range<Q, R>(&self, range: R) -> impl Iterator<Item = &M::Key>
+    pub fn range<Q, R>(&self, range: R) -> Range<'_, Q, M>
     where
         M: SortedMap<Q>,
         M::Key: Borrow<Q>,
         Q: ?Sized,
         R: RangeBounds<Q>,
     {
-        self.map
-            .range(range)
-            .flat_map(|(k, &v)| std::iter::repeat(k).take(v))
+        Range {
+            inner: Occurrences::new(self.map.range(range)),
+        }
     }
 
     /// Returns an iterator over the unique values of the multi-set within a given range, with their counts.
@@ -378,40 +873,1014 @@ where
     {
         self.map.range(range)
     }
-}
 
-impl <T, M> Extend<T> for MultiSet<M>
-where
-    M: Map<Key = T, Val = usize>,
-{
-    fn extend<I>(&mut self, iter: I)
+    /// Returns the value at the given position of the multi-set, counting duplicates, in iteration order.
+    /// Returns `None` if the position is at least the length of the multi-set.
+    /// This takes `O(log n)` time with `OrderStatisticMultiSet`, and linear time otherwise.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use multi_containers::OrderStatisticMultiSet;
+    /// let set = OrderStatisticMultiSet::from([3, 1, 1, 2]);
+    /// assert_eq!(set.nth(0), Some(&1));
+    /// assert_eq!(set.nth(1), Some(&1));
+    /// assert_eq!(set.nth(2), Some(&2));
+    /// assert_eq!(set.nth(4), None);
+    /// ```
+    pub fn nth(&self, index: usize) -> Option<&M::Key>
     where
-        I: IntoIterator<Item = T>,
+        M: Select,
     {
-        for value in iter {
-            self.insert(value);
-        }
+        self.map.select(index)
     }
-}
 
-impl<T, M> FromIterator<T> for MultiSet<M>
-where
-    M: Map<Key = T, Val = usize> + Default,
-{
-    fn from_iter<I>(iter: I) -> Self
+    /// Returns the number of values in the multi-set that are less than the given value, counting duplicates.
+    /// This takes `O(log n)` time with `OrderStatisticMultiSet`, and linear time otherwise.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use multi_containers::OrderStatisticMultiSet;
+    /// let set = OrderStatisticMultiSet::from([3, 1, 1, 2]);
+    /// assert_eq!(set.rank(&1), 0);
+    /// assert_eq!(set.rank(&2), 2);
+    /// assert_eq!(set.rank(&10), 4);
+    /// ```
+    pub fn rank<Q>(&self, value: &Q) -> usize
     where
-        I: IntoIterator<Item = T>,
+        M: Rank<Q>,
+        M::Key: Borrow<Q>,
+        Q: ?Sized,
     {
-        let mut set = MultiSet::default();
-        set.extend(iter);
-        set
+        self.map.rank(value)
     }
-}
 
-impl<M, const N: usize> From<[M::Key; N]> for MultiSet<M>
-where
-    M: Map<Val = usize> + Default,
-    M::Key: Clone,
+    /// Returns the number of values in the multi-set within a given range, counting duplicates.
+    /// This takes `O(log n)` time with `OrderStatisticMultiSet`, and linear time otherwise.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use multi_containers::OrderStatisticMultiSet;
+    /// let set = OrderStatisticMultiSet::from([3, 1, 1, 2, 5]);
+    /// assert_eq!(set.count_in_range(1..3), 3);
+    /// assert_eq!(set.count_in_range(2..), 3);
+    /// ```
+    pub fn count_in_range<Q, R>(&self, range: R) -> usize
+    where
+        M: Rank<Q>,
+        M::Key: Borrow<Q>,
+        Q: ?Sized,
+        R: RangeBounds<Q>,
+    {
+        self.map.count_in_range(range)
+    }
+
+    /// Returns the value at quantile `q` of the multi-set, using the nearest-rank method:
+    /// the smallest value such that at least a fraction `q` of the multi-set is less than or equal to it.
+    /// Returns `None` if the multi-set is empty.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `q` is not between 0 and 1.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use multi_containers::OrderStatisticMultiSet;
+    /// let set: OrderStatisticMultiSet<i32> = (1..=10).collect();
+    /// assert_eq!(set.quantile(0.0), Some(&1));
+    /// assert_eq!(set.quantile(0.25), Some(&3));
+    /// assert_eq!(set.quantile(0.9), Some(&9));
+    /// assert_eq!(set.quantile(1.0), Some(&10));
+    /// ```
+    pub fn quantile(&self, q: f64) -> Option<&M::Key>
+    where
+        M: Rank<<M as Map>::Key>,
+    {
+        assert!((0.0..=1.0).contains(&q), "quantile must be between 0 and 1");
+        let index = (q * self.length as f64).ceil() as usize;
+        self.nth(index.saturating_sub(1).min(self.length.saturating_sub(1)))
+    }
+
+    /// Returns the median of the multi-set, or the lower of the two middle values if its length is even.
+    /// Returns `None` if the multi-set is empty.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use multi_containers::OrderStatisticMultiSet;
+    /// assert_eq!(OrderStatisticMultiSet::from([3, 1, 2]).median(), Some(&2));
+    /// assert_eq!(OrderStatisticMultiSet::from([4, 3, 1, 2]).median(), Some(&2));
+    /// ```
+    pub fn median(&self) -> Option<&M::Key>
+    where
+        M: Rank<<M as Map>::Key>,
+    {
+        self.nth(self.length.saturating_sub(1) / 2)
+    }
+
+    /// Returns the `k` most common values of the multi-set with their counts, most common first.
+    /// Values with equal counts are ordered as they are by `MultiSet::counts`.
+    /// This keeps a heap of at most `k` values, so it takes `O(n log k)` time for `n` unique values.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use multi_containers::BTreeMultiSet;
+    /// let set: BTreeMultiSet<char> = "mississippi".chars().collect();
+    /// assert_eq!(set.most_common(3), vec![(&'i', 4), (&'s', 4), (&'p', 2)]);
+    /// ```
+    pub fn most_common(&self, k: usize) -> Vec<(&M::Key, usize)> {
+        top_k(self.counts(), k, |count, position| {
+            (count, Reverse(position))
+        })
+    }
+
+    /// Returns the `k` least common values of the multi-set with their counts, least common first.
+    /// Values with equal counts are ordered as they are by `MultiSet::counts`.
+    /// This keeps a heap of at most `k` values, so it takes `O(n log k)` time for `n` unique values.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use multi_containers::BTreeMultiSet;
+    /// let set: BTreeMultiSet<char> = "mississippi".chars().collect();
+    /// assert_eq!(set.least_common(2), vec![(&'m', 1), (&'p', 2)]);
+    /// ```
+    pub fn least_common(&self, k: usize) -> Vec<(&M::Key, usize)> {
+        top_k(self.counts(), k, |count, position| {
+            (Reverse(count), Reverse(position))
+        })
+    }
+
+    /// Returns an iterator over the unique values of the multi-set that occur exactly `count` times.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use multi_containers::BTreeMultiSet;
+    /// let set: BTreeMultiSet<char> = "mississippi".chars().collect();
+    /// assert_eq!(set.elements_with_count(4).collect::<Vec<_>>(), vec![&'i', &'s']);
+    /// ```
+    pub fn elements_with_count(&self, count: usize) -> impl Iterator<Item = &M::Key> {
+        self.counts()
+            .filter(move |(_, have)| have.to_usize() == count)
+            .map(|(value, _)| value)
+    }
+
+    /// Returns an iterator over the unique values of the sum of two multi-sets, with their counts.
+    /// The count of each value is the sum of its counts in both multi-sets, saturating at `usize::MAX`.
+    /// Values of `self` are yielded first, followed by the values that are only in `other`.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use multi_containers::BTreeMultiSet;
+    /// let a = BTreeMultiSet::from([1, 1, 2]);
+    /// let b = BTreeMultiSet::from([1, 3]);
+    /// assert_eq!(a.sum(&b).collect::<Vec<_>>(), vec![(&1, 3), (&2, 1), (&3, 1)]);
+    /// ```
+    pub fn sum<'a, N>(
+        &'a self,
+        other: &'a MultiSet<N>,
+    ) -> impl Iterator<Item = (&'a M::Key, usize)> + 'a
+    where
+        M: Lookup<<M as Map>::Key>,
+        N: Map<Key = M::Key, Val: Count> + Lookup<M::Key>,
+    {
+        self.counts()
+            .map(|(value, count)| (value, count.to_usize().saturating_add(other.count(value))))
+            .chain(other.only_in(self))
+    }
+
+    /// Returns an iterator over the unique values of the difference of two multi-sets, with their counts.
+    /// The count of each value is its count in `self` minus its count in `other`, and values whose
+    /// count would drop to zero or below are skipped.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use multi_containers::BTreeMultiSet;
+    /// let a = BTreeMultiSet::from([1, 1, 2]);
+    /// let b = BTreeMultiSet::from([1, 2, 2, 3]);
+    /// assert_eq!(a.difference(&b).collect::<Vec<_>>(), vec![(&1, 1)]);
+    /// ```
+    pub fn difference<'a, N>(
+        &'a self,
+        other: &'a MultiSet<N>,
+    ) -> impl Iterator<Item = (&'a M::Key, usize)> + 'a
+    where
+        N: Map<Key = M::Key, Val: Count> + Lookup<M::Key>,
+    {
+        self.counts()
+            .map(|(value, count)| (value, count.to_usize().saturating_sub(other.count(value))))
+            .filter(|&(_, count)| count > 0)
+    }
+
+    /// Returns an iterator over the unique values of the union of two multi-sets, with their counts.
+    /// The count of each value is the larger of its counts in both multi-sets.
+    /// Values of `self` are yielded first, followed by the values that are only in `other`.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use multi_containers::BTreeMultiSet;
+    /// let a = BTreeMultiSet::from([1, 1, 2]);
+    /// let b = BTreeMultiSet::from([1, 2, 2, 3]);
+    /// assert_eq!(a.union(&b).collect::<Vec<_>>(), vec![(&1, 2), (&2, 2), (&3, 1)]);
+    /// ```
+    pub fn union<'a, N>(
+        &'a self,
+        other: &'a MultiSet<N>,
+    ) -> impl Iterator<Item = (&'a M::Key, usize)> + 'a
+    where
+        M: Lookup<<M as Map>::Key>,
+        N: Map<Key = M::Key, Val: Count> + Lookup<M::Key>,
+    {
+        self.counts()
+            .map(|(value, count)| (value, count.to_usize().max(other.count(value))))
+            .chain(other.only_in(self))
+    }
+
+    /// Returns an iterator over the unique values of the intersection of two multi-sets, with their counts.
+    /// The count of each value is the smaller of its counts in both multi-sets, and values that are
+    /// not in both are skipped.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use multi_containers::BTreeMultiSet;
+    /// let a = BTreeMultiSet::from([1, 1, 2]);
+    /// let b = BTreeMultiSet::from([1, 3]);
+    /// assert_eq!(a.intersection(&b).collect::<Vec<_>>(), vec![(&1, 1)]);
+    /// ```
+    pub fn intersection<'a, N>(
+        &'a self,
+        other: &'a MultiSet<N>,
+    ) -> impl Iterator<Item = (&'a M::Key, usize)> + 'a
+    where
+        N: Map<Key = M::Key, Val: Count> + Lookup<M::Key>,
+    {
+        self.counts()
+            .map(|(value, count)| (value, count.to_usize().min(other.count(value))))
+            .filter(|&(_, count)| count > 0)
+    }
+
+    // Returns the values of `self` that are not in `other`, with their counts.
+    fn only_in<'a, N>(
+        &'a self,
+        other: &'a MultiSet<N>,
+    ) -> impl Iterator<Item = (&'a M::Key, usize)> + 'a
+    where
+        N: Map<Key = M::Key, Val: Count> + Lookup<M::Key>,
+    {
+        self.counts()
+            .filter(|(value, _)| !other.contains(*value))
+            .map(|(value, count)| (value, count.to_usize()))
+    }
+
+    /// Returns `true` if every unique value of `self` is also in `other`, regardless of counts.
+    /// See `MultiSet::contains_occurrences` for inclusion that takes counts into account.
+    /// If both multi-sets keep their values sorted, they are walked together in linear time.
+    /// Otherwise, each unique value of `self` is looked up in `other`.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use multi_containers::{BTreeMultiSet, HashMultiSet};
+    /// let a: HashMultiSet<i32> = HashMultiSet::from([1, 1, 2]);
+    /// let b = BTreeMultiSet::from([1, 2, 3]);
+    /// assert!(a.is_subset(&b));
+    /// assert!(!b.is_subset(&a));
+    /// ```
+    pub fn is_subset<N>(&self, other: &MultiSet<N>) -> bool
+    where
+        N: Map<Key = M::Key, Val: Count> + Lookup<M::Key>,
+    {
+        if self.map.len() > other.map.len() {
+            return false;
+        }
+        match merge_counts(self, other) {
+            Some(mut counts) => counts.all(|(a, b)| a == 0 || b > 0),
+            None => self.counts().all(|(value, _)| other.contains(value)),
+        }
+    }
+
+    /// Returns `true` if every unique value of `other` is also in `self`, regardless of counts.
+    /// See `MultiSet::contains_occurrences` for inclusion that takes counts into account.
+    /// This is `other.is_subset(self)`, so unless both multi-sets are sorted, it looks up each unique value of
+    /// `other` in `self`.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use multi_containers::{BTreeMultiSet, HashMultiSet};
+    /// let a: HashMultiSet<i32> = HashMultiSet::from([1, 2, 3]);
+    /// let b = BTreeMultiSet::from([1, 1, 2]);
+    /// assert!(a.is_superset(&b));
+    /// assert!(!b.is_superset(&a));
+    /// ```
+    pub fn is_superset<N>(&self, other: &MultiSet<N>) -> bool
+    where
+        M: Lookup<<M as Map>::Key>,
+        N: Map<Key = M::Key, Val: Count>,
+    {
+        other.is_subset(self)
+    }
+
+    /// Returns `true` if `self` and `other` have no values in common.
+    /// If both multi-sets keep their values sorted, they are walked together in linear time.
+    /// Otherwise, the unique values of the smaller multi-set are looked up in the larger one.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use multi_containers::{BTreeMultiSet, HashMultiSet};
+    /// let a: HashMultiSet<i32> = HashMultiSet::from([1, 1, 2]);
+    /// assert!(a.is_disjoint(&BTreeMultiSet::from([3, 4])));
+    /// assert!(!a.is_disjoint(&BTreeMultiSet::from([2, 3])));
+    /// ```
+    pub fn is_disjoint<N>(&self, other: &MultiSet<N>) -> bool
+    where
+        M: Lookup<<M as Map>::Key>,
+        N: Map<Key = M::Key, Val: Count> + Lookup<M::Key>,
+    {
+        if let Some(mut counts) = merge_counts(self, other) {
+            return counts.all(|(a, b)| a == 0 || b == 0);
+        }
+        // Look up the values of the smaller multi-set in the larger one.
+        if self.map.len() <= other.map.len() {
+            self.counts().all(|(value, _)| !other.contains(value))
+        } else {
+            other.counts().all(|(value, _)| !self.contains(value))
+        }
+    }
+
+    /// Returns `true` if `self` contains every value of `other` at least as many times as `other` does.
+    /// If both multi-sets keep their values sorted, their counts are compared in a single walk.
+    /// Otherwise, the count of each unique value of `other` is looked up in `self`.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use multi_containers::{BTreeMultiSet, HashMultiSet};
+    /// let a: HashMultiSet<i32> = HashMultiSet::from([1, 1, 2, 3]);
+    /// assert!(a.contains_occurrences(&BTreeMultiSet::from([1, 1, 2])));
+    /// assert!(!a.contains_occurrences(&BTreeMultiSet::from([2, 2])));
+    /// ```
+    pub fn contains_occurrences<N>(&self, other: &MultiSet<N>) -> bool
+    where
+        M: Lookup<<M as Map>::Key>,
+        N: Map<Key = M::Key, Val: Count>,
+    {
+        if other.length > self.length {
+            return false;
+        }
+        match merge_counts(self, other) {
+            Some(mut counts) => counts.all(|(a, b)| a >= b),
+            None => other
+                .counts()
+                .all(|(value, count)| self.count(value) >= count.to_usize()),
+        }
+    }
+}
+
+#[cfg(feature = "rand")]
+impl<M> MultiSet<M>
+where
+    M: Map<Val: Count>,
+{
+    /// Returns a random value of the multi-set, with probability proportional to its count.
+    /// Returns `None` if the multi-set is empty.
+    /// This takes `O(log n)` time with `OrderStatisticMultiSet`, which keeps its counts summed as they change,
+    /// and linear time otherwise.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use multi_containers::HashMultiSet;
+    /// let mut set = HashMultiSet::new();
+    /// set.insert_some("heads", 3);
+    /// set.insert_some("tails", 1);
+    /// let value = set.sample(&mut rand::thread_rng()).unwrap();
+    /// assert!(set.contains(value));
+    /// ```
+    pub fn sample<R>(&self, rng: &mut R) -> Option<&M::Key>
+    where
+        M: Select,
+        R: Rng + ?Sized,
+    {
+        if self.is_empty() {
+            return None;
+        }
+        self.nth(rng.gen_range(0..self.length))
+    }
+
+    /// Returns `amount` random values of the multi-set, each with probability proportional to its count.
+    /// Values are sampled with replacement, so the same value may be returned more often than it occurs.
+    /// This walks the multi-set once, so it takes `O(n + amount log amount)` time for `n` unique values.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use multi_containers::HashMultiSet;
+    /// let mut set = HashMultiSet::new();
+    /// set.insert_some("heads", 3);
+    /// set.insert_some("tails", 1);
+    /// let values = set.sample_many(&mut rand::thread_rng(), 10);
+    /// assert_eq!(values.len(), 10);
+    /// ```
+    pub fn sample_many<R>(&self, rng: &mut R, amount: usize) -> Vec<&M::Key>
+    where
+        R: Rng + ?Sized,
+    {
+        if self.is_empty() {
+            return Vec::new();
+        }
+        let positions = (0..amount).map(|_| rng.gen_range(0..self.length)).collect();
+        values_at(self.counts(), positions)
+    }
+
+    /// Returns `amount` random values of the multi-set, as if drawing them one by one without putting them back.
+    /// A value is returned at most as many times as it occurs, and all values are returned if `amount` is at least
+    /// the length of the multi-set.
+    /// This walks the multi-set once, so it takes `O(n + amount log amount)` time for `n` unique values.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use multi_containers::HashMultiSet;
+    /// let mut set = HashMultiSet::new();
+    /// set.insert_some("heads", 3);
+    /// set.insert_some("tails", 1);
+    /// let values = set.sample_without_replacement(&mut rand::thread_rng(), 10);
+    /// assert_eq!(values.len(), 4);
+    /// assert_eq!(values.iter().filter(|&&&value| value == "tails").count(), 1);
+    /// ```
+    pub fn sample_without_replacement<R>(&self, rng: &mut R, amount: usize) -> Vec<&M::Key>
+    where
+        R: Rng + ?Sized,
+    {
+        let positions = index::sample(rng, self.length, amount.min(self.length)).into_vec();
+        values_at(self.counts(), positions)
+    }
+}
+
+// Returns the values at the given positions of a multi-set, counting duplicates, in the order of the positions.
+// The positions must be less than the length of the multi-set.
+#[cfg(feature = "rand")]
+fn values_at<'a, K, C, I>(counts: I, positions: Vec<usize>) -> Vec<&'a K>
+where
+    K: 'a,
+    C: Count + 'a,
+    I: Iterator<Item = (&'a K, &'a C)>,
+{
+    let mut order = (0..positions.len()).collect::<Vec<_>>();
+    order.sort_unstable_by_key(|&i| positions[i]);
+    let mut order = order.into_iter().peekable();
+    let mut values = vec![None; positions.len()];
+    let mut end = 0;
+    for (value, count) in counts {
+        end += count.to_usize();
+        while let Some(i) = order.next_if(|&i| positions[i] < end) {
+            values[i] = Some(value);
+        }
+    }
+    values
+        .into_iter()
+        .map(|value| value.expect("position out of bounds"))
+        .collect()
+}
+
+// A value and its count, ordered only by its rank.
+struct Ranked<'a, K, R> {
+    rank: R,
+    value: &'a K,
+    count: usize,
+}
+
+impl<K, R: PartialEq> PartialEq for Ranked<'_, K, R> {
+    fn eq(&self, other: &Self) -> bool {
+        self.rank == other.rank
+    }
+}
+
+impl<K, R: Eq> Eq for Ranked<'_, K, R> {}
+
+impl<K, R: Ord> PartialOrd for Ranked<'_, K, R> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl<K, R: Ord> Ord for Ranked<'_, K, R> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.rank.cmp(&other.rank)
+    }
+}
+
+// Returns the `k` (value, count) pairs with the highest rank, highest first.
+// The rank of a pair is computed from its count and its position in the iterator.
+// A min-heap holds the best `k` pairs seen so far, so its top is the pair to evict.
+fn top_k<'a, K, C, I, R, F>(counts: I, k: usize, rank: F) -> Vec<(&'a K, usize)>
+where
+    C: Count + 'a,
+    I: Iterator<Item = (&'a K, &'a C)>,
+    R: Ord,
+    F: Fn(usize, usize) -> R,
+{
+    if k == 0 {
+        return Vec::new();
+    }
+    let mut heap = BinaryHeap::with_capacity(k);
+    for (position, (value, count)) in counts.enumerate() {
+        let count = count.to_usize();
+        let ranked = Ranked {
+            rank: rank(count, position),
+            value,
+            count,
+        };
+        if heap.len() < k {
+            heap.push(Reverse(ranked));
+        } else if heap.peek().is_some_and(|Reverse(min)| ranked > *min) {
+            heap.pop();
+            heap.push(Reverse(ranked));
+        }
+    }
+    // Sorting ascending by `Reverse` puts the highest rank first.
+    heap.into_sorted_vec()
+        .into_iter()
+        .map(|Reverse(ranked)| (ranked.value, ranked.count))
+        .collect()
+}
+
+// Walks two multi-sets that both keep their values sorted, yielding the counts of each value that is in either
+// of them, with zero for a missing value. Returns `None` if either multi-set is unsorted.
+fn merge_counts<'a, M, N>(
+    a: &'a MultiSet<M>,
+    b: &'a MultiSet<N>,
+) -> Option<impl Iterator<Item = (usize, usize)> + 'a>
+where
+    M: Map<Val: Count>,
+    N: Map<Key = M::Key, Val: Count>,
+{
+    let cmp = M::key_order()?;
+    N::key_order()?;
+    let mut a = a.counts().peekable();
+    let mut b = b.counts().peekable();
+    Some(std::iter::from_fn(move || match (a.peek(), b.peek()) {
+        (Some((x, _)), Some((y, _))) => match cmp(x, y) {
+            Ordering::Less => a.next().map(|(_, count)| (count.to_usize(), 0)),
+            Ordering::Greater => b.next().map(|(_, count)| (0, count.to_usize())),
+            Ordering::Equal => Some((a.next()?.1.to_usize(), b.next()?.1.to_usize())),
+        },
+        (Some(_), None) => a.next().map(|(_, count)| (count.to_usize(), 0)),
+        (None, Some(_)) => b.next().map(|(_, count)| (0, count.to_usize())),
+        (None, None) => None,
+    }))
+}
+
+// Expands an iterator over (value, count) pairs into an iterator over values, repeating each value `count`
+// times. This can be iterated from both ends if the underlying iterator can.
+struct Occurrences<'a, K, I> {
+    iter: I,
+    // The values being repeated at either end, and how many more times they should be yielded.
+    front: Option<(&'a K, usize)>,
+    back: Option<(&'a K, usize)>,
+}
+
+impl<'a, K, I> Occurrences<'a, K, I> {
+    fn new(iter: I) -> Self {
+        Occurrences {
+            iter,
+            front: None,
+            back: None,
+        }
+    }
+
+    fn take_one(current: &mut Option<(&'a K, usize)>) -> Option<&'a K> {
+        let (value, remaining) = current.as_mut()?;
+        let value = *value;
+        *remaining -= 1;
+        if *remaining == 0 {
+            *current = None;
+        }
+        Some(value)
+    }
+}
+
+impl<'a, K, C, I> Iterator for Occurrences<'a, K, I>
+where
+    C: Count + 'a,
+    I: Iterator<Item = (&'a K, &'a C)>,
+{
+    type Item = &'a K;
+
+    fn next(&mut self) -> Option<&'a K> {
+        if self.front.is_none() {
+            // Counts are never zero, so a new pair always has something to yield.
+            self.front = self
+                .iter
+                .next()
+                .map(|(value, count)| (value, count.to_usize()));
+        }
+        Self::take_one(&mut self.front).or_else(|| Self::take_one(&mut self.back))
+    }
+}
+
+impl<'a, K, C, I> DoubleEndedIterator for Occurrences<'a, K, I>
+where
+    C: Count + 'a,
+    I: DoubleEndedIterator<Item = (&'a K, &'a C)>,
+{
+    fn next_back(&mut self) -> Option<&'a K> {
+        if self.back.is_none() {
+            self.back = self
+                .iter
+                .next_back()
+                .map(|(value, count)| (value, count.to_usize()));
+        }
+        Self::take_one(&mut self.back).or_else(|| Self::take_one(&mut self.front))
+    }
+}
+
+/// An iterator over the values of a multi-set, including duplicates.
+/// This is constructed by `MultiSet::iter`.
+/// It knows its exact length, and can be iterated from the back if the underlying map can.
+pub struct Iter<'a, M>
+where
+    M: Map<Val: Count> + 'a,
+{
+    inner: Occurrences<'a, M::Key, M::Iter<'a>>,
+    length: usize,
+}
+
+impl<'a, M> Iterator for Iter<'a, M>
+where
+    M: Map<Val: Count> + 'a,
+{
+    type Item = &'a M::Key;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let value = self.inner.next()?;
+        self.length -= 1;
+        Some(value)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.length, Some(self.length))
+    }
+}
+
+impl<'a, M> DoubleEndedIterator for Iter<'a, M>
+where
+    M: Map<Val: Count> + 'a,
+    M::Iter<'a>: DoubleEndedIterator,
+{
+    fn next_back(&mut self) -> Option<Self::Item> {
+        let value = self.inner.next_back()?;
+        self.length -= 1;
+        Some(value)
+    }
+}
+
+impl<'a, M> ExactSizeIterator for Iter<'a, M> where M: Map<Val: Count> + 'a {}
+
+impl<'a, M> FusedIterator for Iter<'a, M>
+where
+    M: Map<Val: Count> + 'a,
+    M::Iter<'a>: FusedIterator,
+{
+}
+
+/// An iterator over the values of a sorted multi-set within a range, including duplicates.
+/// This is constructed by `MultiSet::range`.
+/// Unlike `Iter`, it does not know its exact length, as only the length of the whole multi-set is tracked.
+pub struct Range<'a, Q, M>
+where
+    Q: ?Sized,
+    M: SortedMap<Q, Val: Count> + 'a,
+    M::Key: Borrow<Q>,
+{
+    inner: Occurrences<'a, M::Key, M::RangeIter<'a>>,
+}
+
+impl<'a, Q, M> Iterator for Range<'a, Q, M>
+where
+    Q: ?Sized,
+    M: SortedMap<Q, Val: Count> + 'a,
+    M::Key: Borrow<Q>,
+{
+    type Item = &'a M::Key;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.inner.next()
+    }
+}
+
+impl<'a, Q, M> DoubleEndedIterator for Range<'a, Q, M>
+where
+    Q: ?Sized,
+    M: SortedMap<Q, Val: Count> + 'a,
+    M::Key: Borrow<Q>,
+    M::RangeIter<'a>: DoubleEndedIterator,
+{
+    fn next_back(&mut self) -> Option<Self::Item> {
+        self.inner.next_back()
+    }
+}
+
+impl<'a, Q, M> FusedIterator for Range<'a, Q, M>
+where
+    Q: ?Sized,
+    M: SortedMap<Q, Val: Count> + 'a,
+    M::Key: Borrow<Q>,
+    M::RangeIter<'a>: FusedIterator,
+{
+}
+
+/// An owning iterator over the values of a multi-set, including duplicates.
+/// Each value is cloned for all but its last occurrence.
+/// This is constructed by `MultiSet::into_iter`.
+pub struct IntoIter<M>
+where
+    M: Map<Val: Count>,
+{
+    iter: <M as IntoIterator>::IntoIter,
+    // The value being repeated, and how many more times it should be yielded.
+    current: Option<(M::Key, usize)>,
+    length: usize,
+}
+
+impl<M> Iterator for IntoIter<M>
+where
+    M: Map<Val: Count>,
+    M::Key: Clone,
+{
+    type Item = M::Key;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        // Counts are never zero, so every (value, count) pair yields at least once.
+        let (value, remaining) = match self.current.take() {
+            Some(current) => current,
+            None => {
+                let (value, count) = self.iter.next()?;
+                (value, count.to_usize())
+            }
+        };
+        self.length -= 1;
+        if remaining > 1 {
+            self.current = Some((value.clone(), remaining - 1));
+        }
+        Some(value)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.length, Some(self.length))
+    }
+}
+
+impl<M> ExactSizeIterator for IntoIter<M>
+where
+    M: Map<Val: Count>,
+    M::Key: Clone,
+{
+}
+
+impl<M> FusedIterator for IntoIter<M>
+where
+    M: Map<Val: Count>,
+    M::Key: Clone,
+    <M as IntoIterator>::IntoIter: FusedIterator,
+{
+}
+
+impl<M> IntoIterator for MultiSet<M>
+where
+    M: Map<Val: Count>,
+    M::Key: Clone,
+{
+    type Item = M::Key;
+    type IntoIter = IntoIter<M>;
+
+    fn into_iter(self) -> IntoIter<M> {
+        IntoIter {
+            iter: self.map.into_iter(),
+            current: None,
+            length: self.length,
+        }
+    }
+}
+
+impl<'a, M> IntoIterator for &'a MultiSet<M>
+where
+    M: Map<Val: Count>,
+{
+    type Item = &'a M::Key;
+    type IntoIter = Iter<'a, M>;
+
+    fn into_iter(self) -> Iter<'a, M> {
+        self.iter()
+    }
+}
+
+impl<M, N> AddAssign<&MultiSet<N>> for MultiSet<M>
+where
+    M: Map<Val: Count>,
+    N: Map<Key = M::Key, Val: Count>,
+    M::Key: Clone,
+{
+    /// Adds the counts of `other` to the counts of `self`.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use multi_containers::HashMultiSet;
+    /// let mut a: HashMultiSet<i32> = HashMultiSet::from([1, 1, 2]);
+    /// let b: HashMultiSet<i32> = HashMultiSet::from([1, 3]);
+    /// a += &b;
+    /// assert_eq!(a.count(&1), 3);
+    /// assert_eq!(a.count(&3), 1);
+    /// assert_eq!(a.len(), 5);
+    /// ```
+    fn add_assign(&mut self, other: &MultiSet<N>) {
+        for (value, count) in other.counts() {
+            self.insert_some(value.clone(), count.to_usize());
+        }
+    }
+}
+
+impl<M, N> SubAssign<&MultiSet<N>> for MultiSet<M>
+where
+    M: Map<Val: Count> + Lookup<<M as Map>::Key>,
+    N: Map<Key = M::Key, Val: Count>,
+{
+    /// Subtracts the counts of `other` from the counts of `self`, removing values whose count drops to zero.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use multi_containers::HashMultiSet;
+    /// let mut a: HashMultiSet<i32> = HashMultiSet::from([1, 1, 2]);
+    /// let b: HashMultiSet<i32> = HashMultiSet::from([1, 2, 2, 3]);
+    /// a -= &b;
+    /// assert_eq!(a.count(&1), 1);
+    /// assert_eq!(a.contains(&2), false);
+    /// assert_eq!(a.len(), 1);
+    /// ```
+    fn sub_assign(&mut self, other: &MultiSet<N>) {
+        for (value, count) in other.counts() {
+            self.remove_at_most(value, count.to_usize());
+        }
+    }
+}
+
+impl<M, N> BitOrAssign<&MultiSet<N>> for MultiSet<M>
+where
+    M: Map<Val: Count> + Lookup<<M as Map>::Key>,
+    N: Map<Key = M::Key, Val: Count>,
+    M::Key: Clone,
+{
+    /// Raises the count of each value in `self` to its count in `other`, if that is larger.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use multi_containers::HashMultiSet;
+    /// let mut a: HashMultiSet<i32> = HashMultiSet::from([1, 1, 2]);
+    /// let b: HashMultiSet<i32> = HashMultiSet::from([1, 2, 2, 3]);
+    /// a |= &b;
+    /// assert_eq!(a.count(&1), 2);
+    /// assert_eq!(a.count(&2), 2);
+    /// assert_eq!(a.count(&3), 1);
+    /// assert_eq!(a.len(), 5);
+    /// ```
+    fn bitor_assign(&mut self, other: &MultiSet<N>) {
+        for (value, count) in other.counts() {
+            let count = count.to_usize();
+            let have = self.count(value);
+            if count > have {
+                self.insert_some(value.clone(), count - have);
+            }
+        }
+    }
+}
+
+impl<M, N> BitAndAssign<&MultiSet<N>> for MultiSet<M>
+where
+    M: Map<Val: Count>,
+    N: Map<Key = M::Key, Val: Count> + Lookup<<M as Map>::Key>,
+{
+    /// Lowers the count of each value in `self` to its count in `other`, if that is smaller.
+    /// Values that are not in `other` are removed.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use multi_containers::HashMultiSet;
+    /// let mut a: HashMultiSet<i32> = HashMultiSet::from([1, 1, 2]);
+    /// let b: HashMultiSet<i32> = HashMultiSet::from([1, 3]);
+    /// a &= &b;
+    /// assert_eq!(a.count(&1), 1);
+    /// assert_eq!(a.contains(&2), false);
+    /// assert_eq!(a.len(), 1);
+    /// ```
+    fn bitand_assign(&mut self, other: &MultiSet<N>) {
+        self.retain(|value, count| count.min(other.count(value)));
+    }
+}
+
+// Derives the by-value and by-reference forms of a binary operator from its `*Assign<&MultiSet<N>>` form.
+macro_rules! impl_binary_op {
+    ($op:ident, $method:ident, $op_assign:ident, $method_assign:ident) => {
+        impl<M, N> $op_assign<MultiSet<N>> for MultiSet<M>
+        where
+            MultiSet<M>: for<'a> $op_assign<&'a MultiSet<N>>,
+        {
+            fn $method_assign(&mut self, other: MultiSet<N>) {
+                self.$method_assign(&other);
+            }
+        }
+
+        impl<M, N> $op<&MultiSet<N>> for MultiSet<M>
+        where
+            MultiSet<M>: for<'a> $op_assign<&'a MultiSet<N>>,
+        {
+            type Output = MultiSet<M>;
+
+            fn $method(mut self, other: &MultiSet<N>) -> MultiSet<M> {
+                self.$method_assign(other);
+                self
+            }
+        }
+
+        impl<M, N> $op<MultiSet<N>> for MultiSet<M>
+        where
+            MultiSet<M>: for<'a> $op_assign<&'a MultiSet<N>>,
+        {
+            type Output = MultiSet<M>;
+
+            fn $method(mut self, other: MultiSet<N>) -> MultiSet<M> {
+                self.$method_assign(&other);
+                self
+            }
+        }
+
+        impl<M, N> $op<&MultiSet<N>> for &MultiSet<M>
+        where
+            M: Clone,
+            MultiSet<M>: for<'a> $op_assign<&'a MultiSet<N>>,
+        {
+            type Output = MultiSet<M>;
+
+            fn $method(self, other: &MultiSet<N>) -> MultiSet<M> {
+                let mut result = self.clone();
+                result.$method_assign(other);
+                result
+            }
+        }
+    };
+}
+
+impl_binary_op!(Add, add, AddAssign, add_assign);
+impl_binary_op!(Sub, sub, SubAssign, sub_assign);
+impl_binary_op!(BitOr, bitor, BitOrAssign, bitor_assign);
+impl_binary_op!(BitAnd, bitand, BitAndAssign, bitand_assign);
+
+impl <T, M> Extend<T> for MultiSet<M>
+where
+    M: Map<Key = T, Val: Count>,
+{
+    fn extend<I>(&mut self, iter: I)
+    where
+        I: IntoIterator<Item = T>,
+    {
+        for value in iter {
+            self.insert(value);
+        }
+    }
+}
+
+impl<T, M> FromIterator<T> for MultiSet<M>
+where
+    M: DefaultMap<Key = T, Val: Count>,
+{
+    fn from_iter<I>(iter: I) -> Self
+    where
+        I: IntoIterator<Item = T>,
+    {
+        let mut set = MultiSet::default();
+        set.extend(iter);
+        set
+    }
+}
+
+impl<M, const N: usize> From<[M::Key; N]> for MultiSet<M>
+where
+    M: DefaultMap<Val: Count>,
+    M::Key: Clone,
 {
     fn from(array: [M::Key; N]) -> Self {
         array.into_iter().collect::<Self>()
@@ -427,77 +1896,417 @@ mod tests {
                 use crate::MultiSet;
                 use crate::MultiSetBuilder;
 
+                #[cfg(feature = "rand")]
+                #[test]
+                fn sampling() {
+                    use rand::{rngs::StdRng, SeedableRng};
+                    let mut rng = StdRng::seed_from_u64(0);
+                    let mut set = $set_maker;
+                    assert_eq!(set.sample(&mut rng), None);
+                    assert!(set.sample_many(&mut rng, 3).is_empty());
+                    assert!(set.sample_without_replacement(&mut rng, 3).is_empty());
+                    set.insert_some(1, 3);
+                    set.insert_some(2, 1);
+                    set.insert_some(3, 5);
+                    set.remove_at_most(&3, 5);
+                    for _ in 0..100 {
+                        assert_ne!(set.sample(&mut rng), Some(&3));
+                    }
+                    let values = set.sample_many(&mut rng, 4000);
+                    assert_eq!(values.len(), 4000);
+                    let ones = values.iter().filter(|&&&value| value == 1).count();
+                    assert!((2800..3200).contains(&ones));
+                    let mut values = set.sample_without_replacement(&mut rng, 10);
+                    values.sort();
+                    assert_eq!(values, vec![&1, &1, &1, &2]);
+                    let values = set.sample_without_replacement(&mut rng, 2);
+                    assert_eq!(values.len(), 2);
+                    assert!(values.iter().all(|&value| value == &1 || value == &2));
+                }
+
+                #[test]
+                fn try_remove_exact() {
+                    let mut set = $set_maker;
+                    set.insert_some(1, 3);
+                    assert_eq!(set.try_remove_exact(&1, 0), Ok(3));
+                    assert_eq!(set.try_remove_exact(&1, 2), Ok(3));
+                    set.check_invariants();
+                    assert_eq!(
+                        set.try_remove_exact(&1, 2),
+                        Err(crate::counts::InsufficientCount {
+                            requested: 2,
+                            available: 1
+                        })
+                    );
+                    set.check_invariants();
+                    assert_eq!(set.count(&1), 1);
+                    assert_eq!(set.try_remove_exact(&1, 1), Ok(1));
+                    assert!(!set.contains(&1));
+                    assert!(set.try_remove_exact(&2, 1).is_err());
+                    assert!(set.is_empty());
+                }
+
                 #[test]
                 fn insert() {
                     let mut set = $set_maker;
                     assert_eq!(set.insert(1), 0);
+                    set.check_invariants();
                     assert_eq!(set.insert(1), 1);
+                    set.check_invariants();
                     assert_eq!(set.insert(2), 0);
+                    set.check_invariants();
                     assert_eq!(set.insert(2), 1);
+                    set.check_invariants();
                     assert_eq!(set.insert(2), 2);
+                    set.check_invariants();
                 }
 
                 #[test]
                 fn insert_some() {
                     let mut set = $set_maker;
                     assert_eq!(set.insert_some(1, 2), 0);
+                    set.check_invariants();
                     assert_eq!(set.insert_some(1, 2), 2);
+                    set.check_invariants();
                     assert_eq!(set.insert_some(2, 3), 0);
+                    set.check_invariants();
                     assert_eq!(set.insert_some(2, 3), 3);
+                    set.check_invariants();
                     assert_eq!(set.insert_some(2, 3), 6);
+                    set.check_invariants();
+                }
+
+                #[test]
+                fn insert_some_zero() {
+                    let mut set = $set_maker;
+                    assert_eq!(set.insert_some(1, 0), 0);
+                    set.check_invariants();
+                    assert!(!set.contains(&1));
+                    assert_eq!(set.insert_some(1, 2), 0);
+                    set.check_invariants();
+                    assert_eq!(set.insert_some(1, 0), 2);
+                    set.check_invariants();
+                    assert_eq!(set.len(), 2);
                 }
 
                 #[test]
                 fn set_count() {
                     let mut set = $set_maker;
                     assert_eq!(set.set_count(1, 2), 0);
+                    set.check_invariants();
                     assert_eq!(set.set_count(1, 2), 2);
+                    set.check_invariants();
                     assert_eq!(set.set_count(2, 3), 0);
+                    set.check_invariants();
                     assert_eq!(set.set_count(2, 3), 3);
+                    set.check_invariants();
                     assert_eq!(set.set_count(2, 3), 3);
+                    set.check_invariants();
                 }
 
                 #[test]
                 fn remove() {
                     let mut set = $set_maker;
                     set.insert_some(1, 2);
+                    set.check_invariants();
                     set.insert_some(2, 3);
+                    set.check_invariants();
                     assert_eq!(set.remove(&1), 2);
+                    set.check_invariants();
                     assert_eq!(set.remove(&1), 1);
+                    set.check_invariants();
                     assert_eq!(set.remove(&1), 0);
+                    set.check_invariants();
                     assert_eq!(set.remove(&2), 3);
+                    set.check_invariants();
                     assert_eq!(set.remove(&2), 2);
+                    set.check_invariants();
                     assert_eq!(set.remove(&2), 1);
+                    set.check_invariants();
                 }
 
                 #[test]
                 fn remove_at_most() {
                     let mut set = $set_maker;
                     set.insert_some(1, 2);
+                    set.check_invariants();
                     set.insert_some(2, 3);
+                    set.check_invariants();
                     assert_eq!(set.remove_at_most(&1, 1), 2);
+                    set.check_invariants();
                     assert_eq!(set.remove_at_most(&1, 1), 1);
+                    set.check_invariants();
                     assert_eq!(set.remove_at_most(&2, 2), 3);
+                    set.check_invariants();
                     assert_eq!(set.remove_at_most(&2, 2), 1);
+                    set.check_invariants();
                     assert_eq!(set.remove_at_most(&2, 2), 0);
+                    set.check_invariants();
                 }
 
                 #[test]
                 fn remove_all() {
                     let mut set = $set_maker;
                     set.insert_some(1, 2);
+                    set.check_invariants();
                     set.insert_some(2, 3);
+                    set.check_invariants();
                     assert_eq!(set.remove_all(&1), 2);
+                    set.check_invariants();
                     assert_eq!(set.remove_all(&1), 0);
+                    set.check_invariants();
                     assert_eq!(set.remove_all(&2), 3);
+                    set.check_invariants();
                     assert_eq!(set.remove_all(&2), 0);
+                    set.check_invariants();
+                }
+
+                #[test]
+                fn retain() {
+                    let mut set = $set_maker;
+                    set.insert_some(1, 2);
+                    set.check_invariants();
+                    set.insert_some(2, 3);
+                    set.check_invariants();
+                    set.insert_some(3, 4);
+                    set.check_invariants();
+                    set.retain(|&v, count| match v {
+                        1 => count,
+                        2 => 0,
+                        _ => count + 1,
+                    });
+                    set.check_invariants();
+                    assert_eq!(set.count(&1), 2);
+                    assert_eq!(set.count(&2), 0);
+                    assert_eq!(set.count(&3), 5);
+                    assert_eq!(set.len(), 7);
+                }
+
+                #[test]
+                fn reserve_and_shrink_to_fit() {
+                    let mut set = $set_maker;
+                    set.reserve(100);
+                    set.insert_some(1, 2);
+                    set.check_invariants();
+                    set.insert_some(2, 3);
+                    set.check_invariants();
+                    set.shrink_to_fit();
+                    set.check_invariants();
+                    assert!(set.capacity() >= 2);
+                    assert_eq!(set.count(&1), 2);
+                    assert_eq!(set.len(), 5);
+                }
+
+                #[test]
+                fn arithmetic() {
+                    let mut a = $set_maker;
+                    a.insert_some(1, 2);
+                    a.insert_some(2, 1);
+                    let mut b = $set_maker;
+                    b.insert_some(1, 1);
+                    b.insert_some(2, 2);
+                    b.insert_some(3, 1);
+
+                    let sum = &a + &b;
+                    sum.check_invariants();
+                    assert!(unordered_elements_are(
+                        sum.counts().map(|(&k, &v)| (k, v)),
+                        vec![(1, 3), (2, 3), (3, 1)]
+                    ));
+                    assert!(unordered_elements_are(
+                        a.sum(&b).map(|(&k, v)| (k, v)),
+                        vec![(1, 3), (2, 3), (3, 1)]
+                    ));
+
+                    let difference = &a - &b;
+                    difference.check_invariants();
+                    assert!(unordered_elements_are(
+                        difference.counts().map(|(&k, &v)| (k, v)),
+                        vec![(1, 1)]
+                    ));
+                    assert!(unordered_elements_are(
+                        a.difference(&b).map(|(&k, v)| (k, v)),
+                        vec![(1, 1)]
+                    ));
+
+                    let union = &a | &b;
+                    union.check_invariants();
+                    assert!(unordered_elements_are(
+                        union.counts().map(|(&k, &v)| (k, v)),
+                        vec![(1, 2), (2, 2), (3, 1)]
+                    ));
+                    assert!(unordered_elements_are(
+                        a.union(&b).map(|(&k, v)| (k, v)),
+                        vec![(1, 2), (2, 2), (3, 1)]
+                    ));
+
+                    let intersection = &a & &b;
+                    intersection.check_invariants();
+                    assert!(unordered_elements_are(
+                        intersection.counts().map(|(&k, &v)| (k, v)),
+                        vec![(1, 1), (2, 1)]
+                    ));
+                    assert!(unordered_elements_are(
+                        a.intersection(&b).map(|(&k, v)| (k, v)),
+                        vec![(1, 1), (2, 1)]
+                    ));
+                }
+
+                #[test]
+                fn subset_superset_disjoint() {
+                    let mut a = $set_maker;
+                    a.insert_some(1, 2);
+                    a.insert_some(2, 1);
+                    let mut b = $set_maker;
+                    b.insert_some(1, 1);
+                    b.insert_some(2, 1);
+                    b.insert_some(3, 1);
+                    let mut c = $set_maker;
+                    c.insert_some(4, 1);
+                    let empty = $set_maker;
+
+                    assert!(a.is_subset(&b));
+                    assert!(!b.is_subset(&a));
+                    assert!(b.is_superset(&a));
+                    assert!(!a.is_superset(&b));
+                    assert!(a.is_subset(&a));
+                    assert!(empty.is_subset(&a));
+                    assert!(a.is_disjoint(&c));
+                    assert!(c.is_disjoint(&a));
+                    assert!(!a.is_disjoint(&b));
+                    assert!(empty.is_disjoint(&empty));
+                }
+
+                #[test]
+                fn contains_occurrences() {
+                    let mut a = $set_maker;
+                    a.insert_some(1, 2);
+                    a.insert_some(2, 1);
+                    let mut b = $set_maker;
+                    b.insert_some(1, 1);
+                    b.insert_some(2, 1);
+                    let empty = $set_maker;
+
+                    assert!(a.contains_occurrences(&b));
+                    assert!(!b.contains_occurrences(&a));
+                    assert!(a.contains_occurrences(&a));
+                    assert!(a.contains_occurrences(&empty));
+                    assert!(!empty.contains_occurrences(&a));
+                    b.insert(3);
+                    assert!(!a.contains_occurrences(&b));
+                }
+
+                #[test]
+                fn most_and_least_common() {
+                    let mut set = $set_maker;
+                    assert_eq!(set.most_common(2), vec![]);
+                    set.insert_some(1, 3);
+                    set.insert_some(2, 5);
+                    set.insert_some(3, 1);
+                    set.insert_some(4, 4);
+                    assert_eq!(set.most_common(0), vec![]);
+                    assert_eq!(set.most_common(2), vec![(&2, 5), (&4, 4)]);
+                    assert_eq!(set.least_common(2), vec![(&3, 1), (&1, 3)]);
+                    assert_eq!(
+                        set.most_common(10),
+                        vec![(&2, 5), (&4, 4), (&1, 3), (&3, 1)]
+                    );
+                    assert_eq!(
+                        set.least_common(10),
+                        vec![(&3, 1), (&1, 3), (&4, 4), (&2, 5)]
+                    );
+                }
+
+                #[test]
+                fn most_common_ties_follow_iteration_order() {
+                    let mut set = $set_maker;
+                    for value in 0..10 {
+                        set.insert_some(value, 1 + value % 2);
+                    }
+                    let order = set.counts().map(|(&value, _)| value).collect::<Vec<_>>();
+                    let odd = order.iter().filter(|&&v| v % 2 == 1).collect::<Vec<_>>();
+                    let even = order.iter().filter(|&&v| v % 2 == 0).collect::<Vec<_>>();
+                    let most = set.most_common(3);
+                    assert_eq!(most.iter().map(|&(v, _)| v).collect::<Vec<_>>(), odd[..3]);
+                    let least = set.least_common(3);
+                    assert_eq!(least.iter().map(|&(v, _)| v).collect::<Vec<_>>(), even[..3]);
+                }
+
+                #[test]
+                fn elements_with_count() {
+                    let mut set = $set_maker;
+                    set.insert_some(1, 2);
+                    set.insert_some(2, 1);
+                    set.insert_some(3, 2);
+                    assert!(unordered_elements_are(
+                        set.elements_with_count(2).cloned(),
+                        vec![1, 3]
+                    ));
+                    assert_eq!(set.elements_with_count(3).count(), 0);
+                }
+
+                #[test]
+                fn arithmetic_assign() {
+                    let mut a = $set_maker;
+                    a.insert_some(1, 2);
+                    let mut b = $set_maker;
+                    b.insert_some(1, 3);
+                    b.insert_some(2, 1);
+
+                    a += &b;
+                    a.check_invariants();
+                    assert_eq!(a.len(), 6);
+                    a -= &b;
+                    a.check_invariants();
+                    assert_eq!(a.len(), 2);
+                    a |= &b;
+                    a.check_invariants();
+                    assert_eq!(a.len(), 4);
+                    a &= b.clone();
+                    a.check_invariants();
+                    assert_eq!(a, b);
+                    a -= b;
+                    a.check_invariants();
+                    assert!(a.is_empty());
+                }
+
+                #[test]
+                fn drain() {
+                    let mut set = $set_maker;
+                    set.insert_some(1, 2);
+                    set.check_invariants();
+                    set.insert_some(2, 3);
+                    set.check_invariants();
+                    assert!(unordered_elements_are(set.drain(), vec![(1, 2), (2, 3)]));
+                    set.check_invariants();
+                    assert!(set.is_empty());
+                }
+
+                #[test]
+                fn extract_if() {
+                    let mut set = $set_maker;
+                    set.insert_some(1, 2);
+                    set.check_invariants();
+                    set.insert_some(2, 3);
+                    set.check_invariants();
+                    set.insert_some(3, 4);
+                    set.check_invariants();
+                    assert!(unordered_elements_are(
+                        set.extract_if(|&v, count| v == 1 || count == 4),
+                        vec![(1, 2), (3, 4)]
+                    ));
+                    set.check_invariants();
+                    assert_eq!(set.len(), 3);
+                    assert_eq!(set.count(&2), 3);
                 }
 
                 #[test]
                 fn contains() {
                     let mut set = $set_maker;
                     set.insert_some(1, 2);
+                    set.check_invariants();
                     set.insert_some(2, 3);
+                    set.check_invariants();
                     assert!(set.contains(&1));
                     assert!(set.contains(&2));
                     assert!(!set.contains(&3));
@@ -507,7 +2316,9 @@ mod tests {
                 fn count() {
                     let mut set = $set_maker;
                     set.insert_some(1, 2);
+                    set.check_invariants();
                     set.insert_some(2, 3);
+                    set.check_invariants();
                     assert_eq!(set.count(&1), 2);
                     assert_eq!(set.count(&2), 3);
                     assert_eq!(set.count(&3), 0);
@@ -518,8 +2329,10 @@ mod tests {
                     let mut set = $set_maker;
                     assert!(set.is_empty());
                     set.insert(1);
+                    set.check_invariants();
                     assert!(!set.is_empty());
                     set.remove(&1);
+                    set.check_invariants();
                     assert!(set.is_empty());
                 }
 
@@ -528,10 +2341,13 @@ mod tests {
                     let mut set = $set_maker;
                     assert_eq!(set.len(), 0);
                     set.insert(1);
+                    set.check_invariants();
                     assert_eq!(set.len(), 1);
                     set.insert(1);
+                    set.check_invariants();
                     assert_eq!(set.len(), 2);
                     set.remove_all(&1);
+                    set.check_invariants();
                     assert_eq!(set.len(), 0);
                 }
 
@@ -539,18 +2355,65 @@ mod tests {
                 fn iter() {
                     let mut set = $set_maker;
                     set.insert_some(1, 2);
+                    set.check_invariants();
                     set.insert_some(2, 3);
+                    set.check_invariants();
                     assert!(unordered_elements_are(
                         set.iter().cloned(),
                         vec![1, 1, 2, 2, 2]
                     ));
                 }
 
+                #[test]
+                fn into_iter() {
+                    let mut set = $set_maker;
+                    set.insert_some(1, 2);
+                    set.insert_some(2, 3);
+                    assert!(unordered_elements_are(
+                        (&set).into_iter().cloned(),
+                        vec![1, 1, 2, 2, 2]
+                    ));
+                    let iter = set.into_iter();
+                    assert_eq!(iter.size_hint(), (5, Some(5)));
+                    assert!(unordered_elements_are(iter, vec![1, 1, 2, 2, 2]));
+                }
+
+                #[test]
+                fn into_counts() {
+                    let mut set = $set_maker;
+                    set.insert_some(1, 2);
+                    set.insert_some(2, 3);
+                    assert!(unordered_elements_are(
+                        set.into_counts(),
+                        vec![(1, 2), (2, 3)]
+                    ));
+                }
+
+                #[test]
+                fn iter_len() {
+                    let mut set = $set_maker;
+                    set.insert_some(1, 2);
+                    set.insert_some(2, 3);
+                    let mut iter = set.iter();
+                    assert_eq!(iter.len(), 5);
+                    iter.next();
+                    iter.next();
+                    iter.next();
+                    assert_eq!(iter.len(), 2);
+                    iter.next();
+                    iter.next();
+                    assert_eq!(iter.len(), 0);
+                    assert_eq!(iter.next(), None);
+                    assert_eq!(iter.next(), None);
+                }
+
                 #[test]
                 fn iter_counts() {
                     let mut set = $set_maker;
                     set.insert_some(1, 2);
+                    set.check_invariants();
                     set.insert_some(2, 3);
+                    set.check_invariants();
                     assert!(unordered_elements_are(
                         set.counts().map(|(k, v)| (k.clone(), v.clone())),
                         vec![(1, 2), (2, 3)]
@@ -564,6 +2427,7 @@ mod tests {
                                            // Because we are in a macro, we don't actually know the type of the set.
                                            // So, we let the compiler infer it using the mut variable.
                     set = vec![1, 1, 2, 2, 2].into_iter().collect::<MultiSet<_>>();
+                    set.check_invariants();
                     assert!(unordered_elements_are(
                         set.iter().cloned(),
                         vec![1, 1, 2, 2, 2]
@@ -577,6 +2441,7 @@ mod tests {
                                            // Because we are in a macro, we don't actually know the type of the set.
                                            // So, we let the compiler infer it using the mut variable.
                     set = MultiSet::from([1, 1, 2, 2, 2]);
+                    set.check_invariants();
                     assert!(unordered_elements_are(
                         set.iter().cloned(),
                         vec![1, 1, 2, 2, 2]
@@ -590,7 +2455,38 @@ mod tests {
         ($mod_name:ident, $set_maker:expr) => {
             mod $mod_name {
                 use crate::test_utils::is_sorted;
-                use crate::MultiSetBuilder;
+                use crate::{HashMultiSet, MultiSetBuilder};
+
+                #[test]
+                fn sorted_inclusion_matches_lookups() {
+                    let sets = [
+                        vec![],
+                        vec![(1, 1)],
+                        vec![(1, 2), (2, 1)],
+                        vec![(1, 1), (2, 1), (3, 1)],
+                        vec![(2, 3), (4, 1)],
+                        vec![(4, 2)],
+                    ];
+                    for a in &sets {
+                        for b in &sets {
+                            let mut x = $set_maker;
+                            for &(value, count) in a {
+                                x.insert_some(value, count);
+                            }
+                            let mut y = $set_maker;
+                            for &(value, count) in b {
+                                y.insert_some(value, count);
+                            }
+                            let hx: HashMultiSet<_> = x.iter().cloned().collect();
+                            let hy: HashMultiSet<_> = y.iter().cloned().collect();
+                            assert_eq!(x.is_subset(&y), hx.is_subset(&hy));
+                            assert_eq!(x.is_superset(&y), hx.is_superset(&hy));
+                            assert_eq!(x.is_disjoint(&y), hx.is_disjoint(&hy));
+                            assert_eq!(x.contains_occurrences(&y), hx.contains_occurrences(&hy));
+                            assert_eq!(x.is_subset(&hy), hx.is_subset(&y));
+                        }
+                    }
+                }
 
                 #[test]
                 fn range() {
@@ -603,6 +2499,61 @@ mod tests {
                     assert!(is_sorted(set.range(1..2).cloned()));
                 }
 
+                #[test]
+                fn iter_rev() {
+                    let mut set = $set_maker;
+                    set.insert_some(1, 2);
+                    set.insert_some(2, 3);
+                    assert_eq!(
+                        set.iter().rev().collect::<Vec<_>>(),
+                        vec![&2, &2, &2, &1, &1]
+                    );
+                    assert_eq!(set.range(..2).rev().collect::<Vec<_>>(), vec![&1, &1]);
+                }
+
+                #[test]
+                fn iter_both_ends() {
+                    let mut set = $set_maker;
+                    set.insert_some(1, 3);
+                    let mut iter = set.iter();
+                    assert_eq!(iter.next(), Some(&1));
+                    assert_eq!(iter.next_back(), Some(&1));
+                    assert_eq!(iter.len(), 1);
+                    assert_eq!(iter.next_back(), Some(&1));
+                    assert_eq!(iter.next(), None);
+                    assert_eq!(iter.next_back(), None);
+                    let mut range = set.range(1..);
+                    assert_eq!(range.next_back(), Some(&1));
+                    assert_eq!(range.next(), Some(&1));
+                    assert_eq!(range.next(), Some(&1));
+                    assert_eq!(range.next_back(), None);
+                }
+
+                #[test]
+                fn order_statistics() {
+                    let mut set = $set_maker;
+                    assert_eq!(set.nth(0), None);
+                    assert_eq!(set.median(), None);
+                    assert_eq!(set.quantile(0.5), None);
+                    for i in 0..20 {
+                        set.insert_some(i * 2, i as usize % 3 + 1);
+                    }
+                    set.remove(&10);
+                    for index in 0..=set.len() {
+                        assert_eq!(set.nth(index), set.iter().nth(index));
+                    }
+                    for value in 0..42 {
+                        assert_eq!(set.rank(&value), set.range(..value).count());
+                        assert_eq!(
+                            set.count_in_range(value..value + 5),
+                            set.range(value..value + 5).count()
+                        );
+                    }
+                    assert_eq!(set.quantile(0.0), set.nth(0));
+                    assert_eq!(set.quantile(1.0), set.nth(set.len() - 1));
+                    assert_eq!(set.median(), set.nth((set.len() - 1) / 2));
+                }
+
                 #[test]
                 fn range_counts() {
                     let mut set = $set_maker;
@@ -627,10 +2578,266 @@ mod tests {
 
     base_test_suite!(hash_values, MultiSetBuilder::hash_values().build());
 
+    #[test]
+    fn arithmetic_across_backends() {
+        use crate::MultiSetBuilder;
+        let mut a = MultiSetBuilder::hash_values().build();
+        a.insert_some(1, 2);
+        a.insert_some(2, 1);
+        let mut b = MultiSetBuilder::sorted_values().build();
+        b.insert_some(2, 2);
+        b.insert_some(3, 1);
+        let sum = a.clone() + &b;
+        sum.check_invariants();
+        assert_eq!(sum.len(), 6);
+        assert_eq!(b.sum(&a).count(), 3);
+        let difference = b - a;
+        difference.check_invariants();
+        assert_eq!(
+            difference.counts().collect::<Vec<_>>(),
+            vec![(&2, &1), (&3, &1)]
+        );
+    }
+
+    #[test]
+    fn sum_saturates() {
+        use crate::MultiSetBuilder;
+        let mut a = MultiSetBuilder::sorted_values().build();
+        a.insert_some(1, usize::MAX);
+        let mut b = MultiSetBuilder::sorted_values().build();
+        b.insert_some(1, 1);
+        b.insert_some(2, 1);
+        assert_eq!(
+            a.sum(&b).collect::<Vec<_>>(),
+            vec![(&1, usize::MAX), (&2, 1)]
+        );
+    }
+
+    #[test]
+    fn inclusion_across_backends() {
+        use crate::MultiSetBuilder;
+        let mut a = MultiSetBuilder::hash_values().build();
+        a.insert_some(1, 2);
+        let mut b = MultiSetBuilder::sorted_values().build();
+        b.insert_some(1, 3);
+        b.insert_some(2, 1);
+        assert!(a.is_subset(&b));
+        assert!(b.is_superset(&a));
+        assert!(!a.is_disjoint(&b));
+        assert!(b.contains_occurrences(&a));
+        assert!(!a.contains_occurrences(&b));
+    }
+
+    type DeterministicHasher =
+        std::hash::BuildHasherDefault<std::collections::hash_map::DefaultHasher>;
+
+    // Collecting only infers the default hasher, so a custom hasher cannot run the base suite.
+    #[test]
+    fn with_hasher() {
+        use crate::MultiSetBuilder;
+        let mut set =
+            MultiSetBuilder::hash_values_with_hasher(DeterministicHasher::default()).build();
+        set.insert_some(1, 3);
+        set.insert(2);
+        set.check_invariants();
+        assert_eq!(set.remove(&1), 3);
+        set.check_invariants();
+        assert_eq!(set.count(&1), 2);
+        set.extend([2, 3]);
+        set.check_invariants();
+        assert!(crate::test_utils::unordered_elements_are(
+            set.iter().cloned(),
+            vec![1, 1, 2, 2, 3]
+        ));
+    }
+
+    #[test]
+    fn with_hasher_is_deterministic() {
+        use crate::HashMultiSet;
+        let make_set = || {
+            let mut set: HashMultiSet<i32, DeterministicHasher> =
+                HashMultiSet::with_capacity_and_hasher(16, DeterministicHasher::default());
+            for i in 0..100 {
+                set.insert(i % 17);
+            }
+            set
+        };
+        let a = make_set();
+        let b = make_set();
+        assert!(a.iter().eq(b.iter()));
+    }
+
     base_test_suite!(sorted_values, MultiSetBuilder::sorted_values().build());
 
     sorted_test_suite!(
         sorted_values_sorted,
         MultiSetBuilder::sorted_values().build()
     );
+
+    base_test_suite!(
+        order_statistic_values,
+        MultiSetBuilder::order_statistic_values().build()
+    );
+
+    sorted_test_suite!(
+        order_statistic_values_sorted,
+        MultiSetBuilder::order_statistic_values().build()
+    );
+
+    base_test_suite!(
+        sorted_vec_values,
+        MultiSetBuilder::sorted_vec_values().build()
+    );
+
+    sorted_test_suite!(
+        sorted_vec_values_sorted,
+        MultiSetBuilder::sorted_vec_values().build()
+    );
+
+    #[test]
+    fn freeze_and_from_unsorted() {
+        use crate::{BTreeMultiSet, SortedVecMultiSet};
+        let values = (0..200).map(|i| (i * 7) % 13).collect::<Vec<_>>();
+        let set = values.iter().copied().collect::<BTreeMultiSet<_>>();
+        let frozen = set.clone().freeze();
+        frozen.check_invariants();
+        let unsorted = SortedVecMultiSet::from_unsorted(values);
+        unsorted.check_invariants();
+        assert_eq!(frozen, unsorted);
+        assert!(frozen.iter().eq(set.iter()));
+        assert!(frozen.range_counts(3..=5).eq(set.range_counts(3..=5)));
+    }
+
+    #[cfg(feature = "indexmap")]
+    base_test_suite!(
+        insertion_ordered_values,
+        MultiSetBuilder::insertion_ordered_values().build()
+    );
+
+    #[test]
+    #[cfg(feature = "indexmap")]
+    fn insertion_order() {
+        use crate::IndexMultiSet;
+        let mut set = IndexMultiSet::new();
+        for value in ["c", "a", "c", "b", "a", "d"] {
+            set.insert(value);
+        }
+        assert!(set.iter().eq(&["c", "c", "a", "a", "b", "d"]));
+        set.remove_all("a");
+        set.check_invariants();
+        assert!(set.iter().eq(&["c", "c", "b", "d"]));
+        assert_eq!(set.nth(2), Some(&"b"));
+    }
+
+    base_test_suite!(
+        hash_values_u16_counts,
+        MultiSetBuilder::hash_values_with_count_type::<_, u16>().build()
+    );
+
+    base_test_suite!(
+        sorted_values_u32_counts,
+        MultiSetBuilder::sorted_values_with_count_type::<_, u32>().build()
+    );
+
+    sorted_test_suite!(
+        sorted_values_u32_counts_sorted,
+        MultiSetBuilder::sorted_values_with_count_type::<_, u32>().build()
+    );
+
+    base_test_suite!(
+        sorted_vec_values_u32_counts,
+        MultiSetBuilder::sorted_vec_values_with_count_type::<_, u32>().build()
+    );
+
+    #[cfg(feature = "indexmap")]
+    base_test_suite!(
+        insertion_ordered_values_u16_counts,
+        MultiSetBuilder::insertion_ordered_values_with_count_type::<_, u16>().build()
+    );
+
+    #[test]
+    fn count_overflow() {
+        use crate::counts::CountOverflow;
+        use crate::MultiSetBuilder;
+        let mut set = MultiSetBuilder::hash_values_with_count_type::<_, u8>().build();
+        assert_eq!(set.insert_some(1, 255), 0);
+        assert_eq!(set.try_insert_some(1, 1), Err(CountOverflow));
+        assert_eq!(set.try_insert_some(2, 256), Err(CountOverflow));
+        set.check_invariants();
+        assert_eq!(set.len(), 255);
+        assert_eq!(set.saturating_insert_some(2, 300), 0);
+        assert_eq!(set.count(&2), 255);
+        assert_eq!(set.try_set_count(2, 1000), Err(CountOverflow));
+        assert_eq!(set.try_set_count(2, 3), Ok(255));
+        set.check_invariants();
+        assert_eq!(set.len(), 258);
+        let mut set: crate::HashMultiSet<_> = crate::HashMultiSet::new();
+        set.insert_some(1, usize::MAX);
+        assert_eq!(set.try_insert_some(2, 1), Err(CountOverflow));
+        assert_eq!(set.saturating_insert_some(2, 1), 0);
+        assert!(!set.contains(&2));
+        set.check_invariants();
+    }
+
+    #[test]
+    #[should_panic(expected = "count overflow")]
+    fn insert_past_count_type_panics() {
+        use crate::MultiSetBuilder;
+        let mut set = MultiSetBuilder::sorted_values_with_count_type::<_, u8>().build();
+        set.insert_some(1, 255);
+        set.insert(1);
+    }
+
+    #[test]
+    fn non_zero_counts_are_removed() {
+        use crate::MultiSetBuilder;
+        let mut set =
+            MultiSetBuilder::hash_values_with_count_type::<_, std::num::NonZeroU8>().build();
+        set.insert_some(1, 2);
+        assert_eq!(set.remove_at_most(&1, 5), 2);
+        assert!(set.is_empty());
+        set.insert_some(1, 2);
+        set.retain(|_, _| 0);
+        assert!(set.is_empty());
+        set.check_invariants();
+    }
+
+    #[test]
+    fn try_remove_all_of_is_all_or_nothing() {
+        use crate::counts::InsufficientCount;
+        use crate::MultiSetBuilder;
+        let mut set = MultiSetBuilder::hash_values().build();
+        set.insert_some(1, 3);
+        set.insert_some(2, 1);
+        let mut request = MultiSetBuilder::sorted_values_with_count_type::<_, u8>().build();
+        request.insert_some(1, 2);
+        request.insert_some(2, 2);
+        assert_eq!(
+            set.try_remove_all_of(&request),
+            Err(InsufficientCount {
+                requested: 2,
+                available: 1
+            })
+        );
+        set.check_invariants();
+        assert_eq!(set.len(), 4);
+        request.remove_all(&2);
+        assert_eq!(set.try_remove_all_of(&request), Ok(()));
+        set.check_invariants();
+        assert_eq!(set.count(&1), 1);
+        assert_eq!(set.count(&2), 1);
+    }
+
+    #[test]
+    fn nth_of_hash_values() {
+        use crate::MultiSetBuilder;
+        let mut set = MultiSetBuilder::hash_values().build();
+        set.insert_some(1, 2);
+        set.insert_some(2, 1);
+        let values = set.iter().collect::<Vec<_>>();
+        for (index, value) in values.into_iter().enumerate() {
+            assert_eq!(set.nth(index), Some(value));
+        }
+        assert_eq!(set.nth(3), None);
+    }
 }
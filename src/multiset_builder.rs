@@ -1,16 +1,56 @@
+use crate::counts::Count;
+use crate::maps::{DefaultMap, Map};
+use crate::order_statistic_map::OrderStatisticMap;
+use crate::sorted_vec_map::SortedVecMap;
 use crate::MultiSet;
+#[cfg(feature = "indexmap")]
+use indexmap::IndexMap;
 use std::collections::{BTreeMap, HashMap};
-use std::hash::Hash;
+use std::hash::{BuildHasher, Hash};
 
 /// A builder for a multi-set. This struct does nothing by itself, but it is used to chain method calls to
 /// configure the multi-set before building it.
 pub struct MultiSetBuilder {}
 
 impl MultiSetBuilder {
-    /// Configures the multi-set to use a hashmap.
+    /// Configures the multi-set to use a hashmap with the default hasher.
+    /// See `hash_values_with_hasher` for a custom hasher.
     pub fn hash_values<K, S>() -> MultiSetBuilderWithVals<HashMap<K, usize, S>>
     where
         K: Hash + Eq,
+        HashMap<K, usize, S>: DefaultMap,
+    {
+        Self::with_map_type()
+    }
+
+    /// Configures the multi-set to use a hashmap with the given hasher.
+    /// Counts are stored as `usize`; for another count type with a default hasher, use `with_map_type`.
+    pub fn hash_values_with_hasher<K, S>(hasher: S) -> MultiSetBuilderWithVals<HashMap<K, usize, S>>
+    where
+        K: Hash + Eq,
+        S: BuildHasher,
+    {
+        Self::with_map(HashMap::with_hasher(hasher))
+    }
+
+    /// Configures the multi-set to use a hashmap with the given hasher, with space for at least `capacity`
+    /// unique values. Counts are stored as `usize`.
+    pub fn hash_values_with_capacity_and_hasher<K, S>(
+        capacity: usize,
+        hasher: S,
+    ) -> MultiSetBuilderWithVals<HashMap<K, usize, S>>
+    where
+        K: Hash + Eq,
+        S: BuildHasher,
+    {
+        Self::with_map(HashMap::with_capacity_and_hasher(capacity, hasher))
+    }
+
+    /// Configures the multi-set to use a hashmap, storing counts as `C`.
+    pub fn hash_values_with_count_type<K, C>() -> MultiSetBuilderWithVals<HashMap<K, C>>
+    where
+        K: Hash + Eq,
+        C: Count,
     {
         Self::with_map_type()
     }
@@ -23,25 +63,85 @@ impl MultiSetBuilder {
         Self::with_map_type()
     }
 
+    /// Configures the multi-set to use a sorted map, storing counts as `C`.
+    pub fn sorted_values_with_count_type<K, C>() -> MultiSetBuilderWithVals<BTreeMap<K, C>>
+    where
+        K: Ord,
+        C: Count,
+    {
+        Self::with_map_type()
+    }
+
+    /// Configures the multi-set to use a sorted map that supports order statistics, such as `MultiSet::nth`,
+    /// in `O(log n)` time. `OrderStatisticMap` only stores `usize` counts, so this has no count type variant.
+    pub fn order_statistic_values<K>() -> MultiSetBuilderWithVals<OrderStatisticMap<K>>
+    where
+        K: Ord,
+    {
+        Self::with_map_type()
+    }
+
+    /// Configures the multi-set to use a sorted `Vec`, which is fast to query but slow to update.
+    pub fn sorted_vec_values<K>() -> MultiSetBuilderWithVals<SortedVecMap<K, usize>>
+    where
+        K: Ord,
+    {
+        Self::with_map_type()
+    }
+
+    /// Configures the multi-set to use a sorted `Vec`, storing counts as `C`.
+    pub fn sorted_vec_values_with_count_type<K, C>() -> MultiSetBuilderWithVals<SortedVecMap<K, C>>
+    where
+        K: Ord,
+        C: Count,
+    {
+        Self::with_map_type()
+    }
+
+    /// Configures the multi-set to use a map that keeps values in insertion order.
+    #[cfg(feature = "indexmap")]
+    pub fn insertion_ordered_values<K>() -> MultiSetBuilderWithVals<IndexMap<K, usize>>
+    where
+        K: Hash + Eq,
+    {
+        Self::with_map_type()
+    }
+
+    /// Configures the multi-set to use a map that keeps values in insertion order, storing counts as `C`.
+    #[cfg(feature = "indexmap")]
+    pub fn insertion_ordered_values_with_count_type<K, C>(
+    ) -> MultiSetBuilderWithVals<IndexMap<K, C>>
+    where
+        K: Hash + Eq,
+        C: Count,
+    {
+        Self::with_map_type()
+    }
+
     /// An advanced method that allows you to specify the type of map to use for keys.
-    pub fn with_map_type<M>() -> MultiSetBuilderWithVals<M> {
-        MultiSetBuilderWithVals {
-            _m: std::marker::PhantomData,
-        }
+    pub fn with_map_type<M>() -> MultiSetBuilderWithVals<M>
+    where
+        M: Default,
+    {
+        Self::with_map(M::default())
+    }
+
+    fn with_map<M>(map: M) -> MultiSetBuilderWithVals<M> {
+        MultiSetBuilderWithVals { map }
     }
 }
 
 /// A builder for a multi-set that has a known type for the map.
 pub struct MultiSetBuilderWithVals<M> {
-    _m: std::marker::PhantomData<M>,
+    map: M,
 }
 
 impl<M> MultiSetBuilderWithVals<M>
 where
-    M: Default,
+    M: Map<Val: Count>,
 {
     /// Builds the multi-set.
     pub fn build(self) -> MultiSet<M> {
-        Default::default()
+        MultiSet::from_empty_map(self.map)
     }
 }
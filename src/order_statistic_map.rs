@@ -0,0 +1,1102 @@
+use crate::maps::{
+    self, is_before_end, is_before_start, DefaultMap, KeyOrder, Lookup, Map, Rank, Select,
+    SortedMap,
+};
+use std::borrow::Borrow;
+use std::cmp::Ordering;
+use std::fmt::{self, Debug};
+use std::iter::FusedIterator;
+use std::ops::RangeBounds;
+use std::{iter, vec};
+
+type Link<K> = Option<Box<Node<K>>>;
+
+#[derive(Clone)]
+struct Node<K> {
+    key: K,
+    count: usize,
+    // The sum of the counts in this subtree.
+    total: usize,
+    // The number of nodes in this subtree.
+    size: usize,
+    height: usize,
+    left: Link<K>,
+    right: Link<K>,
+}
+
+/// A sorted map from keys to counts, which also tracks the sum of the counts in every subtree.
+/// This is the backend of `OrderStatisticMultiSet`, and lets it find the value at a given position,
+/// or the number of values below a given value, in `O(log n)` time.
+/// Counts are always `usize`, so multi-sets backed by this map cannot choose another `Count` type.
+///
+/// The map is an AVL tree. Counts that are handed out mutably (for example through `Lookup::get_mut` or
+/// an entry) are reconciled with the subtree sums before the next modification of the map.
+/// Queries made in between stay correct, and `Map::iter_mut` only makes them linear until then.
+#[derive(Clone)]
+pub struct OrderStatisticMap<K> {
+    root: Link<K>,
+    // A count that may have been changed since the subtree sums were computed, as its position among the keys
+    // and its value at that time. The subtree sums along its path are off by however much it has changed.
+    pending: Option<(usize, usize)>,
+    // Whether any number of counts may have been changed, in which case all subtree sums must be recomputed.
+    stale: bool,
+}
+
+impl<K> OrderStatisticMap<K> {
+    /// Creates a new, empty map.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use multi_containers::order_statistic_map::OrderStatisticMap;
+    /// use multi_containers::maps::Map;
+    /// let map = OrderStatisticMap::<i32>::new();
+    /// assert!(map.is_empty());
+    /// ```
+    pub fn new() -> Self {
+        OrderStatisticMap {
+            root: None,
+            pending: None,
+            stale: false,
+        }
+    }
+
+    fn node_at(&self, mut index: usize) -> &Node<K> {
+        let mut node = self.root.as_deref().expect("index out of bounds");
+        loop {
+            let left = size(&node.left);
+            match index.cmp(&left) {
+                Ordering::Less => node = node.left.as_deref().expect("index out of bounds"),
+                Ordering::Equal => return node,
+                Ordering::Greater => {
+                    index -= left + 1;
+                    node = node.right.as_deref().expect("index out of bounds");
+                }
+            }
+        }
+    }
+
+    fn node_at_mut(&mut self, mut index: usize) -> &mut Node<K> {
+        let mut node = self.root.as_deref_mut().expect("index out of bounds");
+        loop {
+            let left = size(&node.left);
+            match index.cmp(&left) {
+                Ordering::Less => node = node.left.as_deref_mut().expect("index out of bounds"),
+                Ordering::Equal => return node,
+                Ordering::Greater => {
+                    index -= left + 1;
+                    node = node.right.as_deref_mut().expect("index out of bounds");
+                }
+            }
+        }
+    }
+
+    // Brings the subtree sums up to date with any counts that were handed out mutably.
+    fn flush(&mut self) {
+        if self.stale {
+            recompute(&mut self.root);
+            self.stale = false;
+            self.pending = None;
+        } else if let Some((mut index, old)) = self.pending.take() {
+            let delta = self.node_at(index).count.wrapping_sub(old);
+            let mut node = self.root.as_deref_mut();
+            while let Some(n) = node {
+                n.total = n.total.wrapping_add(delta);
+                let left = size(&n.left);
+                node = match index.cmp(&left) {
+                    Ordering::Less => n.left.as_deref_mut(),
+                    Ordering::Equal => None,
+                    Ordering::Greater => {
+                        index -= left + 1;
+                        n.right.as_deref_mut()
+                    }
+                };
+            }
+        }
+    }
+
+    // Hands out the count at a position mutably, remembering its value so the subtree sums can be fixed later.
+    // The map must have been flushed.
+    fn count_mut(&mut self, index: usize) -> &mut usize {
+        self.pending = Some((index, self.node_at(index).count));
+        &mut self.node_at_mut(index).count
+    }
+
+    // Returns the sum of the counts in a subtree whose first key is at position `offset`, accounting for a
+    // pending count change.
+    fn total(&self, link: &Link<K>, offset: usize, delta: usize) -> usize {
+        match (link, self.pending) {
+            (Some(node), Some((index, _))) if (offset..offset + node.size).contains(&index) => {
+                node.total.wrapping_add(delta)
+            }
+            (Some(node), _) => node.total,
+            (None, _) => 0,
+        }
+    }
+
+    // Returns how much the pending count has changed since the subtree sums were computed.
+    fn pending_delta(&self) -> usize {
+        match self.pending {
+            Some((index, old)) => self.node_at(index).count.wrapping_sub(old),
+            None => 0,
+        }
+    }
+
+    // Returns the number of keys for which `is_before` holds, and the sum of their counts.
+    // `is_before` must hold for a prefix of the keys.
+    fn count_before<F>(&self, is_before: F) -> (usize, usize)
+    where
+        F: Fn(&K) -> bool,
+    {
+        if self.stale {
+            return self
+                .entries()
+                .take_while(|(key, _)| is_before(key))
+                .fold((0, 0), |(keys, total), (_, &count)| {
+                    (keys + 1, total + count)
+                });
+        }
+        let delta = self.pending_delta();
+        let (mut keys, mut total) = (0, 0);
+        let mut node = self.root.as_deref();
+        while let Some(n) = node {
+            if is_before(&n.key) {
+                total += self.total(&n.left, keys, delta) + n.count;
+                keys += size(&n.left) + 1;
+                node = n.right.as_deref();
+            } else {
+                node = n.left.as_deref();
+            }
+        }
+        (keys, total)
+    }
+
+    // Returns the positions of the first key in a range, and of the first key after it.
+    fn bounds<Q, R>(&self, range: &R) -> (usize, usize)
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+        R: RangeBounds<Q>,
+    {
+        let start = self
+            .count_before(|key| is_before_start(key.borrow(), range.start_bound()))
+            .0;
+        let end = self
+            .count_before(|key| is_before_end(key.borrow(), range.end_bound()))
+            .0;
+        (start, end.max(start))
+    }
+
+    // Returns the position of a key if it is present, or else the position it would be inserted at.
+    fn position<Q>(&self, key: &Q) -> Result<usize, usize>
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        let mut offset = 0;
+        let mut node = self.root.as_deref();
+        while let Some(n) = node {
+            match key.cmp(n.key.borrow()) {
+                Ordering::Less => node = n.left.as_deref(),
+                Ordering::Equal => return Ok(offset + size(&n.left)),
+                Ordering::Greater => {
+                    offset += size(&n.left) + 1;
+                    node = n.right.as_deref();
+                }
+            }
+        }
+        Err(offset)
+    }
+
+    fn remove_at(&mut self, index: usize) -> (K, usize) {
+        self.flush();
+        let (root, key, count) = remove_at(self.root.take().expect("index out of bounds"), index);
+        self.root = root;
+        (key, count)
+    }
+
+    fn entries(&self) -> Iter<'_, K> {
+        self.iter_range(0, size(&self.root))
+    }
+
+    fn iter_range(&self, start: usize, end: usize) -> Iter<'_, K> {
+        let mut front = Vec::new();
+        let (mut offset, mut node) = (0, self.root.as_deref());
+        while let Some(n) = node {
+            let index = offset + size(&n.left);
+            if index >= start {
+                front.push(n);
+                node = n.left.as_deref();
+            } else {
+                offset = index + 1;
+                node = n.right.as_deref();
+            }
+        }
+        let mut back = Vec::new();
+        let (mut offset, mut node) = (0, self.root.as_deref());
+        while let Some(n) = node {
+            let index = offset + size(&n.left);
+            if index < end {
+                back.push(n);
+                offset = index + 1;
+                node = n.right.as_deref();
+            } else {
+                node = n.left.as_deref();
+            }
+        }
+        Iter {
+            front,
+            back,
+            remaining: end - start,
+        }
+    }
+
+    fn iter_range_mut(&mut self, start: usize, end: usize) -> IterMut<'_, K> {
+        self.flush();
+        // The counts are handed out in bulk, so the subtree sums have to be recomputed afterwards.
+        self.stale = true;
+        IterMut {
+            stack: self
+                .root
+                .as_deref_mut()
+                .map(|node| IterMutItem::Subtree(node, 0))
+                .into_iter()
+                .collect(),
+            start,
+            end,
+        }
+    }
+
+    #[cfg(test)]
+    fn check_invariants(&self)
+    where
+        K: Ord + Clone,
+    {
+        fn check<K: Ord>(link: &Link<K>) -> (usize, usize, usize) {
+            match link {
+                None => (0, 0, 0),
+                Some(node) => {
+                    let (left_total, left_size, left_height) = check(&node.left);
+                    let (right_total, right_size, right_height) = check(&node.right);
+                    assert!(node.left.as_ref().is_none_or(|left| left.key < node.key));
+                    assert!(node.right.as_ref().is_none_or(|right| right.key > node.key));
+                    assert!(
+                        left_height.abs_diff(right_height) <= 1,
+                        "tree is unbalanced"
+                    );
+                    assert_eq!(node.total, left_total + node.count + right_total);
+                    assert_eq!(node.size, left_size + 1 + right_size);
+                    assert_eq!(node.height, 1 + left_height.max(right_height));
+                    (node.total, node.size, node.height)
+                }
+            }
+        }
+        let mut map = self.clone();
+        map.flush();
+        check(&map.root);
+    }
+}
+
+impl<K> Default for OrderStatisticMap<K> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<K> Debug for OrderStatisticMap<K>
+where
+    K: Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_map().entries(self.entries()).finish()
+    }
+}
+
+impl<K> PartialEq for OrderStatisticMap<K>
+where
+    K: PartialEq,
+{
+    fn eq(&self, other: &Self) -> bool {
+        size(&self.root) == size(&other.root) && self.entries().eq(other.entries())
+    }
+}
+
+impl<K> Eq for OrderStatisticMap<K> where K: Eq {}
+
+fn size<K>(link: &Link<K>) -> usize {
+    link.as_ref().map_or(0, |node| node.size)
+}
+
+fn height<K>(link: &Link<K>) -> usize {
+    link.as_ref().map_or(0, |node| node.height)
+}
+
+fn subtree_total<K>(link: &Link<K>) -> usize {
+    link.as_ref().map_or(0, |node| node.total)
+}
+
+fn update<K>(node: &mut Node<K>) {
+    node.total = subtree_total(&node.left) + node.count + subtree_total(&node.right);
+    node.size = size(&node.left) + 1 + size(&node.right);
+    node.height = height(&node.left).max(height(&node.right)) + 1;
+}
+
+fn recompute<K>(link: &mut Link<K>) {
+    if let Some(node) = link {
+        recompute(&mut node.left);
+        recompute(&mut node.right);
+        update(node);
+    }
+}
+
+fn rotate_left<K>(mut node: Box<Node<K>>) -> Box<Node<K>> {
+    let mut right = node.right.take().expect("rotating without a right child");
+    node.right = right.left.take();
+    update(&mut node);
+    right.left = Some(node);
+    update(&mut right);
+    right
+}
+
+fn rotate_right<K>(mut node: Box<Node<K>>) -> Box<Node<K>> {
+    let mut left = node.left.take().expect("rotating without a left child");
+    node.left = left.right.take();
+    update(&mut node);
+    left.right = Some(node);
+    update(&mut left);
+    left
+}
+
+// Restores the balance of a node whose subtrees differ in height by at most two.
+fn balance<K>(mut node: Box<Node<K>>) -> Box<Node<K>> {
+    let (left, right) = (height(&node.left), height(&node.right));
+    if left > right + 1 {
+        let child = node.left.take().expect("left subtree is taller");
+        node.left = Some(if height(&child.left) < height(&child.right) {
+            rotate_left(child)
+        } else {
+            child
+        });
+        rotate_right(node)
+    } else if right > left + 1 {
+        let child = node.right.take().expect("right subtree is taller");
+        node.right = Some(if height(&child.right) < height(&child.left) {
+            rotate_right(child)
+        } else {
+            child
+        });
+        rotate_left(node)
+    } else {
+        update(&mut node);
+        node
+    }
+}
+
+// Inserts a key with a count into a subtree, returning the new subtree and the previous count of the key.
+fn insert<K: Ord>(link: Link<K>, key: K, count: usize) -> (Box<Node<K>>, Option<usize>) {
+    let Some(mut node) = link else {
+        let node = Node {
+            key,
+            count,
+            total: count,
+            size: 1,
+            height: 1,
+            left: None,
+            right: None,
+        };
+        return (Box::new(node), None);
+    };
+    let prev = match key.cmp(&node.key) {
+        Ordering::Less => {
+            let (left, prev) = insert(node.left.take(), key, count);
+            node.left = Some(left);
+            prev
+        }
+        Ordering::Equal => Some(std::mem::replace(&mut node.count, count)),
+        Ordering::Greater => {
+            let (right, prev) = insert(node.right.take(), key, count);
+            node.right = Some(right);
+            prev
+        }
+    };
+    (balance(node), prev)
+}
+
+// Removes the smallest key of a subtree, returning the new subtree and the node of the removed key.
+fn remove_min<K>(mut node: Box<Node<K>>) -> (Link<K>, Box<Node<K>>) {
+    match node.left.take() {
+        None => (node.right.take(), node),
+        Some(left) => {
+            let (left, min) = remove_min(left);
+            node.left = left;
+            (Some(balance(node)), min)
+        }
+    }
+}
+
+// Removes the key at a position in a subtree, returning the new subtree and the removed key and count.
+fn remove_at<K>(mut node: Box<Node<K>>, index: usize) -> (Link<K>, K, usize) {
+    let left_size = size(&node.left);
+    match index.cmp(&left_size) {
+        Ordering::Less => {
+            let left = node.left.take().expect("index out of bounds");
+            let (left, key, count) = remove_at(left, index);
+            node.left = left;
+            (Some(balance(node)), key, count)
+        }
+        Ordering::Greater => {
+            let right = node.right.take().expect("index out of bounds");
+            let (right, key, count) = remove_at(right, index - left_size - 1);
+            node.right = right;
+            (Some(balance(node)), key, count)
+        }
+        Ordering::Equal => {
+            let Node {
+                key,
+                count,
+                left,
+                right,
+                ..
+            } = *node;
+            let replacement = match (left, right) {
+                (left, None) => left,
+                (None, right) => right,
+                (Some(left), Some(right)) => {
+                    let (right, mut min) = remove_min(right);
+                    min.left = Some(left);
+                    min.right = right;
+                    Some(balance(min))
+                }
+            };
+            (replacement, key, count)
+        }
+    }
+}
+
+fn into_entries<K>(link: Link<K>, entries: &mut Vec<(K, usize)>) {
+    if let Some(node) = link {
+        let Node {
+            key,
+            count,
+            left,
+            right,
+            ..
+        } = *node;
+        into_entries(left, entries);
+        entries.push((key, count));
+        into_entries(right, entries);
+    }
+}
+
+// Builds a balanced subtree from entries sorted by key.
+fn from_sorted<K>(entries: &mut vec::IntoIter<(K, usize)>, len: usize) -> Link<K> {
+    if len == 0 {
+        return None;
+    }
+    let left = from_sorted(entries, len / 2);
+    let (key, count) = entries.next().expect("fewer entries than expected");
+    let right = from_sorted(entries, len - len / 2 - 1);
+    let mut node = Node {
+        key,
+        count,
+        total: 0,
+        size: 0,
+        height: 0,
+        left,
+        right,
+    };
+    update(&mut node);
+    Some(Box::new(node))
+}
+
+/// An iterator over the entries of an `OrderStatisticMap`, or of a range of it, in sorted order.
+pub struct Iter<'a, K> {
+    // The nodes whose key and right subtree have yet to be visited from the front, and from the back with
+    // the left subtree instead.
+    front: Vec<&'a Node<K>>,
+    back: Vec<&'a Node<K>>,
+    remaining: usize,
+}
+
+impl<'a, K> Iterator for Iter<'a, K> {
+    type Item = (&'a K, &'a usize);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.remaining == 0 {
+            return None;
+        }
+        self.remaining -= 1;
+        let node = self.front.pop()?;
+        let mut next = node.right.as_deref();
+        while let Some(n) = next {
+            self.front.push(n);
+            next = n.left.as_deref();
+        }
+        Some((&node.key, &node.count))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining, Some(self.remaining))
+    }
+}
+
+impl<K> DoubleEndedIterator for Iter<'_, K> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        if self.remaining == 0 {
+            return None;
+        }
+        self.remaining -= 1;
+        let node = self.back.pop()?;
+        let mut next = node.left.as_deref();
+        while let Some(n) = next {
+            self.back.push(n);
+            next = n.right.as_deref();
+        }
+        Some((&node.key, &node.count))
+    }
+}
+
+impl<K> ExactSizeIterator for Iter<'_, K> {}
+
+impl<K> FusedIterator for Iter<'_, K> {}
+
+enum IterMutItem<'a, K> {
+    // A subtree that has yet to be visited, and the position of its first key.
+    Subtree(&'a mut Node<K>, usize),
+    Entry(&'a K, &'a mut usize),
+}
+
+/// An iterator over the entries of an `OrderStatisticMap`, or of a range of it, in sorted order, with mutable
+/// references to the counts.
+pub struct IterMut<'a, K> {
+    stack: Vec<IterMutItem<'a, K>>,
+    // The positions of the first key in the range, and of the first key after it.
+    start: usize,
+    end: usize,
+}
+
+impl<'a, K> Iterator for IterMut<'a, K> {
+    type Item = (&'a K, &'a mut usize);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            match self.stack.pop()? {
+                IterMutItem::Entry(key, count) => return Some((key, count)),
+                IterMutItem::Subtree(node, offset) => {
+                    let index = offset + size(&node.left);
+                    let Node {
+                        key,
+                        count,
+                        left,
+                        right,
+                        ..
+                    } = node;
+                    if let Some(right) = right.as_deref_mut() {
+                        if index + 1 < self.end {
+                            self.stack.push(IterMutItem::Subtree(right, index + 1));
+                        }
+                    }
+                    if (self.start..self.end).contains(&index) {
+                        self.stack.push(IterMutItem::Entry(key, count));
+                    }
+                    if let Some(left) = left.as_deref_mut() {
+                        if index > self.start {
+                            self.stack.push(IterMutItem::Subtree(left, offset));
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl<K> FusedIterator for IterMut<'_, K> {}
+
+/// An iterator that removes and yields the entries of an `OrderStatisticMap` that match a predicate.
+pub struct ExtractIf<'a, K, F> {
+    map: &'a mut OrderStatisticMap<K>,
+    // The position of the next entry to test.
+    index: usize,
+    pred: F,
+}
+
+impl<K, F> Iterator for ExtractIf<'_, K, F>
+where
+    F: FnMut(&K, &mut usize) -> bool,
+{
+    type Item = (K, usize);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while self.index < size(&self.map.root) {
+            self.map.flush();
+            self.map.count_mut(self.index);
+            let node = self.map.node_at_mut(self.index);
+            if (self.pred)(&node.key, &mut node.count) {
+                return Some(self.map.remove_at(self.index));
+            }
+            self.index += 1;
+        }
+        None
+    }
+}
+
+impl<K, F> FusedIterator for ExtractIf<'_, K, F> where F: FnMut(&K, &mut usize) -> bool {}
+
+/// A view into an occupied entry of an `OrderStatisticMap`.
+pub struct OccupiedEntry<'a, K> {
+    map: &'a mut OrderStatisticMap<K>,
+    index: usize,
+}
+
+/// A view into a vacant entry of an `OrderStatisticMap`.
+pub struct VacantEntry<'a, K> {
+    map: &'a mut OrderStatisticMap<K>,
+    key: K,
+}
+
+impl<'a, K> maps::OccupiedEntry<'a> for OccupiedEntry<'a, K>
+where
+    K: Ord,
+{
+    type Key = K;
+    type Val = usize;
+
+    fn key(&self) -> &K {
+        &self.map.node_at(self.index).key
+    }
+
+    fn get(&self) -> &usize {
+        &self.map.node_at(self.index).count
+    }
+
+    fn get_mut(&mut self) -> &mut usize {
+        // The count was already marked as pending when the entry was created.
+        &mut self.map.node_at_mut(self.index).count
+    }
+
+    fn into_mut(self) -> &'a mut usize {
+        &mut self.map.node_at_mut(self.index).count
+    }
+
+    fn remove_entry(self) -> (K, usize) {
+        self.map.remove_at(self.index)
+    }
+}
+
+impl<'a, K> maps::VacantEntry<'a> for VacantEntry<'a, K>
+where
+    K: Ord,
+{
+    type Key = K;
+    type Val = usize;
+    type Occupied = OccupiedEntry<'a, K>;
+
+    fn key(&self) -> &K {
+        &self.key
+    }
+
+    fn into_key(self) -> K {
+        self.key
+    }
+
+    fn insert(self, value: usize) -> &'a mut usize {
+        maps::OccupiedEntry::into_mut(self.insert_entry(value))
+    }
+
+    fn insert_entry(self, value: usize) -> OccupiedEntry<'a, K> {
+        let index = self.map.position(&self.key).unwrap_err();
+        self.map.insert(self.key, value);
+        self.map.count_mut(index);
+        OccupiedEntry {
+            map: self.map,
+            index,
+        }
+    }
+}
+
+impl<K> IntoIterator for OrderStatisticMap<K> {
+    type Item = (K, usize);
+    type IntoIter = vec::IntoIter<(K, usize)>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        let mut entries = Vec::with_capacity(size(&self.root));
+        into_entries(self.root, &mut entries);
+        entries.into_iter()
+    }
+}
+
+impl<K> DefaultMap for OrderStatisticMap<K> where K: Ord {}
+
+impl<K> Map for OrderStatisticMap<K>
+where
+    K: Ord,
+{
+    type Key = K;
+    type Val = usize;
+    type Iter<'a>
+        = Iter<'a, K>
+    where
+        Self: 'a;
+    type IterMut<'a>
+        = IterMut<'a, K>
+    where
+        Self: 'a;
+    type KeyIter<'a>
+        = iter::Map<Iter<'a, K>, fn((&'a K, &'a usize)) -> &'a K>
+    where
+        Self: 'a;
+    type ValIter<'a>
+        = iter::Map<Iter<'a, K>, fn((&'a K, &'a usize)) -> &'a usize>
+    where
+        Self: 'a;
+    // Like `BTreeMap`, draining takes the whole map and iterates over it.
+    type Drain<'a>
+        = vec::IntoIter<(K, usize)>
+    where
+        Self: 'a;
+    type ExtractIf<'a, F>
+        = ExtractIf<'a, K, F>
+    where
+        Self: 'a,
+        F: FnMut(&K, &mut usize) -> bool + 'a;
+    type Occupied<'a>
+        = OccupiedEntry<'a, K>
+    where
+        Self: 'a;
+    type Vacant<'a>
+        = VacantEntry<'a, K>
+    where
+        Self: 'a;
+
+    fn insert(&mut self, key: K, value: usize) -> Option<usize> {
+        self.flush();
+        let (root, prev) = insert(self.root.take(), key, value);
+        self.root = Some(root);
+        prev
+    }
+
+    fn get_or_insert<F>(&mut self, key: K, make_value: F) -> &mut usize
+    where
+        F: FnOnce() -> usize,
+    {
+        self.entry(key).or_insert_with(make_value)
+    }
+
+    fn entry(&mut self, key: K) -> maps::Entry<'_, Self> {
+        self.flush();
+        match self.position(&key) {
+            Ok(index) => {
+                self.count_mut(index);
+                maps::Entry::Occupied(OccupiedEntry { map: self, index })
+            }
+            Err(_) => maps::Entry::Vacant(VacantEntry { map: self, key }),
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.root.is_none()
+    }
+
+    fn len(&self) -> usize {
+        size(&self.root)
+    }
+
+    fn iter(&self) -> Self::Iter<'_> {
+        self.iter_range(0, self.len())
+    }
+
+    fn iter_mut(&mut self) -> Self::IterMut<'_> {
+        self.iter_range_mut(0, self.len())
+    }
+
+    fn keys(&self) -> Self::KeyIter<'_> {
+        self.iter().map(|(key, _)| key)
+    }
+
+    fn values(&self) -> Self::ValIter<'_> {
+        self.iter().map(|(_, count)| count)
+    }
+
+    fn retain<F>(&mut self, mut f: F)
+    where
+        F: FnMut(&K, &mut usize) -> bool,
+    {
+        let entries = std::mem::take(self)
+            .into_iter()
+            .filter_map(|(key, mut count)| f(&key, &mut count).then_some((key, count)))
+            .collect::<Vec<_>>();
+        let len = entries.len();
+        self.root = from_sorted(&mut entries.into_iter(), len);
+    }
+
+    fn drain(&mut self) -> Self::Drain<'_> {
+        std::mem::take(self).into_iter()
+    }
+
+    fn extract_if<'a, F>(&'a mut self, pred: F) -> Self::ExtractIf<'a, F>
+    where
+        F: FnMut(&K, &mut usize) -> bool + 'a,
+    {
+        ExtractIf {
+            map: self,
+            index: 0,
+            pred,
+        }
+    }
+
+    fn key_order() -> Option<KeyOrder<K>> {
+        Some(K::cmp)
+    }
+}
+
+impl<K, Q> Lookup<Q> for OrderStatisticMap<K>
+where
+    K: Ord + Borrow<Q>,
+    Q: Ord + ?Sized,
+{
+    fn contains_key(&self, key: &Q) -> bool {
+        self.position(key).is_ok()
+    }
+
+    fn get(&self, key: &Q) -> Option<&usize> {
+        let index = self.position(key).ok()?;
+        Some(&self.node_at(index).count)
+    }
+
+    fn get_mut(&mut self, key: &Q) -> Option<&mut usize> {
+        self.flush();
+        let index = self.position(key).ok()?;
+        Some(self.count_mut(index))
+    }
+
+    fn remove(&mut self, key: &Q) -> Option<usize> {
+        let index = self.position(key).ok()?;
+        Some(self.remove_at(index).1)
+    }
+}
+
+impl<K, Q> SortedMap<Q> for OrderStatisticMap<K>
+where
+    K: Ord + Borrow<Q>,
+    Q: Ord + ?Sized,
+{
+    type RangeIter<'a>
+        = Iter<'a, K>
+    where
+        Self: 'a;
+    type RangeIterMut<'a>
+        = IterMut<'a, K>
+    where
+        Self: 'a;
+
+    fn range<R>(&self, range: R) -> Self::RangeIter<'_>
+    where
+        R: RangeBounds<Q>,
+    {
+        let (start, end) = self.bounds(&range);
+        self.iter_range(start, end)
+    }
+
+    fn range_mut<R>(&mut self, range: R) -> Self::RangeIterMut<'_>
+    where
+        R: RangeBounds<Q>,
+    {
+        let (start, end) = self.bounds(&range);
+        self.iter_range_mut(start, end)
+    }
+}
+
+impl<K> Select for OrderStatisticMap<K>
+where
+    K: Ord,
+{
+    fn select(&self, mut index: usize) -> Option<&K> {
+        if self.stale {
+            return self.iter().find_map(|(key, &count)| {
+                if index < count {
+                    Some(key)
+                } else {
+                    index -= count;
+                    None
+                }
+            });
+        }
+        let delta = self.pending_delta();
+        let mut offset = 0;
+        let mut node = self.root.as_deref();
+        while let Some(n) = node {
+            let left = self.total(&n.left, offset, delta);
+            if index < left {
+                node = n.left.as_deref();
+            } else if index < left + n.count {
+                return Some(&n.key);
+            } else {
+                index -= left + n.count;
+                offset += size(&n.left) + 1;
+                node = n.right.as_deref();
+            }
+        }
+        None
+    }
+}
+
+impl<K, Q> Rank<Q> for OrderStatisticMap<K>
+where
+    K: Ord + Borrow<Q>,
+    Q: Ord + ?Sized,
+{
+    fn rank(&self, key: &Q) -> usize {
+        self.count_before(|k| k.borrow() < key).1
+    }
+
+    fn count_in_range<R>(&self, range: R) -> usize
+    where
+        R: RangeBounds<Q>,
+    {
+        let start = self.count_before(|key| is_before_start(key.borrow(), range.start_bound()));
+        let end = self.count_before(|key| is_before_end(key.borrow(), range.end_bound()));
+        end.1.saturating_sub(start.1)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::maps::{Entry, OccupiedEntry as _};
+    use crate::test_utils::Lcg;
+    use std::collections::BTreeMap;
+
+    fn assert_same(map: &OrderStatisticMap<u64>, expected: &BTreeMap<u64, usize>) {
+        map.check_invariants();
+        assert_eq!(map.len(), expected.len());
+        assert!(map.iter().eq(expected.iter()));
+        assert!(map.iter().rev().eq(expected.iter().rev()));
+        let total = expected.values().sum::<usize>();
+        let mut occurrences = expected
+            .iter()
+            .flat_map(|(key, &count)| iter::repeat_n(key, count));
+        for index in 0..total {
+            assert_eq!(map.select(index), occurrences.next());
+        }
+        assert_eq!(map.select(total), None);
+        for key in 0..30 {
+            assert_eq!(
+                map.rank(&key),
+                expected.range(..key).map(|(_, c)| c).sum::<usize>()
+            );
+            assert!(map.range(key..key + 5).eq(expected.range(key..key + 5)));
+            assert_eq!(
+                map.count_in_range(key..=key + 5),
+                expected.range(key..=key + 5).map(|(_, c)| c).sum::<usize>()
+            );
+        }
+    }
+
+    #[test]
+    fn matches_btree_map() {
+        let mut rng = Lcg(1);
+        let mut map = OrderStatisticMap::new();
+        let mut expected = BTreeMap::new();
+        for step in 0..2000 {
+            let key = rng.next(30);
+            let count = rng.next(4) as usize + 1;
+            match rng.next(7) {
+                0 => assert_eq!(
+                    Map::insert(&mut map, key, count),
+                    expected.insert(key, count)
+                ),
+                1 => assert_eq!(Lookup::remove(&mut map, &key), expected.remove(&key)),
+                2 => {
+                    if let (Some(a), Some(b)) =
+                        (Lookup::get_mut(&mut map, &key), expected.get_mut(&key))
+                    {
+                        *a += count;
+                        *b += count;
+                    }
+                }
+                3 => match Map::entry(&mut map, key) {
+                    Entry::Occupied(mut entry) => {
+                        *entry.get_mut() += count;
+                        *expected.get_mut(&key).unwrap() += count;
+                    }
+                    Entry::Vacant(entry) => {
+                        maps::VacantEntry::insert(entry, count);
+                        expected.insert(key, count);
+                    }
+                },
+                4 => {
+                    *Map::get_or_insert(&mut map, key, || 0) += count;
+                    *expected.entry(key).or_insert(0) += count;
+                }
+                5 => {
+                    for (_, count) in map.range_mut(key..key + 3) {
+                        *count += 1;
+                    }
+                    for (_, count) in expected.range_mut(key..key + 3) {
+                        *count += 1;
+                    }
+                }
+                _ => {
+                    let extracted = map.extract_if(|k, c| {
+                        *c += 1;
+                        k % 7 == key % 7
+                    });
+                    let mut expected_extracted = Vec::new();
+                    expected.retain(|k, c| {
+                        *c += 1;
+                        if k % 7 == key % 7 {
+                            expected_extracted.push((*k, *c));
+                            false
+                        } else {
+                            true
+                        }
+                    });
+                    assert_eq!(extracted.collect::<Vec<_>>(), expected_extracted);
+                }
+            }
+            if step % 50 == 0 {
+                assert_same(&map, &expected);
+            }
+        }
+        assert_same(&map, &expected);
+    }
+
+    #[test]
+    fn queries_between_mutations() {
+        let mut map = OrderStatisticMap::new();
+        for key in 0..10 {
+            Map::insert(&mut map, key, 1);
+        }
+        *Lookup::get_mut(&mut map, &3).unwrap() += 5;
+        assert_eq!(map.rank(&4), 9);
+        assert_eq!(map.select(8), Some(&3));
+        assert_eq!(map.count_in_range(3..5), 7);
+        for (_, count) in map.iter_mut() {
+            *count += 1;
+        }
+        assert_eq!(map.rank(&4), 13);
+        assert_eq!(map.select(12), Some(&3));
+        assert_eq!(map.select(13), Some(&4));
+        Map::insert(&mut map, 10, 1);
+        assert_eq!(map.rank(&11), 26);
+        map.check_invariants();
+    }
+
+    #[test]
+    fn retain_and_drain() {
+        let mut map = OrderStatisticMap::new();
+        for key in 0..100 {
+            Map::insert(&mut map, key, key as usize + 1);
+        }
+        map.retain(|key, count| {
+            *count += 1;
+            key % 3 == 0
+        });
+        map.check_invariants();
+        assert_eq!(map.len(), 34);
+        assert_eq!(map.select(0), Some(&0));
+        assert_eq!(map.select(2), Some(&3));
+        assert_eq!(map.drain().count(), 34);
+        assert!(map.is_empty());
+    }
+}
@@ -0,0 +1,303 @@
+use crate::counts::Count;
+use crate::maps::Map;
+use crate::sets::Set;
+use crate::{MultiMap, MultiSet};
+use serde::de::{Error, MapAccess, Unexpected, Visitor};
+use serde::ser::SerializeMap;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::fmt;
+use std::marker::PhantomData;
+
+/// Serializes a multi-map as a map from each key to the sequence of its values.
+impl<M> Serialize for MultiMap<M>
+where
+    M: Map,
+    M::Key: Serialize,
+    M::Val: Set,
+    <M::Val as Set>::Elem: Serialize,
+{
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let mut map = serializer.serialize_map(Some(self.num_keys()))?;
+        for (key, values) in self.value_sets() {
+            map.serialize_entry(key, &Values(values))?;
+        }
+        map.end()
+    }
+}
+
+// Serializes a value set as a sequence.
+struct Values<'a, T>(&'a T);
+
+impl<T> Serialize for Values<'_, T>
+where
+    T: Set,
+    T::Elem: Serialize,
+{
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.collect_seq(self.0.iter())
+    }
+}
+
+/// Deserializes a multi-map from a map from each key to a sequence of its values.
+/// Empty sequences are rejected, as a multi-map never stores a key without values.
+impl<'de, M> Deserialize<'de> for MultiMap<M>
+where
+    M: Map + Default,
+    M::Key: Deserialize<'de> + Clone,
+    M::Val: Set + Default,
+    <M::Val as Set>::Elem: Deserialize<'de>,
+{
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        deserializer.deserialize_map(MultiMapVisitor(PhantomData))
+    }
+}
+
+struct MultiMapVisitor<M>(PhantomData<M>);
+
+impl<'de, M> Visitor<'de> for MultiMapVisitor<M>
+where
+    M: Map + Default,
+    M::Key: Deserialize<'de> + Clone,
+    M::Val: Set + Default,
+    <M::Val as Set>::Elem: Deserialize<'de>,
+{
+    type Value = MultiMap<M>;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("a map from keys to non-empty sequences of values")
+    }
+
+    fn visit_map<A>(self, mut access: A) -> Result<Self::Value, A::Error>
+    where
+        A: MapAccess<'de>,
+    {
+        let mut map = MultiMap::default();
+        while let Some((key, values)) = access.next_entry::<M::Key, Vec<<M::Val as Set>::Elem>>()? {
+            if values.is_empty() {
+                return Err(A::Error::invalid_length(
+                    0,
+                    &"a non-empty sequence of values",
+                ));
+            }
+            // Inserting the values one by one keeps the number of mappings up to date, and merges the values of
+            // repeated keys.
+            for value in values {
+                map.insert(key.clone(), value);
+            }
+        }
+        Ok(map)
+    }
+}
+
+/// Serializes a multi-set as a map from each unique value to its count.
+/// See `flat_multiset` for a representation as a sequence of values.
+impl<M> Serialize for MultiSet<M>
+where
+    M: Map<Val: Count>,
+    M::Key: Serialize,
+{
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.collect_map(
+            self.counts()
+                .map(|(value, count)| (value, count.to_usize())),
+        )
+    }
+}
+
+/// Deserializes a multi-set from a map from each unique value to its count.
+/// Zero counts are rejected, as a multi-set never stores a value that does not occur.
+impl<'de, M> Deserialize<'de> for MultiSet<M>
+where
+    M: Map<Val: Count> + Default,
+    M::Key: Deserialize<'de>,
+{
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        deserializer.deserialize_map(MultiSetVisitor(PhantomData))
+    }
+}
+
+struct MultiSetVisitor<M>(PhantomData<M>);
+
+impl<'de, M> Visitor<'de> for MultiSetVisitor<M>
+where
+    M: Map<Val: Count> + Default,
+    M::Key: Deserialize<'de>,
+{
+    type Value = MultiSet<M>;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("a map from values to non-zero counts")
+    }
+
+    fn visit_map<A>(self, mut access: A) -> Result<Self::Value, A::Error>
+    where
+        A: MapAccess<'de>,
+    {
+        let mut set = MultiSet::default();
+        while let Some((value, count)) = access.next_entry::<M::Key, usize>()? {
+            if count == 0 {
+                return Err(A::Error::invalid_value(
+                    Unexpected::Unsigned(0),
+                    &"a non-zero count",
+                ));
+            }
+            // Inserting the counts keeps the length up to date.
+            set.try_insert_some(value, count)
+                .map_err(A::Error::custom)?;
+        }
+        Ok(set)
+    }
+}
+
+/// Serializes a multi-set as a flat sequence of its values, repeating each value as many times as it occurs.
+/// Use this with `#[serde(with = "multi_containers::serialization::flat_multiset")]`.
+///
+/// # Example
+///
+/// ```
+/// use multi_containers::BTreeMultiSet;
+/// use serde::{Deserialize, Serialize};
+///
+/// #[derive(Serialize, Deserialize)]
+/// struct Bag {
+///     #[serde(with = "multi_containers::serialization::flat_multiset")]
+///     items: BTreeMultiSet<String>,
+/// }
+/// ```
+pub mod flat_multiset {
+    use crate::counts::Count;
+    use crate::maps::Map;
+    use crate::MultiSet;
+    use serde::de::{SeqAccess, Visitor};
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+    use std::fmt;
+    use std::marker::PhantomData;
+
+    /// Serializes a multi-set as a sequence of its values, including duplicates.
+    pub fn serialize<M, S>(set: &MultiSet<M>, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        M: Map<Val: Count>,
+        M::Key: Serialize,
+        S: Serializer,
+    {
+        serializer.collect_seq(set.iter())
+    }
+
+    /// Deserializes a multi-set from a sequence of its values, including duplicates.
+    pub fn deserialize<'de, M, D>(deserializer: D) -> Result<MultiSet<M>, D::Error>
+    where
+        M: Map<Val: Count> + Default,
+        M::Key: Deserialize<'de>,
+        D: Deserializer<'de>,
+    {
+        deserializer.deserialize_seq(FlatVisitor(PhantomData))
+    }
+
+    struct FlatVisitor<M>(PhantomData<M>);
+
+    impl<'de, M> Visitor<'de> for FlatVisitor<M>
+    where
+        M: Map<Val: Count> + Default,
+        M::Key: Deserialize<'de>,
+    {
+        type Value = MultiSet<M>;
+
+        fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+            formatter.write_str("a sequence of values")
+        }
+
+        fn visit_seq<A>(self, mut access: A) -> Result<Self::Value, A::Error>
+        where
+            A: SeqAccess<'de>,
+        {
+            let mut set = MultiSet::default();
+            while let Some(value) = access.next_element()? {
+                set.try_insert_some(value, 1)
+                    .map_err(serde::de::Error::custom)?;
+            }
+            Ok(set)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{BTreeMultiMap, BTreeMultiSet, HashMultiMap, HashMultiSet, MultiSet};
+    use serde::{Deserialize, Serialize};
+    use std::collections::BTreeMap;
+
+    #[test]
+    fn multimap_round_trip() {
+        let map = BTreeMultiMap::from([("a", 1), ("a", 2), ("b", 3)]);
+        let json = serde_json::to_string(&map).unwrap();
+        assert_eq!(json, r#"{"a":[1,2],"b":[3]}"#);
+        let back: BTreeMultiMap<String, i32> = serde_json::from_str(&json).unwrap();
+        back.check_invariants();
+        assert_eq!(back.num_keys(), 2);
+        assert_eq!(back.num_mappings(), 3);
+        let hashed: HashMultiMap<String, i32> = serde_json::from_str(&json).unwrap();
+        hashed.check_invariants();
+        assert_eq!(hashed.num_mappings(), 3);
+    }
+
+    #[test]
+    fn multimap_rejects_empty_value_sets() {
+        let result = serde_json::from_str::<BTreeMultiMap<String, i32>>(r#"{"a":[1],"b":[]}"#);
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn multiset_round_trip() {
+        let set = BTreeMultiSet::from(["a", "a", "b"]);
+        let json = serde_json::to_string(&set).unwrap();
+        assert_eq!(json, r#"{"a":2,"b":1}"#);
+        let back: BTreeMultiSet<String> = serde_json::from_str(&json).unwrap();
+        back.check_invariants();
+        assert_eq!(back.len(), 3);
+        let hashed: HashMultiSet<String> = serde_json::from_str(&json).unwrap();
+        hashed.check_invariants();
+        assert_eq!(hashed.count("a"), 2);
+    }
+
+    #[test]
+    fn multiset_rejects_zero_and_overflowing_counts() {
+        type SmallCountMultiSet = MultiSet<BTreeMap<String, u8>>;
+        assert!(serde_json::from_str::<BTreeMultiSet<String>>(r#"{"a":0}"#).is_err());
+        let set: SmallCountMultiSet = serde_json::from_str(r#"{"a":255}"#).unwrap();
+        assert_eq!(set.len(), 255);
+        assert!(serde_json::from_str::<SmallCountMultiSet>(r#"{"a":256}"#).is_err());
+    }
+
+    #[derive(Serialize, Deserialize)]
+    struct Bag {
+        #[serde(with = "crate::serialization::flat_multiset")]
+        items: BTreeMultiSet<String>,
+    }
+
+    #[test]
+    fn flat_multiset_round_trip() {
+        let bag = Bag {
+            items: BTreeMultiSet::from(["a".to_string(), "b".to_string(), "a".to_string()]),
+        };
+        let json = serde_json::to_string(&bag).unwrap();
+        assert_eq!(json, r#"{"items":["a","a","b"]}"#);
+        let back: Bag = serde_json::from_str(&json).unwrap();
+        back.items.check_invariants();
+        assert_eq!(back.items.count("a"), 2);
+    }
+}
@@ -1,9 +1,13 @@
+#[cfg(feature = "indexmap")]
+use indexmap::{set as index_set, IndexSet};
 use std::borrow::Borrow;
 use std::collections::{btree_set, hash_set, BTreeSet, HashSet};
-use std::hash::Hash;
+use std::hash::{BuildHasher, Hash};
+use std::ops::RangeFull;
 
 /// A set of elements.
-pub trait Set {
+/// Sets can be consumed into an iterator over their owned elements.
+pub trait Set: IntoIterator<Item = <Self as Set>::Elem> {
     /// The type of elements in the set.
     type Elem;
 
@@ -12,6 +16,17 @@ pub trait Set {
     where
         Self: 'a;
 
+    /// The type of iterator that removes and yields all elements of the set.
+    type Drain<'a>: Iterator<Item = Self::Elem>
+    where
+        Self: 'a;
+
+    /// The type of iterator that removes and yields the elements of the set that match a predicate.
+    type ExtractIf<'a, F>: Iterator<Item = Self::Elem>
+    where
+        Self: 'a,
+        F: FnMut(&Self::Elem) -> bool + 'a;
+
     /// Inserts a value into the set. Returns `true` if the value was not already present.
     fn insert(&mut self, value: Self::Elem) -> bool;
 
@@ -23,6 +38,44 @@ pub trait Set {
 
     /// Returns an iterator over the elements of the set.
     fn iter(&self) -> Self::Iter<'_>;
+
+    /// Keeps only the elements for which the predicate returns `true`.
+    fn retain<F>(&mut self, f: F)
+    where
+        F: FnMut(&Self::Elem) -> bool;
+
+    /// Removes all elements from the set, returning them as an iterator.
+    fn drain(&mut self) -> Self::Drain<'_>;
+
+    /// Returns an iterator that removes and yields the elements for which the predicate returns `true`.
+    /// Elements are only removed as the iterator is advanced.
+    fn extract_if<'a, F>(&'a mut self, pred: F) -> Self::ExtractIf<'a, F>
+    where
+        F: FnMut(&Self::Elem) -> bool + 'a;
+
+    /// Creates an empty set with space for at least `capacity` elements.
+    fn with_capacity(capacity: usize) -> Self
+    where
+        Self: Default,
+    {
+        let mut set = Self::default();
+        set.reserve(capacity);
+        set
+    }
+
+    /// Returns the number of elements the set can hold without reallocating.
+    /// Sets without a notion of capacity report their length.
+    fn capacity(&self) -> usize {
+        self.len()
+    }
+
+    /// Reserves space for at least `additional` more elements. Does nothing by default.
+    fn reserve(&mut self, additional: usize) {
+        let _ = additional;
+    }
+
+    /// Shrinks the capacity of the set as much as possible. Does nothing by default.
+    fn shrink_to_fit(&mut self) {}
 }
 
 /// A helper trait that allows us to query the set more flexibly, and matches the API of `HashMap` and `BTreeMap`.
@@ -38,13 +91,19 @@ where
     fn contains(&self, value: &Q) -> bool;
 }
 
-impl<T> Set for HashSet<T>
+impl<T, S> Set for HashSet<T, S>
 where
     T: Hash + Eq,
+    S: BuildHasher,
 {
     type Elem = T;
 
-    type Iter<'a> = hash_set::Iter<'a, T> where T: 'a;
+    type Iter<'a> = hash_set::Iter<'a, T> where Self: 'a;
+    type Drain<'a> = hash_set::Drain<'a, T> where Self: 'a;
+    type ExtractIf<'a, F> = hash_set::ExtractIf<'a, T, F>
+    where
+        Self: 'a,
+        F: FnMut(&T) -> bool + 'a;
 
     fn insert(&mut self, value: Self::Elem) -> bool {
         self.insert(value)
@@ -61,12 +120,43 @@ where
     fn iter(&self) -> Self::Iter<'_> {
         self.iter()
     }
+
+    fn retain<F>(&mut self, f: F)
+    where
+        F: FnMut(&T) -> bool,
+    {
+        self.retain(f)
+    }
+
+    fn drain(&mut self) -> Self::Drain<'_> {
+        self.drain()
+    }
+
+    fn extract_if<'a, F>(&'a mut self, pred: F) -> Self::ExtractIf<'a, F>
+    where
+        F: FnMut(&T) -> bool + 'a,
+    {
+        self.extract_if(pred)
+    }
+
+    fn capacity(&self) -> usize {
+        self.capacity()
+    }
+
+    fn reserve(&mut self, additional: usize) {
+        self.reserve(additional)
+    }
+
+    fn shrink_to_fit(&mut self) {
+        self.shrink_to_fit()
+    }
 }
 
-impl<T, Q> Container<Q> for HashSet<T>
+impl<T, S, Q> Container<Q> for HashSet<T, S>
 where
     Q: Hash + Eq + ?Sized,
     T: Hash + Eq + Borrow<Q>,
+    S: BuildHasher,
 {
     fn remove(&mut self, value: &Q) -> bool {
         self.remove(value)
@@ -83,6 +173,12 @@ where
 {
     type Elem = T;
     type Iter<'a> = btree_set::Iter<'a, T> where T: 'a;
+    // `BTreeSet` has no `drain`, so draining takes the whole set and iterates over it.
+    type Drain<'a> = btree_set::IntoIter<T> where T: 'a;
+    type ExtractIf<'a, F> = btree_set::ExtractIf<'a, T, RangeFull, F>
+    where
+        T: 'a,
+        F: FnMut(&T) -> bool + 'a;
 
     fn insert(&mut self, value: Self::Elem) -> bool {
         self.insert(value)
@@ -99,6 +195,24 @@ where
     fn iter(&self) -> Self::Iter<'_> {
         self.iter()
     }
+
+    fn retain<F>(&mut self, f: F)
+    where
+        F: FnMut(&T) -> bool,
+    {
+        self.retain(f)
+    }
+
+    fn drain(&mut self) -> Self::Drain<'_> {
+        std::mem::take(self).into_iter()
+    }
+
+    fn extract_if<'a, F>(&'a mut self, pred: F) -> Self::ExtractIf<'a, F>
+    where
+        F: FnMut(&T) -> bool + 'a,
+    {
+        self.extract_if(.., pred)
+    }
 }
 
 impl<T, Q> Container<Q> for BTreeSet<T>
@@ -114,3 +228,83 @@ where
         self.contains(value)
     }
 }
+
+// `IndexSet` keeps its elements in insertion order. Removing through the `Container` trait shifts the following
+// elements to preserve that order, at the cost of `O(n)` time.
+#[cfg(feature = "indexmap")]
+impl<T, S> Set for IndexSet<T, S>
+where
+    T: Hash + Eq,
+    S: BuildHasher,
+{
+    type Elem = T;
+
+    type Iter<'a> = index_set::Iter<'a, T> where Self: 'a;
+    type Drain<'a> = index_set::Drain<'a, T> where Self: 'a;
+    type ExtractIf<'a, F> = index_set::ExtractIf<'a, T, F>
+    where
+        Self: 'a,
+        F: FnMut(&T) -> bool + 'a;
+
+    fn insert(&mut self, value: Self::Elem) -> bool {
+        self.insert(value)
+    }
+
+    fn is_empty(&self) -> bool {
+        self.is_empty()
+    }
+
+    fn len(&self) -> usize {
+        self.len()
+    }
+
+    fn iter(&self) -> Self::Iter<'_> {
+        self.iter()
+    }
+
+    fn retain<F>(&mut self, f: F)
+    where
+        F: FnMut(&T) -> bool,
+    {
+        self.retain(f)
+    }
+
+    fn drain(&mut self) -> Self::Drain<'_> {
+        self.drain(..)
+    }
+
+    fn extract_if<'a, F>(&'a mut self, pred: F) -> Self::ExtractIf<'a, F>
+    where
+        F: FnMut(&T) -> bool + 'a,
+    {
+        self.extract_if(.., pred)
+    }
+
+    fn capacity(&self) -> usize {
+        self.capacity()
+    }
+
+    fn reserve(&mut self, additional: usize) {
+        self.reserve(additional)
+    }
+
+    fn shrink_to_fit(&mut self) {
+        self.shrink_to_fit()
+    }
+}
+
+#[cfg(feature = "indexmap")]
+impl<T, S, Q> Container<Q> for IndexSet<T, S>
+where
+    Q: Hash + Eq + ?Sized,
+    T: Hash + Eq + Borrow<Q>,
+    S: BuildHasher,
+{
+    fn remove(&mut self, value: &Q) -> bool {
+        self.shift_remove(value)
+    }
+
+    fn contains(&self, value: &Q) -> bool {
+        self.contains(value)
+    }
+}
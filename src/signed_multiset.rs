@@ -0,0 +1,540 @@
+use crate::counts::Count;
+use crate::maps::{DefaultMap, Entry, Lookup, Map, OccupiedEntry, VacantEntry};
+use crate::MultiSet;
+use std::borrow::Borrow;
+use std::collections::HashMap;
+
+/// A multi-set whose counts, called weights, may be negative, also known as a Z-set.
+/// This is useful to represent changes to a multi-set: adding a weight of `3` and then `-3` cancels out.
+/// Values whose weight drops to zero are removed, so only non-zero weights are stored.
+/// The set is implemented as a map from elements to their weights.
+#[derive(Default, Debug, PartialEq, Eq, Clone)]
+pub struct SignedMultiSet<M> {
+    map: M,
+    // The sum of all weights, which parallels the length of a `MultiSet`.
+    total_weight: isize,
+}
+
+impl<M> SignedMultiSet<M>
+where
+    M: DefaultMap,
+{
+    /// Creates a new empty signed multi-set.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use multi_containers::HashSignedMultiSet;
+    /// let mut set = HashSignedMultiSet::new();
+    /// assert!(set.is_empty());
+    /// set.add_delta(1, -1);
+    /// assert!(!set.is_empty());
+    /// ```
+    pub fn new() -> Self {
+        SignedMultiSet {
+            map: M::default(),
+            total_weight: 0,
+        }
+    }
+}
+
+impl<K> SignedMultiSet<HashMap<K, isize>> {
+    /// Creates a new empty signed multi-set with space for at least `capacity` unique values.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use multi_containers::HashSignedMultiSet;
+    /// let set: HashSignedMultiSet<i32> = HashSignedMultiSet::with_capacity(10);
+    /// assert!(set.is_empty());
+    /// ```
+    pub fn with_capacity(capacity: usize) -> Self {
+        SignedMultiSet {
+            map: HashMap::with_capacity(capacity),
+            total_weight: 0,
+        }
+    }
+}
+
+impl<M> SignedMultiSet<M>
+where
+    M: Map<Val = isize>,
+{
+    /// Adds `delta` to the weight of a value. Returns the previous weight of the value.
+    /// If the value was not present, the previous weight is 0.
+    /// If the weight of the value becomes 0, the value is removed.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the new weight of the value, or the new total weight, does not fit in an `isize`.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use multi_containers::HashSignedMultiSet;
+    /// let mut set = HashSignedMultiSet::new();
+    /// assert_eq!(set.add_delta(1, 3), 0);
+    /// assert_eq!(set.add_delta(1, -5), 3);
+    /// assert_eq!(set.weight(&1), -2);
+    /// assert_eq!(set.add_delta(1, 2), -2);
+    /// assert_eq!(set.contains(&1), false);
+    /// ```
+    pub fn add_delta(&mut self, value: M::Key, delta: isize) -> isize {
+        let total_weight = self
+            .total_weight
+            .checked_add(delta)
+            .expect("weight overflow");
+        let prev = match self.map.entry(value) {
+            Entry::Occupied(mut entry) => {
+                let prev = *entry.get();
+                let new = prev.checked_add(delta).expect("weight overflow");
+                if new == 0 {
+                    entry.remove_entry();
+                } else {
+                    *entry.get_mut() = new;
+                }
+                prev
+            }
+            Entry::Vacant(entry) => {
+                if delta != 0 {
+                    entry.insert(delta);
+                }
+                0
+            }
+        };
+        self.total_weight = total_weight;
+        prev
+    }
+
+    /// Adds the weights of `other` to the weights of `self`.
+    /// Values whose weights cancel out are removed.
+    ///
+    /// # Panics
+    ///
+    /// Panics if a new weight, or the new total weight, does not fit in an `isize`.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use multi_containers::BTreeSignedMultiSet;
+    /// let mut a = BTreeSignedMultiSet::from([(1, 2), (2, 1)]);
+    /// let b = BTreeSignedMultiSet::from([(1, -2), (3, -1)]);
+    /// a.merge(&b);
+    /// assert_eq!(a.weights().collect::<Vec<_>>(), vec![(&2, &1), (&3, &-1)]);
+    /// assert_eq!(a.total_weight(), 0);
+    /// ```
+    pub fn merge<N>(&mut self, other: &SignedMultiSet<N>)
+    where
+        N: Map<Key = M::Key, Val = isize>,
+        M::Key: Clone,
+    {
+        for (value, &weight) in other.weights() {
+            self.add_delta(value.clone(), weight);
+        }
+    }
+
+    /// Negates the weight of every value.
+    ///
+    /// # Panics
+    ///
+    /// Panics if a weight, or the total weight, is `isize::MIN`.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use multi_containers::BTreeSignedMultiSet;
+    /// let mut set = BTreeSignedMultiSet::from([(1, 2), (2, -1)]);
+    /// set.negate();
+    /// assert_eq!(set.weights().collect::<Vec<_>>(), vec![(&1, &-2), (&2, &1)]);
+    /// assert_eq!(set.total_weight(), -1);
+    /// ```
+    pub fn negate(&mut self) {
+        self.total_weight = self.total_weight.checked_neg().expect("weight overflow");
+        for (_, weight) in self.map.iter_mut() {
+            *weight = weight.checked_neg().expect("weight overflow");
+        }
+    }
+
+    /// Returns a multi-set of the values with a positive weight, counted as many times as their weight.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use multi_containers::{BTreeMultiSet, BTreeSignedMultiSet};
+    /// let set = BTreeSignedMultiSet::from([(1, 2), (2, -1), (3, 1)]);
+    /// let positive: BTreeMultiSet<_> = set.positive_part();
+    /// assert_eq!(positive.iter().collect::<Vec<_>>(), vec![&1, &1, &3]);
+    /// ```
+    pub fn positive_part<N>(&self) -> MultiSet<N>
+    where
+        N: Map<Key = M::Key, Val: Count> + Default,
+        M::Key: Clone,
+    {
+        self.part(|weight| weight > 0)
+    }
+
+    /// Returns a multi-set of the values with a negative weight, counted as many times as the magnitude of
+    /// their weight.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use multi_containers::{BTreeMultiSet, BTreeSignedMultiSet};
+    /// let set = BTreeSignedMultiSet::from([(1, 2), (2, -2), (3, 1)]);
+    /// let negative: BTreeMultiSet<_> = set.negative_part();
+    /// assert_eq!(negative.iter().collect::<Vec<_>>(), vec![&2, &2]);
+    /// ```
+    pub fn negative_part<N>(&self) -> MultiSet<N>
+    where
+        N: Map<Key = M::Key, Val: Count> + Default,
+        M::Key: Clone,
+    {
+        self.part(|weight| weight < 0)
+    }
+
+    // Returns a multi-set of the values whose weight matches a predicate, counted by the magnitude of their weight.
+    fn part<N, F>(&self, pred: F) -> MultiSet<N>
+    where
+        N: Map<Key = M::Key, Val: Count> + Default,
+        M::Key: Clone,
+        F: Fn(isize) -> bool,
+    {
+        let mut set = MultiSet::default();
+        for (value, &weight) in self.weights() {
+            if pred(weight) {
+                set.insert_some(value.clone(), weight.unsigned_abs());
+            }
+        }
+        set
+    }
+
+    /// Removes a value from the signed multi-set. Returns its previous weight.
+    /// If the value was not present, the previous weight is 0.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use multi_containers::HashSignedMultiSet;
+    /// let mut set = HashSignedMultiSet::new();
+    /// set.add_delta(1, -2);
+    /// assert_eq!(set.remove(&1), -2);
+    /// assert_eq!(set.remove(&1), 0);
+    /// assert_eq!(set.total_weight(), 0);
+    /// ```
+    pub fn remove<Q>(&mut self, value: &Q) -> isize
+    where
+        M: Lookup<Q>,
+        M::Key: Borrow<Q>,
+        Q: ?Sized,
+    {
+        match self.map.remove(value) {
+            Some(weight) => {
+                self.total_weight = self
+                    .total_weight
+                    .checked_sub(weight)
+                    .expect("weight overflow");
+                weight
+            }
+            None => 0,
+        }
+    }
+
+    /// Removes all values from the signed multi-set, returning them as an iterator of owned (value, weight) pairs.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use multi_containers::BTreeSignedMultiSet;
+    /// let mut set = BTreeSignedMultiSet::from([(1, 2), (2, -1)]);
+    /// assert_eq!(set.drain().collect::<Vec<_>>(), vec![(1, 2), (2, -1)]);
+    /// assert!(set.is_empty());
+    /// ```
+    pub fn drain(&mut self) -> M::Drain<'_> {
+        self.total_weight = 0;
+        self.map.drain()
+    }
+
+    /// Returns `true` if the signed multi-set contains the given value, with a non-zero weight.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use multi_containers::HashSignedMultiSet;
+    /// let mut set = HashSignedMultiSet::new();
+    /// set.add_delta(1, -1);
+    /// assert_eq!(set.contains(&1), true);
+    /// assert_eq!(set.contains(&2), false);
+    /// ```
+    pub fn contains<Q>(&self, value: &Q) -> bool
+    where
+        M: Lookup<Q>,
+        M::Key: Borrow<Q>,
+        Q: ?Sized,
+    {
+        self.map.contains_key(value)
+    }
+
+    /// Returns the weight of a value, which is 0 if the value is not present.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use multi_containers::HashSignedMultiSet;
+    /// let mut set = HashSignedMultiSet::new();
+    /// set.add_delta(1, -3);
+    /// assert_eq!(set.weight(&1), -3);
+    /// assert_eq!(set.weight(&2), 0);
+    /// ```
+    pub fn weight<Q>(&self, value: &Q) -> isize
+    where
+        M: Lookup<Q>,
+        M::Key: Borrow<Q>,
+        Q: ?Sized,
+    {
+        self.map.get(value).copied().unwrap_or(0)
+    }
+
+    /// Returns the sum of the weights of all values.
+    /// Positive and negative weights cancel out, so this may be 0 even if the signed multi-set is not empty.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use multi_containers::HashSignedMultiSet;
+    /// let mut set = HashSignedMultiSet::new();
+    /// set.add_delta(1, 3);
+    /// set.add_delta(2, -1);
+    /// assert_eq!(set.total_weight(), 2);
+    /// ```
+    pub fn total_weight(&self) -> isize {
+        self.total_weight
+    }
+
+    /// Returns `true` if no value has a non-zero weight.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use multi_containers::HashSignedMultiSet;
+    /// let mut set = HashSignedMultiSet::new();
+    /// set.add_delta(1, 1);
+    /// set.add_delta(2, -1);
+    /// assert_eq!(set.is_empty(), false);
+    /// set.add_delta(1, -1);
+    /// set.add_delta(2, 1);
+    /// assert_eq!(set.is_empty(), true);
+    /// ```
+    pub fn is_empty(&self) -> bool {
+        self.map.is_empty()
+    }
+
+    /// Returns the number of values with a non-zero weight.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use multi_containers::HashSignedMultiSet;
+    /// let mut set = HashSignedMultiSet::new();
+    /// set.add_delta(1, 3);
+    /// set.add_delta(2, -1);
+    /// assert_eq!(set.unique_len(), 2);
+    /// ```
+    pub fn unique_len(&self) -> usize {
+        self.map.len()
+    }
+
+    /// Returns an iterator over the values of the signed multi-set, with their weights.
+    /// The order of the values depends on the underlying map implementation.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use multi_containers::BTreeSignedMultiSet;
+    /// let set = BTreeSignedMultiSet::from([(1, 2), (2, -1)]);
+    /// assert_eq!(set.weights().collect::<Vec<_>>(), vec![(&1, &2), (&2, &-1)]);
+    /// ```
+    pub fn weights(&self) -> M::Iter<'_> {
+        self.map.iter()
+    }
+
+    /// Consumes the signed multi-set, returning an iterator over the values with their weights.
+    /// The order of the values depends on the underlying map implementation.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use multi_containers::BTreeSignedMultiSet;
+    /// let set = BTreeSignedMultiSet::from([(1, 2), (2, -1)]);
+    /// assert_eq!(set.into_weights().collect::<Vec<_>>(), vec![(1, 2), (2, -1)]);
+    /// ```
+    pub fn into_weights(self) -> <M as IntoIterator>::IntoIter {
+        self.map.into_iter()
+    }
+
+    /// Panics if the bookkeeping of the signed multi-set is inconsistent:
+    /// every weight must be non-zero, and the total weight must match the sum of the weights.
+    #[cfg(test)]
+    pub(crate) fn check_invariants(&self) {
+        assert!(
+            self.map.values().all(|&weight| weight != 0),
+            "signed multi-set contains a zero weight"
+        );
+        assert_eq!(
+            self.total_weight,
+            self.map.values().sum::<isize>(),
+            "signed multi-set total weight does not match its weights"
+        );
+    }
+}
+
+impl<T, M> Extend<(T, isize)> for SignedMultiSet<M>
+where
+    M: Map<Key = T, Val = isize>,
+{
+    fn extend<I>(&mut self, iter: I)
+    where
+        I: IntoIterator<Item = (T, isize)>,
+    {
+        for (value, delta) in iter {
+            self.add_delta(value, delta);
+        }
+    }
+}
+
+impl<T, M> FromIterator<(T, isize)> for SignedMultiSet<M>
+where
+    M: DefaultMap<Key = T, Val = isize>,
+{
+    fn from_iter<I>(iter: I) -> Self
+    where
+        I: IntoIterator<Item = (T, isize)>,
+    {
+        let mut set = SignedMultiSet::default();
+        set.extend(iter);
+        set
+    }
+}
+
+impl<M, const N: usize> From<[(M::Key, isize); N]> for SignedMultiSet<M>
+where
+    M: DefaultMap<Val = isize>,
+{
+    fn from(array: [(M::Key, isize); N]) -> Self {
+        array.into_iter().collect::<Self>()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    macro_rules! base_test_suite {
+        ($mod_name:ident, $set_maker:expr) => {
+            mod $mod_name {
+                use crate::test_utils::unordered_elements_are;
+                use crate::{BTreeMultiSet, SignedMultiSet};
+
+                #[test]
+                fn add_delta() {
+                    let mut set = $set_maker;
+                    assert_eq!(set.add_delta(1, 3), 0);
+                    set.check_invariants();
+                    assert_eq!(set.add_delta(1, -3), 3);
+                    set.check_invariants();
+                    assert!(!set.contains(&1));
+                    assert!(set.is_empty());
+                    assert_eq!(set.add_delta(2, 0), 0);
+                    assert!(set.is_empty());
+                    assert_eq!(set.add_delta(2, -4), 0);
+                    assert_eq!(set.add_delta(3, 1), 0);
+                    set.check_invariants();
+                    assert_eq!(set.weight(&2), -4);
+                    assert_eq!(set.total_weight(), -3);
+                    assert_eq!(set.unique_len(), 2);
+                }
+
+                #[test]
+                fn merge() {
+                    let mut a = $set_maker;
+                    a.extend([(1, 2), (2, -1), (3, 5)]);
+                    let mut b = $set_maker;
+                    b.extend([(1, -2), (2, -1), (4, 1)]);
+                    a.merge(&b);
+                    a.check_invariants();
+                    assert!(unordered_elements_are(
+                        a.weights().map(|(&v, &w)| (v, w)),
+                        vec![(2, -2), (3, 5), (4, 1)]
+                    ));
+                    assert_eq!(a.total_weight(), 4);
+                }
+
+                #[test]
+                fn merge_across_backends() {
+                    let mut a = $set_maker;
+                    a.add_delta(1, 2);
+                    let b = SignedMultiSet::<std::collections::BTreeMap<_, _>>::from([(1, -2)]);
+                    a.merge(&b);
+                    a.check_invariants();
+                    assert!(a.is_empty());
+                }
+
+                #[test]
+                fn negate() {
+                    let mut set = $set_maker;
+                    set.extend([(1, 2), (2, -3)]);
+                    set.negate();
+                    set.check_invariants();
+                    assert_eq!(set.weight(&1), -2);
+                    assert_eq!(set.weight(&2), 3);
+                    assert_eq!(set.total_weight(), 1);
+                }
+
+                #[test]
+                fn parts() {
+                    let mut set = $set_maker;
+                    set.extend([(1, 2), (2, -3), (3, 1)]);
+                    let positive: BTreeMultiSet<_> = set.positive_part();
+                    positive.check_invariants();
+                    assert_eq!(positive.iter().collect::<Vec<_>>(), vec![&1, &1, &3]);
+                    let negative: BTreeMultiSet<_> = set.negative_part();
+                    negative.check_invariants();
+                    assert_eq!(negative.iter().collect::<Vec<_>>(), vec![&2, &2, &2]);
+                }
+
+                #[test]
+                fn remove_and_drain() {
+                    let mut set = $set_maker;
+                    set.extend([(1, 2), (2, -3), (3, 1)]);
+                    assert_eq!(set.remove(&2), -3);
+                    assert_eq!(set.remove(&2), 0);
+                    set.check_invariants();
+                    assert_eq!(set.total_weight(), 3);
+                    assert!(unordered_elements_are(set.drain(), vec![(1, 2), (3, 1)]));
+                    set.check_invariants();
+                    assert!(set.is_empty());
+                    assert_eq!(set.total_weight(), 0);
+                }
+
+                #[test]
+                #[should_panic(expected = "weight overflow")]
+                fn overflow_panics() {
+                    let mut set = $set_maker;
+                    set.add_delta(1, isize::MAX);
+                    set.add_delta(2, 1);
+                }
+
+                #[test]
+                #[should_panic(expected = "weight overflow")]
+                fn remove_overflow_panics() {
+                    let mut set = $set_maker;
+                    set.add_delta(1, isize::MAX);
+                    set.add_delta(2, -isize::MAX);
+                    set.add_delta(3, isize::MAX);
+                    set.remove(&2);
+                }
+            }
+        };
+    }
+
+    base_test_suite!(hash_values, crate::HashSignedMultiSet::new());
+
+    base_test_suite!(sorted_values, crate::BTreeSignedMultiSet::new());
+}
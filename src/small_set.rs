@@ -0,0 +1,691 @@
+use crate::sets::{Container, Set};
+use std::borrow::Borrow;
+use std::collections::HashSet;
+use std::fmt::{self, Debug};
+use std::iter::Flatten;
+use std::{array, mem, slice};
+
+/// A set that stores up to `N` elements inline, and spills into a set of type `S` when it grows past that.
+/// This is meant for the value sets of a multi-map where most keys have only a few values: an inline set needs no
+/// allocation of its own, and lookups scan its elements linearly. The spilled set is boxed, so that the inline
+/// storage is not padded to its size.
+///
+/// Inline elements are kept in insertion order, and compared with `Eq`. Once spilled, the set stays spilled
+/// until `Set::shrink_to_fit` is called with at most `N` elements left.
+///
+/// # Example
+///
+/// ```
+/// use multi_containers::small_set::SmallSet;
+/// use multi_containers::MultiMapBuilder;
+/// let mut map = MultiMapBuilder::hash_keys::<_, SmallSet<i32, 2>>()
+///     .with_set_type()
+///     .build();
+/// map.insert("a", 1);
+/// map.insert("a", 2);
+/// assert!(!map.get("a").unwrap().spilled());
+/// map.insert("a", 3);
+/// assert!(map.get("a").unwrap().spilled());
+/// assert_eq!(map.num_mappings(), 3);
+/// ```
+#[derive(Clone)]
+pub struct SmallSet<T, const N: usize, S = HashSet<T>> {
+    storage: Storage<T, N, S>,
+}
+
+#[derive(Clone)]
+enum Storage<T, const N: usize, S> {
+    // The first `len` items are occupied, and the rest are empty.
+    Inline { items: [Option<T>; N], len: usize },
+    Spilled(Box<S>),
+}
+
+impl<T, const N: usize, S> SmallSet<T, N, S> {
+    /// Creates a new, empty set, which stores its elements inline.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use multi_containers::small_set::SmallSet;
+    /// use std::collections::BTreeSet;
+    /// let set = SmallSet::<i32, 4, BTreeSet<i32>>::new();
+    /// assert!(!set.spilled());
+    /// ```
+    pub fn new() -> Self {
+        SmallSet {
+            storage: Storage::Inline {
+                items: array::from_fn(|_| None),
+                len: 0,
+            },
+        }
+    }
+
+    /// Returns `true` if the set has spilled its elements into a set of type `S`.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use multi_containers::small_set::SmallSet;
+    /// use multi_containers::sets::Set;
+    /// use std::collections::BTreeSet;
+    /// let mut set = SmallSet::<i32, 1, BTreeSet<i32>>::new();
+    /// set.insert(1);
+    /// assert!(!set.spilled());
+    /// set.insert(2);
+    /// assert!(set.spilled());
+    /// ```
+    pub fn spilled(&self) -> bool {
+        matches!(self.storage, Storage::Spilled(_))
+    }
+}
+
+impl<T, const N: usize, S> SmallSet<T, N, S>
+where
+    S: Set<Elem = T>,
+{
+    /// Returns the number of elements in the set.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use multi_containers::small_set::SmallSet;
+    /// let set: SmallSet<i32, 2> = [1, 2, 3, 1].into_iter().collect();
+    /// assert_eq!(set.len(), 3);
+    /// ```
+    pub fn len(&self) -> usize {
+        match &self.storage {
+            Storage::Inline { len, .. } => *len,
+            Storage::Spilled(set) => set.len(),
+        }
+    }
+
+    /// Returns `true` if the set is empty.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use multi_containers::small_set::SmallSet;
+    /// let set = SmallSet::<i32, 2>::new();
+    /// assert!(set.is_empty());
+    /// ```
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns an iterator over the elements of the set.
+    /// Inline elements are returned in insertion order, and spilled elements in the order of `S`.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use multi_containers::small_set::SmallSet;
+    /// let set: SmallSet<i32, 4> = [3, 1, 2].into_iter().collect();
+    /// assert_eq!(set.iter().collect::<Vec<_>>(), vec![&3, &1, &2]);
+    /// ```
+    pub fn iter(&self) -> Iter<'_, T, S> {
+        let inner = match &self.storage {
+            Storage::Inline { items, len } => IterInner::Inline(items[..*len].iter().flatten()),
+            Storage::Spilled(set) => IterInner::Spilled(set.iter()),
+        };
+        Iter { inner }
+    }
+
+    /// Returns `true` if the set contains the given value.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use multi_containers::small_set::SmallSet;
+    /// let set: SmallSet<String, 2> = ["a".to_string()].into_iter().collect();
+    /// assert!(set.contains("a"));
+    /// assert!(!set.contains("b"));
+    /// ```
+    pub fn contains<Q>(&self, value: &Q) -> bool
+    where
+        T: Borrow<Q>,
+        S: Container<Q>,
+        Q: Eq + ?Sized,
+    {
+        match &self.storage {
+            Storage::Inline { items, len } => items[..*len]
+                .iter()
+                .flatten()
+                .any(|item| item.borrow() == value),
+            Storage::Spilled(set) => set.contains(value),
+        }
+    }
+}
+
+impl<T, const N: usize, S> SmallSet<T, N, S>
+where
+    T: Eq,
+    S: Set<Elem = T> + Default,
+{
+    /// Inserts a value into the set, spilling it if it is full. Returns `true` if the value was not already present.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use multi_containers::small_set::SmallSet;
+    /// let mut set = SmallSet::<i32, 1>::new();
+    /// assert_eq!(set.insert(1), true);
+    /// assert_eq!(set.insert(1), false);
+    /// assert_eq!(set.insert(2), true);
+    /// assert!(set.spilled());
+    /// ```
+    pub fn insert(&mut self, value: T) -> bool {
+        match &mut self.storage {
+            Storage::Inline { items, len } => {
+                if items[..*len].iter().flatten().any(|item| *item == value) {
+                    return false;
+                }
+                if *len < N {
+                    items[*len] = Some(value);
+                    *len += 1;
+                    return true;
+                }
+            }
+            Storage::Spilled(set) => return set.insert(value),
+        }
+        self.spill(1);
+        self.insert(value)
+    }
+
+    /// Removes a value from the set. Returns `true` if the value was present.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use multi_containers::small_set::SmallSet;
+    /// let mut set: SmallSet<i32, 2> = [1, 2].into_iter().collect();
+    /// assert_eq!(set.remove(&1), true);
+    /// assert_eq!(set.remove(&1), false);
+    /// assert_eq!(set.len(), 1);
+    /// ```
+    pub fn remove<Q>(&mut self, value: &Q) -> bool
+    where
+        T: Borrow<Q>,
+        S: Container<Q>,
+        Q: Eq + ?Sized,
+    {
+        match &mut self.storage {
+            Storage::Inline { items, len } => {
+                match items[..*len]
+                    .iter()
+                    .flatten()
+                    .position(|item| item.borrow() == value)
+                {
+                    Some(index) => {
+                        remove_inline(items, len, index);
+                        true
+                    }
+                    None => false,
+                }
+            }
+            Storage::Spilled(set) => set.remove(value),
+        }
+    }
+
+    /// Keeps only the elements for which the predicate returns `true`.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use multi_containers::small_set::SmallSet;
+    /// let mut set: SmallSet<i32, 4> = [1, 2, 3, 4].into_iter().collect();
+    /// set.retain(|v| v % 2 == 0);
+    /// assert_eq!(set.iter().collect::<Vec<_>>(), vec![&2, &4]);
+    /// ```
+    pub fn retain<F>(&mut self, mut f: F)
+    where
+        F: FnMut(&T) -> bool,
+    {
+        match &mut self.storage {
+            Storage::Inline { items, len } => {
+                let mut index = 0;
+                while index < *len {
+                    if f(items[index].as_ref().expect("inline item is missing")) {
+                        index += 1;
+                    } else {
+                        remove_inline(items, len, index);
+                    }
+                }
+            }
+            Storage::Spilled(set) => set.retain(f),
+        }
+    }
+
+    /// Removes all elements from the set. A spilled set keeps its allocation.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use multi_containers::small_set::SmallSet;
+    /// let mut set: SmallSet<i32, 2> = [1, 2, 3].into_iter().collect();
+    /// set.clear();
+    /// assert!(set.is_empty());
+    /// ```
+    pub fn clear(&mut self) {
+        self.retain(|_| false);
+    }
+
+    // Moves the inline elements into a set of type `S`, with space for `additional` more elements.
+    fn spill(&mut self, additional: usize) {
+        if let Storage::Inline { items, len } = &mut self.storage {
+            let mut set = S::with_capacity(*len + additional);
+            for item in items[..*len].iter_mut() {
+                set.insert(item.take().expect("inline item is missing"));
+            }
+            self.storage = Storage::Spilled(Box::new(set));
+        }
+    }
+}
+
+// Removes the inline item at `index`, shifting the following items down to keep them in order.
+fn remove_inline<T>(items: &mut [Option<T>], len: &mut usize, index: usize) -> T {
+    let item = items[index].take().expect("inline item is missing");
+    items[index..*len].rotate_left(1);
+    *len -= 1;
+    item
+}
+
+impl<T, const N: usize, S> Default for SmallSet<T, N, S> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T, const N: usize, S> Debug for SmallSet<T, N, S>
+where
+    T: Debug,
+    S: Set<Elem = T>,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_set().entries(self.iter()).finish()
+    }
+}
+
+impl<T, const N: usize, S> PartialEq for SmallSet<T, N, S>
+where
+    T: Eq,
+    S: Container<T> + Set<Elem = T> + Default,
+{
+    fn eq(&self, other: &Self) -> bool {
+        self.len() == other.len() && self.iter().all(|value| other.contains(value))
+    }
+}
+
+impl<T, const N: usize, S> Eq for SmallSet<T, N, S>
+where
+    T: Eq,
+    S: Container<T> + Set<Elem = T> + Default,
+{
+}
+
+impl<T, const N: usize, S> Set for SmallSet<T, N, S>
+where
+    T: Eq,
+    S: Set<Elem = T> + Default,
+{
+    type Elem = T;
+    type Iter<'a>
+        = Iter<'a, T, S>
+    where
+        Self: 'a;
+    type Drain<'a>
+        = Drain<'a, T, N, S>
+    where
+        Self: 'a;
+    type ExtractIf<'a, F>
+        = ExtractIf<'a, T, S, F>
+    where
+        Self: 'a,
+        F: FnMut(&T) -> bool + 'a;
+
+    fn insert(&mut self, value: T) -> bool {
+        self.insert(value)
+    }
+
+    fn is_empty(&self) -> bool {
+        self.is_empty()
+    }
+
+    fn len(&self) -> usize {
+        self.len()
+    }
+
+    fn iter(&self) -> Self::Iter<'_> {
+        self.iter()
+    }
+
+    fn retain<F>(&mut self, f: F)
+    where
+        F: FnMut(&T) -> bool,
+    {
+        self.retain(f)
+    }
+
+    fn drain(&mut self) -> Self::Drain<'_> {
+        let inner = match &mut self.storage {
+            Storage::Inline { items, len } => {
+                *len = 0;
+                DrainInner::Inline(
+                    mem::replace(items, array::from_fn(|_| None))
+                        .into_iter()
+                        .flatten(),
+                )
+            }
+            Storage::Spilled(set) => DrainInner::Spilled(set.drain()),
+        };
+        Drain { inner }
+    }
+
+    fn extract_if<'a, F>(&'a mut self, pred: F) -> Self::ExtractIf<'a, F>
+    where
+        F: FnMut(&T) -> bool + 'a,
+    {
+        let inner = match &mut self.storage {
+            Storage::Inline { items, len } => ExtractIfInner::Inline {
+                items,
+                len,
+                index: 0,
+                pred,
+            },
+            Storage::Spilled(set) => ExtractIfInner::Spilled(set.extract_if(pred)),
+        };
+        ExtractIf { inner }
+    }
+
+    fn capacity(&self) -> usize {
+        match &self.storage {
+            Storage::Inline { .. } => N,
+            Storage::Spilled(set) => set.capacity(),
+        }
+    }
+
+    fn reserve(&mut self, additional: usize) {
+        match &mut self.storage {
+            Storage::Inline { len, .. } => {
+                if *len + additional > N {
+                    self.spill(additional);
+                }
+            }
+            Storage::Spilled(set) => set.reserve(additional),
+        }
+    }
+
+    fn shrink_to_fit(&mut self) {
+        match &mut self.storage {
+            Storage::Inline { .. } => {}
+            Storage::Spilled(set) if set.len() <= N => {
+                let mut items = array::from_fn(|_| None);
+                let mut len = 0;
+                for value in set.drain() {
+                    items[len] = Some(value);
+                    len += 1;
+                }
+                self.storage = Storage::Inline { items, len };
+            }
+            Storage::Spilled(set) => set.shrink_to_fit(),
+        }
+    }
+}
+
+impl<T, const N: usize, S, Q> Container<Q> for SmallSet<T, N, S>
+where
+    T: Eq + Borrow<Q>,
+    S: Container<Q> + Set<Elem = T> + Default,
+    Q: Eq + ?Sized,
+{
+    fn remove(&mut self, value: &Q) -> bool {
+        self.remove(value)
+    }
+
+    fn contains(&self, value: &Q) -> bool {
+        self.contains(value)
+    }
+}
+
+impl<T, const N: usize, S> IntoIterator for SmallSet<T, N, S>
+where
+    S: Set<Elem = T>,
+{
+    type Item = T;
+    type IntoIter = IntoIter<T, N, S>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        let inner = match self.storage {
+            Storage::Inline { items, .. } => IntoIterInner::Inline(items.into_iter().flatten()),
+            Storage::Spilled(set) => IntoIterInner::Spilled((*set).into_iter()),
+        };
+        IntoIter { inner }
+    }
+}
+
+impl<T, const N: usize, S> FromIterator<T> for SmallSet<T, N, S>
+where
+    T: Eq,
+    S: Set<Elem = T> + Default,
+{
+    fn from_iter<I>(iter: I) -> Self
+    where
+        I: IntoIterator<Item = T>,
+    {
+        let mut set = Self::new();
+        for value in iter {
+            set.insert(value);
+        }
+        set
+    }
+}
+
+/// An iterator over the elements of a `SmallSet`.
+pub struct Iter<'a, T, S>
+where
+    S: Set<Elem = T> + 'a,
+{
+    inner: IterInner<'a, T, S>,
+}
+
+enum IterInner<'a, T, S>
+where
+    S: Set<Elem = T> + 'a,
+{
+    Inline(Flatten<slice::Iter<'a, Option<T>>>),
+    Spilled(S::Iter<'a>),
+}
+
+impl<'a, T, S> Iterator for Iter<'a, T, S>
+where
+    S: Set<Elem = T> + 'a,
+{
+    type Item = &'a T;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match &mut self.inner {
+            IterInner::Inline(iter) => iter.next(),
+            IterInner::Spilled(iter) => iter.next(),
+        }
+    }
+}
+
+/// An iterator that removes and yields all elements of a `SmallSet`.
+pub struct Drain<'a, T, const N: usize, S>
+where
+    S: Set<Elem = T> + 'a,
+{
+    inner: DrainInner<'a, T, N, S>,
+}
+
+enum DrainInner<'a, T, const N: usize, S>
+where
+    S: Set<Elem = T> + 'a,
+{
+    Inline(Flatten<array::IntoIter<Option<T>, N>>),
+    Spilled(S::Drain<'a>),
+}
+
+impl<'a, T, const N: usize, S> Iterator for Drain<'a, T, N, S>
+where
+    S: Set<Elem = T> + 'a,
+{
+    type Item = T;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match &mut self.inner {
+            DrainInner::Inline(iter) => iter.next(),
+            DrainInner::Spilled(iter) => iter.next(),
+        }
+    }
+}
+
+/// An iterator that removes and yields the elements of a `SmallSet` that match a predicate.
+pub struct ExtractIf<'a, T, S, F>
+where
+    S: Set<Elem = T> + 'a,
+    F: FnMut(&T) -> bool + 'a,
+{
+    inner: ExtractIfInner<'a, T, S, F>,
+}
+
+enum ExtractIfInner<'a, T, S, F>
+where
+    S: Set<Elem = T> + 'a,
+    F: FnMut(&T) -> bool + 'a,
+{
+    Inline {
+        items: &'a mut [Option<T>],
+        len: &'a mut usize,
+        index: usize,
+        pred: F,
+    },
+    Spilled(S::ExtractIf<'a, F>),
+}
+
+impl<'a, T, S, F> Iterator for ExtractIf<'a, T, S, F>
+where
+    S: Set<Elem = T> + 'a,
+    F: FnMut(&T) -> bool + 'a,
+{
+    type Item = T;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match &mut self.inner {
+            ExtractIfInner::Inline {
+                items,
+                len,
+                index,
+                pred,
+            } => {
+                while *index < **len {
+                    if pred(items[*index].as_ref().expect("inline item is missing")) {
+                        return Some(remove_inline(items, len, *index));
+                    }
+                    *index += 1;
+                }
+                None
+            }
+            ExtractIfInner::Spilled(iter) => iter.next(),
+        }
+    }
+}
+
+/// An iterator that consumes a `SmallSet` and yields its elements.
+pub struct IntoIter<T, const N: usize, S>
+where
+    S: Set<Elem = T>,
+{
+    inner: IntoIterInner<T, N, S>,
+}
+
+enum IntoIterInner<T, const N: usize, S>
+where
+    S: Set<Elem = T>,
+{
+    Inline(Flatten<array::IntoIter<Option<T>, N>>),
+    Spilled(S::IntoIter),
+}
+
+impl<T, const N: usize, S> Iterator for IntoIter<T, N, S>
+where
+    S: Set<Elem = T>,
+{
+    type Item = T;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match &mut self.inner {
+            IntoIterInner::Inline(iter) => iter.next(),
+            IntoIterInner::Spilled(iter) => iter.next(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::SmallSet;
+    use crate::sets::Set;
+    use crate::test_utils::unordered_elements_are;
+    use std::collections::{BTreeSet, HashSet};
+
+    type Small = SmallSet<i32, 2, HashSet<i32>>;
+
+    #[test]
+    fn spills_and_unspills() {
+        let mut set = Small::new();
+        assert!(set.insert(1));
+        assert!(!set.insert(1));
+        assert!(set.insert(2));
+        assert!(!set.spilled());
+        assert_eq!(set.capacity(), 2);
+        assert!(set.insert(3));
+        assert!(set.spilled());
+        assert!(unordered_elements_are(set.iter().copied(), vec![1, 2, 3]));
+        assert!(set.remove(&2));
+        assert!(!set.remove(&2));
+        set.shrink_to_fit();
+        assert!(!set.spilled());
+        assert!(unordered_elements_are(set.iter().copied(), vec![1, 3]));
+        set.reserve(5);
+        assert!(set.spilled());
+        assert_eq!(set.len(), 2);
+    }
+
+    #[test]
+    fn inline_removal_keeps_order() {
+        let mut set: SmallSet<i32, 4, BTreeSet<i32>> = [4, 1, 3, 2].into_iter().collect();
+        assert!(set.iter().eq(&[4, 1, 3, 2]));
+        assert!(set.remove(&1));
+        assert!(set.contains(&3));
+        assert!(!set.contains(&1));
+        assert!(set.iter().eq(&[4, 3, 2]));
+        set.retain(|v| *v != 3);
+        assert!(set.iter().eq(&[4, 2]));
+        assert_eq!(set.extract_if(|v| *v > 3).collect::<Vec<_>>(), vec![4]);
+        assert!(set.iter().eq(&[2]));
+        assert!(set.insert(5));
+        assert_eq!(set.drain().collect::<Vec<_>>(), vec![2, 5]);
+        assert!(set.is_empty());
+        assert!(set.insert(6));
+        assert_eq!(set.into_iter().collect::<Vec<_>>(), vec![6]);
+    }
+
+    #[test]
+    fn spilled_operations() {
+        let mut set: SmallSet<i32, 1, BTreeSet<i32>> = [3, 1, 2].into_iter().collect();
+        assert!(set.iter().eq(&[1, 2, 3]));
+        set.retain(|v| *v != 2);
+        assert_eq!(set.extract_if(|v| *v == 3).collect::<Vec<_>>(), vec![3]);
+        assert_eq!(set.drain().collect::<Vec<_>>(), vec![1]);
+        assert!(set.is_empty());
+    }
+
+    #[test]
+    fn equality_ignores_storage() {
+        let a: Small = [1, 2].into_iter().collect();
+        let mut b: Small = [2, 1, 3].into_iter().collect();
+        assert_ne!(a, b);
+        b.remove(&3);
+        assert!(b.spilled());
+        assert_eq!(a, b);
+    }
+}
@@ -0,0 +1,528 @@
+use crate::counts::Count;
+use crate::maps::{
+    self, is_before_end, is_before_start, DefaultMap, KeyOrder, Lookup, Map, Rank, Select,
+    SortedMap,
+};
+use crate::sorted_vec_set::Extractor;
+use std::borrow::Borrow;
+use std::ops::RangeBounds;
+use std::{iter, mem, slice, vec};
+
+/// A sorted map backed by a `Vec` of entries, sorted by key.
+/// Lookups are binary searches over contiguous memory, which makes this map faster and smaller than `BTreeMap`
+/// for data that is built once and queried many times. Inserting or removing a single entry takes `O(n)` time,
+/// so build the map in bulk with `FromIterator` or `From<Vec<(K, V)>>`, which sort their input in `O(n log n)`.
+///
+/// # Example
+///
+/// ```
+/// use multi_containers::sorted_vec_map::SortedVecMap;
+/// use multi_containers::maps::{Lookup, SortedMap};
+/// let map = SortedVecMap::from(vec![(3, 'c'), (1, 'a'), (2, 'b')]);
+/// assert_eq!(map.get(&2), Some(&'b'));
+/// assert_eq!(map.range(2..).map(|(k, _)| *k).collect::<Vec<_>>(), vec![2, 3]);
+/// ```
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct SortedVecMap<K, V> {
+    entries: Vec<(K, V)>,
+}
+
+/// An iterator over the entries of a `SortedVecMap`, or of a range of it, in sorted order.
+pub type Iter<'a, K, V> = iter::Map<slice::Iter<'a, (K, V)>, fn(&'a (K, V)) -> (&'a K, &'a V)>;
+
+/// An iterator over the entries of a `SortedVecMap`, or of a range of it, with mutable references to the values.
+pub type IterMut<'a, K, V> =
+    iter::Map<slice::IterMut<'a, (K, V)>, fn(&'a mut (K, V)) -> (&'a K, &'a mut V)>;
+
+/// An iterator over the keys of a `SortedVecMap`, in sorted order.
+pub type Keys<'a, K, V> = iter::Map<slice::Iter<'a, (K, V)>, fn(&'a (K, V)) -> &'a K>;
+
+/// An iterator over the values of a `SortedVecMap`, in the order of their keys.
+pub type Values<'a, K, V> = iter::Map<slice::Iter<'a, (K, V)>, fn(&'a (K, V)) -> &'a V>;
+
+/// An iterator that removes and yields the entries of a `SortedVecMap` that match a predicate.
+/// If it is dropped early, the entries that were not visited yet are kept.
+pub struct ExtractIf<'a, K, V, F> {
+    extractor: Extractor<'a, (K, V)>,
+    pred: F,
+}
+
+impl<K, V, F> Iterator for ExtractIf<'_, K, V, F>
+where
+    F: FnMut(&K, &mut V) -> bool,
+{
+    type Item = (K, V);
+
+    fn next(&mut self) -> Option<(K, V)> {
+        let pred = &mut self.pred;
+        self.extractor.next_by(|(k, v)| pred(k, v))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.extractor.size_hint()
+    }
+}
+
+impl<K, V> Default for SortedVecMap<K, V> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<K, V> SortedVecMap<K, V> {
+    /// Creates a new, empty map.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use multi_containers::sorted_vec_map::SortedVecMap;
+    /// use multi_containers::maps::Map;
+    /// let map = SortedVecMap::<i32, i32>::new();
+    /// assert!(map.is_empty());
+    /// ```
+    pub fn new() -> Self {
+        SortedVecMap {
+            entries: Vec::new(),
+        }
+    }
+
+    /// Creates a new, empty map with space for at least `capacity` entries.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use multi_containers::sorted_vec_map::SortedVecMap;
+    /// use multi_containers::maps::Map;
+    /// let map = SortedVecMap::<i32, i32>::with_capacity(10);
+    /// assert!(map.capacity() >= 10);
+    /// ```
+    pub fn with_capacity(capacity: usize) -> Self {
+        SortedVecMap {
+            entries: Vec::with_capacity(capacity),
+        }
+    }
+
+    // Wraps entries that are already sorted by key, without duplicate keys.
+    pub(crate) fn from_sorted_vec(entries: Vec<(K, V)>) -> Self {
+        SortedVecMap { entries }
+    }
+}
+
+impl<K, V> SortedVecMap<K, V>
+where
+    K: Ord,
+{
+    // Returns the position of a key if it is present, or else the position it would be inserted at.
+    fn position<Q>(&self, key: &Q) -> Result<usize, usize>
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        self.entries.binary_search_by(|(k, _)| k.borrow().cmp(key))
+    }
+
+    // Returns the positions of the first entry within a range of keys, and of the first entry after it.
+    fn bounds<Q, R>(&self, range: &R) -> (usize, usize)
+    where
+        K: Borrow<Q>,
+        Q: Ord + ?Sized,
+        R: RangeBounds<Q>,
+    {
+        let start = self
+            .entries
+            .partition_point(|(k, _)| is_before_start(k.borrow(), range.start_bound()));
+        let end = self
+            .entries
+            .partition_point(|(k, _)| is_before_end(k.borrow(), range.end_bound()));
+        (start, end.max(start))
+    }
+}
+
+impl<K, V> DefaultMap for SortedVecMap<K, V> where K: Ord {}
+
+impl<K, V> Map for SortedVecMap<K, V>
+where
+    K: Ord,
+{
+    type Key = K;
+    type Val = V;
+    type Iter<'a>
+        = Iter<'a, K, V>
+    where
+        Self: 'a;
+    type IterMut<'a>
+        = IterMut<'a, K, V>
+    where
+        Self: 'a;
+    type KeyIter<'a>
+        = Keys<'a, K, V>
+    where
+        Self: 'a;
+    type ValIter<'a>
+        = Values<'a, K, V>
+    where
+        Self: 'a;
+    type Drain<'a>
+        = vec::Drain<'a, (K, V)>
+    where
+        Self: 'a;
+    type ExtractIf<'a, F>
+        = ExtractIf<'a, K, V, F>
+    where
+        Self: 'a,
+        F: FnMut(&K, &mut V) -> bool + 'a;
+    type Occupied<'a>
+        = OccupiedEntry<'a, K, V>
+    where
+        Self: 'a;
+    type Vacant<'a>
+        = VacantEntry<'a, K, V>
+    where
+        Self: 'a;
+
+    fn insert(&mut self, key: K, value: V) -> Option<V> {
+        match self.position(&key) {
+            Ok(index) => Some(mem::replace(&mut self.entries[index].1, value)),
+            Err(index) => {
+                self.entries.insert(index, (key, value));
+                None
+            }
+        }
+    }
+
+    fn get_or_insert<F>(&mut self, key: K, make_value: F) -> &mut V
+    where
+        F: FnOnce() -> V,
+    {
+        let index = match self.position(&key) {
+            Ok(index) => index,
+            Err(index) => {
+                self.entries.insert(index, (key, make_value()));
+                index
+            }
+        };
+        &mut self.entries[index].1
+    }
+
+    fn entry(&mut self, key: K) -> maps::Entry<'_, Self> {
+        match self.position(&key) {
+            Ok(index) => maps::Entry::Occupied(OccupiedEntry {
+                entries: &mut self.entries,
+                index,
+            }),
+            Err(index) => maps::Entry::Vacant(VacantEntry {
+                entries: &mut self.entries,
+                index,
+                key,
+            }),
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    fn iter(&self) -> Self::Iter<'_> {
+        self.entries.iter().map(|(k, v)| (k, v))
+    }
+
+    fn iter_mut(&mut self) -> Self::IterMut<'_> {
+        self.entries.iter_mut().map(|(k, v)| (&*k, v))
+    }
+
+    fn keys(&self) -> Self::KeyIter<'_> {
+        self.entries.iter().map(|(k, _)| k)
+    }
+
+    fn values(&self) -> Self::ValIter<'_> {
+        self.entries.iter().map(|(_, v)| v)
+    }
+
+    fn retain<F>(&mut self, mut f: F)
+    where
+        F: FnMut(&K, &mut V) -> bool,
+    {
+        self.entries.retain_mut(|(k, v)| f(k, v))
+    }
+
+    fn drain(&mut self) -> Self::Drain<'_> {
+        self.entries.drain(..)
+    }
+
+    fn extract_if<'a, F>(&'a mut self, pred: F) -> Self::ExtractIf<'a, F>
+    where
+        F: FnMut(&K, &mut V) -> bool + 'a,
+    {
+        ExtractIf {
+            extractor: Extractor::new(&mut self.entries),
+            pred,
+        }
+    }
+
+    fn capacity(&self) -> usize {
+        self.entries.capacity()
+    }
+
+    fn reserve(&mut self, additional: usize) {
+        self.entries.reserve(additional)
+    }
+
+    fn shrink_to_fit(&mut self) {
+        self.entries.shrink_to_fit()
+    }
+
+    fn key_order() -> Option<KeyOrder<K>> {
+        Some(K::cmp)
+    }
+}
+
+impl<K, V, Q> Lookup<Q> for SortedVecMap<K, V>
+where
+    K: Ord + Borrow<Q>,
+    Q: Ord + ?Sized,
+{
+    fn contains_key(&self, key: &Q) -> bool {
+        self.position(key).is_ok()
+    }
+
+    fn get(&self, key: &Q) -> Option<&V> {
+        let index = self.position(key).ok()?;
+        Some(&self.entries[index].1)
+    }
+
+    fn get_mut(&mut self, key: &Q) -> Option<&mut V> {
+        let index = self.position(key).ok()?;
+        Some(&mut self.entries[index].1)
+    }
+
+    fn remove(&mut self, key: &Q) -> Option<V> {
+        let index = self.position(key).ok()?;
+        Some(self.entries.remove(index).1)
+    }
+}
+
+impl<K, V, Q> SortedMap<Q> for SortedVecMap<K, V>
+where
+    K: Ord + Borrow<Q>,
+    Q: Ord + ?Sized,
+{
+    type RangeIter<'a>
+        = Iter<'a, K, V>
+    where
+        Self: 'a;
+    type RangeIterMut<'a>
+        = IterMut<'a, K, V>
+    where
+        Self: 'a;
+
+    fn range<R>(&self, range: R) -> Self::RangeIter<'_>
+    where
+        R: RangeBounds<Q>,
+    {
+        let (start, end) = self.bounds(&range);
+        self.entries[start..end].iter().map(|(k, v)| (k, v))
+    }
+
+    fn range_mut<R>(&mut self, range: R) -> Self::RangeIterMut<'_>
+    where
+        R: RangeBounds<Q>,
+    {
+        let (start, end) = self.bounds(&range);
+        self.entries[start..end].iter_mut().map(|(k, v)| (&*k, v))
+    }
+}
+
+impl<K, C> Select for SortedVecMap<K, C>
+where
+    K: Ord,
+    C: Count,
+{
+}
+
+impl<K, C, Q> Rank<Q> for SortedVecMap<K, C>
+where
+    K: Ord + Borrow<Q>,
+    C: Count,
+    Q: Ord + ?Sized,
+{
+}
+
+impl<K, V> IntoIterator for SortedVecMap<K, V> {
+    type Item = (K, V);
+    type IntoIter = vec::IntoIter<(K, V)>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.entries.into_iter()
+    }
+}
+
+/// Sorts the entries in `O(n log n)` time. If a key is repeated, its last value is kept, like `BTreeMap`.
+impl<K, V> From<Vec<(K, V)>> for SortedVecMap<K, V>
+where
+    K: Ord,
+{
+    fn from(mut entries: Vec<(K, V)>) -> Self {
+        // Reversing before the stable sort puts the last value of each key first, where `dedup_by` keeps it.
+        entries.reverse();
+        entries.sort_by(|(a, _), (b, _)| a.cmp(b));
+        entries.dedup_by(|(a, _), (b, _)| a == b);
+        SortedVecMap { entries }
+    }
+}
+
+impl<K, V> FromIterator<(K, V)> for SortedVecMap<K, V>
+where
+    K: Ord,
+{
+    fn from_iter<I>(iter: I) -> Self
+    where
+        I: IntoIterator<Item = (K, V)>,
+    {
+        Self::from(iter.into_iter().collect::<Vec<_>>())
+    }
+}
+
+/// A view into an occupied entry of a `SortedVecMap`.
+pub struct OccupiedEntry<'a, K, V> {
+    entries: &'a mut Vec<(K, V)>,
+    index: usize,
+}
+
+impl<'a, K, V> maps::OccupiedEntry<'a> for OccupiedEntry<'a, K, V> {
+    type Key = K;
+    type Val = V;
+
+    fn key(&self) -> &K {
+        &self.entries[self.index].0
+    }
+
+    fn get(&self) -> &V {
+        &self.entries[self.index].1
+    }
+
+    fn get_mut(&mut self) -> &mut V {
+        &mut self.entries[self.index].1
+    }
+
+    fn into_mut(self) -> &'a mut V {
+        &mut self.entries[self.index].1
+    }
+
+    fn remove_entry(self) -> (K, V) {
+        self.entries.remove(self.index)
+    }
+}
+
+/// A view into a vacant entry of a `SortedVecMap`.
+pub struct VacantEntry<'a, K, V> {
+    entries: &'a mut Vec<(K, V)>,
+    // The position the key will be inserted at.
+    index: usize,
+    key: K,
+}
+
+impl<'a, K, V> maps::VacantEntry<'a> for VacantEntry<'a, K, V> {
+    type Key = K;
+    type Val = V;
+    type Occupied = OccupiedEntry<'a, K, V>;
+
+    fn key(&self) -> &K {
+        &self.key
+    }
+
+    fn into_key(self) -> K {
+        self.key
+    }
+
+    fn insert(self, value: V) -> &'a mut V {
+        self.entries.insert(self.index, (self.key, value));
+        &mut self.entries[self.index].1
+    }
+
+    fn insert_entry(self, value: V) -> Self::Occupied {
+        self.entries.insert(self.index, (self.key, value));
+        OccupiedEntry {
+            entries: self.entries,
+            index: self.index,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::SortedVecMap;
+    use crate::maps::{self, Entry, Lookup, Map, SortedMap};
+    use crate::test_utils::Lcg;
+    use std::collections::BTreeMap;
+
+    #[test]
+    fn matches_btree_map() {
+        let mut rng = Lcg(3);
+        let mut map = SortedVecMap::new();
+        let mut expected = BTreeMap::new();
+        for _ in 0..2000 {
+            let key = rng.next(30);
+            let value = rng.next(100);
+            match rng.next(6) {
+                0 => assert_eq!(
+                    Map::insert(&mut map, key, value),
+                    expected.insert(key, value)
+                ),
+                1 => assert_eq!(Lookup::remove(&mut map, &key), expected.remove(&key)),
+                2 => match Map::entry(&mut map, key) {
+                    Entry::Occupied(mut entry) => {
+                        *maps::OccupiedEntry::get_mut(&mut entry) += value;
+                        *expected.get_mut(&key).unwrap() += value;
+                    }
+                    Entry::Vacant(entry) => {
+                        maps::VacantEntry::insert(entry, value);
+                        expected.insert(key, value);
+                    }
+                },
+                3 => {
+                    *Map::get_or_insert(&mut map, key, || 0) += value;
+                    *expected.entry(key).or_insert(0) += value;
+                }
+                4 => {
+                    for (_, v) in map.range_mut(key..key + 3) {
+                        *v += 1;
+                    }
+                    for (_, v) in expected.range_mut(key..key + 3) {
+                        *v += 1;
+                    }
+                }
+                _ => {
+                    let extracted = map.extract_if(|k, _| k % 7 == key % 7).collect::<Vec<_>>();
+                    let expected_extracted = expected
+                        .extract_if(.., |k, _| k % 7 == key % 7)
+                        .collect::<Vec<_>>();
+                    assert_eq!(extracted, expected_extracted);
+                }
+            }
+            assert!(map.iter().eq(expected.iter()));
+            assert_eq!(Lookup::get(&map, &key), expected.get(&key));
+            assert!(map.range(..=key).eq(expected.range(..=key)));
+            assert!(map
+                .range((key + 1)..key)
+                .eq(expected.range((key + 1)..(key + 1))));
+        }
+    }
+
+    #[test]
+    fn bulk_construction_sorts_and_keeps_last_value() {
+        let map = SortedVecMap::from(vec![(3, 'a'), (1, 'b'), (3, 'c'), (2, 'd'), (1, 'e')]);
+        assert_eq!(
+            map.into_iter().collect::<Vec<_>>(),
+            vec![(1, 'e'), (2, 'd'), (3, 'c')]
+        );
+        let expected = BTreeMap::from([(3, 'a'), (1, 'b'), (3, 'c')]);
+        let map = [(3, 'a'), (1, 'b'), (3, 'c')]
+            .into_iter()
+            .collect::<SortedVecMap<_, _>>();
+        assert!(map.iter().eq(expected.iter()));
+    }
+}
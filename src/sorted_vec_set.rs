@@ -0,0 +1,468 @@
+use crate::sets::{Container, Set};
+use std::borrow::Borrow;
+use std::collections::VecDeque;
+use std::{mem, slice, vec};
+
+/// A sorted set backed by a `Vec`.
+/// Lookups are binary searches over contiguous memory, which makes this set faster and smaller than `BTreeSet`
+/// for data that is built once and queried many times. Inserting or removing a single element takes `O(n)` time,
+/// so build the set in bulk with `FromIterator` or `From<Vec<T>>`, which sort their input in `O(n log n)`.
+///
+/// # Example
+///
+/// ```
+/// use multi_containers::sorted_vec_set::SortedVecSet;
+/// let set = SortedVecSet::from(vec![3, 1, 2, 1]);
+/// assert!(set.contains(&2));
+/// assert!(set.iter().eq(&[1, 2, 3]));
+/// ```
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct SortedVecSet<T> {
+    items: Vec<T>,
+}
+
+/// An iterator that removes and yields the elements of a `SortedVecSet` that match a predicate.
+/// If it is dropped early, the elements that were not visited yet are kept.
+pub struct ExtractIf<'a, T, F> {
+    extractor: Extractor<'a, T>,
+    pred: F,
+}
+
+impl<T, F> Iterator for ExtractIf<'_, T, F>
+where
+    F: FnMut(&T) -> bool,
+{
+    type Item = T;
+
+    fn next(&mut self) -> Option<T> {
+        let pred = &mut self.pred;
+        self.extractor.next_by(|item| pred(item))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.extractor.size_hint()
+    }
+}
+
+// Removes the matching elements of a sorted `Vec` in one pass, keeping the order of the others.
+// The elements are moved into a `VecDeque` that reuses the allocation of the `Vec`. The unvisited ones are taken from
+// the front and the kept ones are pushed to the back, so they take the place of the removed ones without any memory
+// being allocated. On drop, the unvisited ones are rotated behind the kept ones and the `Vec` gets its buffer back.
+pub(crate) struct Extractor<'a, T> {
+    items: &'a mut Vec<T>,
+    deque: VecDeque<T>,
+    unvisited: usize,
+}
+
+impl<'a, T> Extractor<'a, T> {
+    pub(crate) fn new(items: &'a mut Vec<T>) -> Self {
+        let deque = VecDeque::from(mem::take(items));
+        let unvisited = deque.len();
+        Extractor {
+            items,
+            deque,
+            unvisited,
+        }
+    }
+
+    // Returns the next element for which the predicate returns `true`, keeping the elements before it.
+    pub(crate) fn next_by<P>(&mut self, mut pred: P) -> Option<T>
+    where
+        P: FnMut(&mut T) -> bool,
+    {
+        while self.unvisited > 0 {
+            self.unvisited -= 1;
+            let mut item = self
+                .deque
+                .pop_front()
+                .expect("unvisited elements are at the front");
+            if pred(&mut item) {
+                return Some(item);
+            }
+            self.deque.push_back(item);
+        }
+        None
+    }
+
+    pub(crate) fn size_hint(&self) -> (usize, Option<usize>) {
+        (0, Some(self.unvisited))
+    }
+}
+
+impl<T> Drop for Extractor<'_, T> {
+    fn drop(&mut self) {
+        self.deque.rotate_left(self.unvisited);
+        *self.items = Vec::from(mem::take(&mut self.deque));
+    }
+}
+
+impl<T> Default for SortedVecSet<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> SortedVecSet<T> {
+    /// Creates a new, empty set.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use multi_containers::sorted_vec_set::SortedVecSet;
+    /// let set = SortedVecSet::<i32>::new();
+    /// assert!(set.is_empty());
+    /// ```
+    pub fn new() -> Self {
+        SortedVecSet { items: Vec::new() }
+    }
+
+    /// Returns the number of elements in the set.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use multi_containers::sorted_vec_set::SortedVecSet;
+    /// let set = SortedVecSet::from(vec![1, 2, 2]);
+    /// assert_eq!(set.len(), 2);
+    /// ```
+    pub fn len(&self) -> usize {
+        self.items.len()
+    }
+
+    /// Returns `true` if the set is empty.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use multi_containers::sorted_vec_set::SortedVecSet;
+    /// let set = SortedVecSet::from(vec![1]);
+    /// assert!(!set.is_empty());
+    /// ```
+    pub fn is_empty(&self) -> bool {
+        self.items.is_empty()
+    }
+
+    /// Returns an iterator over the elements of the set, in sorted order.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use multi_containers::sorted_vec_set::SortedVecSet;
+    /// let set = SortedVecSet::from(vec![2, 1]);
+    /// assert_eq!(set.iter().collect::<Vec<_>>(), vec![&1, &2]);
+    /// ```
+    pub fn iter(&self) -> slice::Iter<'_, T> {
+        self.items.iter()
+    }
+
+    /// Returns the elements of the set as a sorted slice.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use multi_containers::sorted_vec_set::SortedVecSet;
+    /// let set = SortedVecSet::from(vec![2, 1]);
+    /// assert_eq!(set.as_slice(), &[1, 2]);
+    /// ```
+    pub fn as_slice(&self) -> &[T] {
+        &self.items
+    }
+
+    /// Removes all elements from the set.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use multi_containers::sorted_vec_set::SortedVecSet;
+    /// let mut set = SortedVecSet::from(vec![1, 2]);
+    /// set.clear();
+    /// assert!(set.is_empty());
+    /// ```
+    pub fn clear(&mut self) {
+        self.items.clear()
+    }
+
+    /// Keeps only the elements for which the predicate returns `true`.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use multi_containers::sorted_vec_set::SortedVecSet;
+    /// let mut set = SortedVecSet::from(vec![1, 2, 3]);
+    /// set.retain(|v| v % 2 == 1);
+    /// assert!(set.iter().eq(&[1, 3]));
+    /// ```
+    pub fn retain<F>(&mut self, f: F)
+    where
+        F: FnMut(&T) -> bool,
+    {
+        self.items.retain(f)
+    }
+
+    // Wraps elements that are already sorted, without duplicates.
+    pub(crate) fn from_sorted_vec(items: Vec<T>) -> Self {
+        SortedVecSet { items }
+    }
+}
+
+impl<T> SortedVecSet<T>
+where
+    T: Ord,
+{
+    // Returns the position of a value if it is present, or else the position it would be inserted at.
+    fn position<Q>(&self, value: &Q) -> Result<usize, usize>
+    where
+        T: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        self.items.binary_search_by(|item| item.borrow().cmp(value))
+    }
+
+    /// Inserts a value into the set. Returns `true` if the value was not already present.
+    /// This takes `O(n)` time, as the following elements are shifted to make room.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use multi_containers::sorted_vec_set::SortedVecSet;
+    /// let mut set = SortedVecSet::new();
+    /// assert!(set.insert(2));
+    /// assert!(set.insert(1));
+    /// assert!(!set.insert(2));
+    /// assert!(set.iter().eq(&[1, 2]));
+    /// ```
+    pub fn insert(&mut self, value: T) -> bool {
+        match self.position(&value) {
+            Ok(_) => false,
+            Err(index) => {
+                self.items.insert(index, value);
+                true
+            }
+        }
+    }
+
+    /// Removes a value from the set. Returns `true` if the value was present.
+    /// This takes `O(n)` time, as the following elements are shifted to fill the gap.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use multi_containers::sorted_vec_set::SortedVecSet;
+    /// let mut set = SortedVecSet::from(vec![1, 2]);
+    /// assert!(set.remove(&1));
+    /// assert!(!set.remove(&1));
+    /// ```
+    pub fn remove<Q>(&mut self, value: &Q) -> bool
+    where
+        T: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        match self.position(value) {
+            Ok(index) => {
+                self.items.remove(index);
+                true
+            }
+            Err(_) => false,
+        }
+    }
+
+    /// Returns `true` if the set contains the given value.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use multi_containers::sorted_vec_set::SortedVecSet;
+    /// let set = SortedVecSet::from(vec!["a".to_string()]);
+    /// assert!(set.contains("a"));
+    /// assert!(!set.contains("b"));
+    /// ```
+    pub fn contains<Q>(&self, value: &Q) -> bool
+    where
+        T: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        self.position(value).is_ok()
+    }
+}
+
+impl<T> Set for SortedVecSet<T>
+where
+    T: Ord,
+{
+    type Elem = T;
+    type Iter<'a>
+        = slice::Iter<'a, T>
+    where
+        Self: 'a;
+    type Drain<'a>
+        = vec::Drain<'a, T>
+    where
+        Self: 'a;
+    type ExtractIf<'a, F>
+        = ExtractIf<'a, T, F>
+    where
+        Self: 'a,
+        F: FnMut(&T) -> bool + 'a;
+
+    fn insert(&mut self, value: T) -> bool {
+        self.insert(value)
+    }
+
+    fn is_empty(&self) -> bool {
+        self.is_empty()
+    }
+
+    fn len(&self) -> usize {
+        self.len()
+    }
+
+    fn iter(&self) -> Self::Iter<'_> {
+        self.iter()
+    }
+
+    fn retain<F>(&mut self, f: F)
+    where
+        F: FnMut(&T) -> bool,
+    {
+        self.retain(f)
+    }
+
+    fn drain(&mut self) -> Self::Drain<'_> {
+        self.items.drain(..)
+    }
+
+    fn extract_if<'a, F>(&'a mut self, pred: F) -> Self::ExtractIf<'a, F>
+    where
+        F: FnMut(&T) -> bool + 'a,
+    {
+        ExtractIf {
+            extractor: Extractor::new(&mut self.items),
+            pred,
+        }
+    }
+
+    fn capacity(&self) -> usize {
+        self.items.capacity()
+    }
+
+    fn reserve(&mut self, additional: usize) {
+        self.items.reserve(additional)
+    }
+
+    fn shrink_to_fit(&mut self) {
+        self.items.shrink_to_fit()
+    }
+}
+
+impl<T, Q> Container<Q> for SortedVecSet<T>
+where
+    T: Ord + Borrow<Q>,
+    Q: Ord + ?Sized,
+{
+    fn remove(&mut self, value: &Q) -> bool {
+        self.remove(value)
+    }
+
+    fn contains(&self, value: &Q) -> bool {
+        self.contains(value)
+    }
+}
+
+impl<T> IntoIterator for SortedVecSet<T> {
+    type Item = T;
+    type IntoIter = vec::IntoIter<T>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.items.into_iter()
+    }
+}
+
+impl<'a, T> IntoIterator for &'a SortedVecSet<T> {
+    type Item = &'a T;
+    type IntoIter = slice::Iter<'a, T>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.items.iter()
+    }
+}
+
+/// Sorts the elements in `O(n log n)` time. If a value is repeated, its first occurrence is kept, like `BTreeSet`.
+impl<T> From<Vec<T>> for SortedVecSet<T>
+where
+    T: Ord,
+{
+    fn from(mut items: Vec<T>) -> Self {
+        items.sort();
+        items.dedup();
+        SortedVecSet { items }
+    }
+}
+
+impl<T> FromIterator<T> for SortedVecSet<T>
+where
+    T: Ord,
+{
+    fn from_iter<I>(iter: I) -> Self
+    where
+        I: IntoIterator<Item = T>,
+    {
+        Self::from(iter.into_iter().collect::<Vec<_>>())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::SortedVecSet;
+    use crate::sets::Set;
+    use crate::test_utils::Lcg;
+    use std::collections::BTreeSet;
+
+    #[test]
+    fn matches_btree_set() {
+        let mut rng = Lcg(5);
+        let mut set = SortedVecSet::new();
+        let mut expected = BTreeSet::new();
+        for _ in 0..500 {
+            let value = rng.next(40);
+            if rng.next(3) == 0 {
+                assert_eq!(set.remove(&value), expected.remove(&value));
+            } else {
+                assert_eq!(set.insert(value), expected.insert(value));
+            }
+            assert!(set.iter().eq(expected.iter()));
+            assert_eq!(set.contains(&(value + 1)), expected.contains(&(value + 1)));
+        }
+        let extracted = set.extract_if(|v| v % 2 == 0).collect::<Vec<_>>();
+        let expected_extracted = expected.extract_if(.., |v| v % 2 == 0).collect::<Vec<_>>();
+        assert_eq!(extracted, expected_extracted);
+        assert!(set.iter().eq(expected.iter()));
+        assert!(set.drain().eq(expected.into_iter()));
+        assert!(set.is_empty());
+    }
+
+    #[test]
+    fn extract_if_dropped_early_keeps_the_rest() {
+        let mut set = SortedVecSet::from(vec![1, 2, 3, 4, 5, 6]);
+        assert_eq!(set.extract_if(|v| v % 2 == 0).next(), Some(2));
+        assert_eq!(set.as_slice(), &[1, 3, 4, 5, 6]);
+    }
+
+    #[test]
+    fn extract_if_reuses_the_buffer() {
+        let mut set = SortedVecSet::from(vec![1, 2, 3, 4, 5, 6]);
+        let ptr = set.as_slice().as_ptr();
+        let mut evens = set.extract_if(|v| v % 2 == 0);
+        assert_eq!(evens.next(), Some(2));
+        assert_eq!(evens.next(), Some(4));
+        drop(evens);
+        assert_eq!(set.as_slice(), &[1, 3, 5, 6]);
+        assert_eq!(set.as_slice().as_ptr(), ptr);
+    }
+
+    #[test]
+    fn bulk_construction_sorts_and_dedups() {
+        let set: SortedVecSet<_> = [5, 3, 5, 1, 3].into_iter().collect();
+        assert_eq!(set.as_slice(), &[1, 3, 5]);
+        assert_eq!(set.into_iter().collect::<Vec<_>>(), vec![1, 3, 5]);
+    }
+}
@@ -15,6 +15,21 @@ where
     v.is_empty()
 }
 
+// A small deterministic pseudo-random number generator, so that the tests are reproducible.
+#[cfg(test)]
+pub struct Lcg(pub u64);
+
+#[cfg(test)]
+impl Lcg {
+    pub fn next(&mut self, bound: u64) -> u64 {
+        self.0 = self
+            .0
+            .wrapping_mul(6364136223846793005)
+            .wrapping_add(1442695040888963407);
+        (self.0 >> 33) % bound
+    }
+}
+
 #[cfg(test)]
 pub fn is_sorted<I>(i: I) -> bool
 where
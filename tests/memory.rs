@@ -0,0 +1,71 @@
+//! Compares the memory used by multi-maps with `SmallSet` and `HashSet` value sets.
+//! This lives in its own test binary, as it counts every allocation made through the global allocator.
+
+use multi_containers::small_set::SmallSet;
+use multi_containers::{HashMultiMap, MultiMap};
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+// Tracks the number of bytes currently allocated.
+struct CountingAllocator;
+
+static ALLOCATED: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATED.fetch_add(layout.size(), Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        ALLOCATED.fetch_sub(layout.size(), Ordering::Relaxed);
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: CountingAllocator = CountingAllocator;
+
+type SmallMultiMap = MultiMap<HashMap<u32, SmallSet<u32, 2>>>;
+
+// Returns the number of bytes allocated while building a multi-map with the given number of values per key,
+// which is kept alive until it has been measured.
+fn measure<M, F>(values_per_key: impl Fn(u32) -> u32, make_map: F) -> usize
+where
+    F: FnOnce() -> M,
+    M: Extend<(u32, u32)>,
+{
+    let before = ALLOCATED.load(Ordering::Relaxed);
+    let mut map = make_map();
+    map.extend((0..10_000).flat_map(|key| (0..values_per_key(key)).map(move |value| (key, value))));
+    let used = ALLOCATED.load(Ordering::Relaxed) - before;
+    drop(map);
+    used
+}
+
+#[test]
+fn small_sets_use_less_memory_than_hash_sets() {
+    // Mostly one or two values per key, with every twentieth key holding more.
+    let values_per_key = |key: u32| {
+        if key.is_multiple_of(20) {
+            10
+        } else {
+            key % 2 + 1
+        }
+    };
+    let hash = measure(values_per_key, HashMultiMap::<u32, u32>::new);
+    let small = measure(values_per_key, SmallMultiMap::default);
+    assert!(
+        small * 2 < hash,
+        "{small} bytes is not less than half of {hash} bytes"
+    );
+
+    // With only large value sets, every small set spills, and costs about as much as a hash set.
+    let hash = measure(|_| 10, HashMultiMap::<u32, u32>::new);
+    let small = measure(|_| 10, SmallMultiMap::default);
+    assert!(
+        small < hash * 2,
+        "{small} bytes is not comparable to {hash} bytes"
+    );
+}